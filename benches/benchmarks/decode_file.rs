@@ -1,6 +1,10 @@
 use criterion::{criterion_group, Criterion};
 use png;
+#[cfg(feature = "zlib-rs")]
+use png_decoder::inflater::ZlibRsInflater;
 use png_decoder::png as my_png;
+#[cfg(feature = "zlib-rs")]
+use std::sync::Arc;
 
 fn bench(c: &mut Criterion) {
     let png_raw_data = std::fs::read("data/depth.png").unwrap();
@@ -11,6 +15,9 @@ fn bench(c: &mut Criterion) {
     // let png_raw_data = std::fs::read("data/texture_alpha.png").unwrap();
     // let png_raw_data = std::fs::read("data/transparent.png").unwrap();
     let png_raw_data_bis = png_raw_data.clone();
+    let png_raw_data_options = png_raw_data.clone();
+    #[cfg(feature = "zlib-rs")]
+    let png_raw_data_zlib_rs = png_raw_data.clone();
     let png_raw_data_clone = png_raw_data.clone();
 
     c.bench_function("decode_file, slice", move |b| {
@@ -21,6 +28,20 @@ fn bench(c: &mut Criterion) {
         b.iter(|| my_png::decode_no_check_bis(&png_raw_data_bis))
     });
 
+    c.bench_function("decode_file, miniz inflater", move |b| {
+        let options = my_png::DecodeOptions::default();
+        b.iter(|| my_png::decode_with_options(&png_raw_data_options, &options))
+    });
+
+    #[cfg(feature = "zlib-rs")]
+    c.bench_function("decode_file, zlib-rs inflater", move |b| {
+        let options = my_png::DecodeOptions {
+            inflater: Some(Arc::new(ZlibRsInflater)),
+            ..Default::default()
+        };
+        b.iter(|| my_png::decode_with_options(&png_raw_data_zlib_rs, &options))
+    });
+
     c.bench_function("decode_file, png crate", move |b| {
         b.iter(|| {
             let mut decoder = png::Decoder::new(png_raw_data_clone.as_slice());
@@ -78,13 +78,13 @@ fn unfilter_paeth(c: &mut Criterion) {
 
 fn unfilter_slice(data: &[u8]) {
     let data = data.to_vec();
-    let scanlines = png::lines_slices(&data, bb(SCANLINE_WIDTH));
+    let scanlines = png::lines_slices(&data, bb(SCANLINE_WIDTH)).unwrap();
     filter::unfilter(bb(WIDTH), bb(HEIGHT), bb(BPP), scanlines);
 }
 
 fn unfilter_mut(data: &[u8]) {
     let mut data = data.to_vec();
-    let scanlines = png::lines_num(&data, bb(SCANLINE_WIDTH));
+    let scanlines = png::lines_num(&data, bb(SCANLINE_WIDTH)).unwrap();
     filter::unfilter_bis(bb(WIDTH), bb(HEIGHT), bb(BPP), scanlines, &mut data);
 }
 
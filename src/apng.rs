@@ -0,0 +1,503 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::time::Duration;
+
+// Internal imports
+use crate::chunk::{self, ChunkType};
+use crate::chunk_data::{self, AnimationControl, BlendOp, ChunkData, DisposeOp};
+use crate::chunk_data::{FrameControl, FrameData, IHDRData, Transparency};
+use crate::color::ColorType;
+use crate::encoder;
+use crate::filter::FilterStrategy;
+use crate::inflater::{Inflater, MinizInflater};
+use crate::limits::Limits;
+use crate::optimizer;
+use crate::png;
+
+// TYPES #######################################################################
+
+/// Frames of an animated PNG, decoded one at a time.
+/// A PNG without acTL chunk is a single frame animation.
+#[derive(Debug)]
+pub struct Animation<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Number of times to play the animation, 0 meaning forever.
+    pub num_plays: u32,
+    ihdr: IHDRData,
+    palette: Vec<&'a [u8]>,
+    transparency: Option<Transparency>,
+    limits: Limits,
+    frames: Vec<(FrameControl, Vec<&'a [u8]>)>,
+    next_frame: usize,
+    canvas: Vec<u8>,
+    // Frame region disposal to apply before rendering the next frame.
+    dispose: Option<(FrameControl, Vec<u8>)>,
+}
+
+/// A fully composited frame, with 8 bits RGBA samples.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    pub delay: Duration,
+}
+
+/// Options of `encode_animation`.
+#[derive(Debug, Clone)]
+pub struct AnimationOptions {
+    /// Number of times to play the animation, 0 meaning forever.
+    pub num_plays: u32,
+    /// zlib compression level, from 0 (none) to 10 (best).
+    pub compression_level: u8,
+    pub filter_strategy: FilterStrategy,
+    /// Only encode the region changed since the previous frame,
+    /// choosing the dispose and blend operations minimizing it.
+    pub optimize_regions: bool,
+}
+
+// Rectangle of a frame, as (x, y, width, height).
+type Region = (usize, usize, usize, usize);
+
+// FUNCTIONS ###################################################################
+
+/// Parse an animated PNG, its frames being decoded by iterating on it.
+pub fn decode_animation(input: &[u8]) -> Result<Animation<'_>, Box<dyn Error>> {
+    decode_animation_with_limits(input, &Limits::default())
+}
+
+pub fn decode_animation_with_limits<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+) -> Result<Animation<'a>, Box<dyn Error>> {
+    let chunks = png::parse_chunks_with_limits(input, limits)?;
+    png::reject_cgbi(&chunks)?;
+    let chunks = chunk::validate_chunk_constraints(&chunks)?;
+    let ihdr = chunk_data::parse_valid_ihdr_data(chunks[0].data)?;
+    limits.check_ihdr(&ihdr)?;
+    if ihdr.interlace_method != 0 {
+        return Err("Interlaced images are not supported".into());
+    }
+    let (palette, transparency) = chunk_data::palette_and_transparency(chunks, ihdr.color_type);
+    let whole_image = FrameControl {
+        sequence_number: 0,
+        width: ihdr.width,
+        height: ihdr.height,
+        x_offset: 0,
+        y_offset: 0,
+        delay_num: 0,
+        delay_den: 0,
+        dispose_op: DisposeOp::None,
+        blend_op: BlendOp::Source,
+    };
+
+    // Group the data chunks of each frame after its fcTL chunk.
+    // The default image is the first frame only if it has an fcTL chunk.
+    let mut animation_control = None;
+    let mut frames: Vec<(FrameControl, Vec<&[u8]>)> = Vec::new();
+    let mut idats = Vec::new();
+    let mut default_image_is_frame = false;
+    let mut sequence_number = 0;
+    for chunk in chunks.iter() {
+        let mut check_sequence = |n: u32| {
+            if n == sequence_number {
+                sequence_number += 1;
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} sequence number is {} instead of {}",
+                    chunk.chunk_type, n, sequence_number
+                ))
+            }
+        };
+        match chunk.chunk_type {
+            ChunkType::IDAT => {
+                if frames.len() > 1 {
+                    return Err("Only one fcTL chunk can precede the IDAT chunks".into());
+                }
+                default_image_is_frame = frames.len() == 1;
+                idats.push(chunk.data);
+            }
+            ChunkType::acTL | ChunkType::fcTL | ChunkType::fdAT => {
+                match parse(chunk_data::parse_chunk_data(chunk))? {
+                    ChunkData::acTL(actl) => animation_control = Some(actl),
+                    ChunkData::fcTL(fctl) => {
+                        check_sequence(fctl.sequence_number)?;
+                        check_region(&fctl, &ihdr, frames.is_empty())?;
+                        frames.push((fctl, Vec::new()));
+                    }
+                    // The frame data borrows the chunk, it is taken from the input instead.
+                    ChunkData::fdAT(fdat) => {
+                        check_sequence(fdat.sequence_number)?;
+                        let after_idat = !default_image_is_frame || frames.len() > 1;
+                        match frames.last_mut() {
+                            Some((_, data)) if after_idat && !idats.is_empty() => {
+                                data.push(&chunk.data[4..])
+                            }
+                            _ => return Err("fdAT chunk without an fcTL chunk after IDAT".into()),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => {}
+        }
+    }
+    let (num_plays, frames) = match animation_control {
+        Some(actl) if frames.len() != actl.num_frames as usize => {
+            return Err(format!(
+                "acTL announces {} frames but there are {} fcTL chunks",
+                actl.num_frames,
+                frames.len()
+            )
+            .into());
+        }
+        Some(actl) => {
+            if default_image_is_frame {
+                frames[0].1 = idats;
+            }
+            (actl.num_plays, frames)
+        }
+        None => (0, vec![(whole_image, idats)]),
+    };
+    Ok(Animation {
+        width: ihdr.width as usize,
+        height: ihdr.height as usize,
+        num_plays,
+        ihdr,
+        palette,
+        transparency,
+        limits: *limits,
+        frames,
+        next_frame: 0,
+        canvas: vec![0; ihdr.width as usize * ihdr.height as usize * 4],
+        dispose: None,
+    })
+}
+
+impl Animation<'_> {
+    /// Number of frames of the animation.
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Decode the next frame and composite it on the canvas.
+    fn render_next(&mut self, index: usize) -> Result<Frame, Box<dyn Error>> {
+        if let Some((previous, saved)) = self.dispose.take() {
+            self.dispose_region(&previous, saved);
+        }
+        let (fctl, data) = &self.frames[index];
+        let fctl = *fctl;
+        let saved = match fctl.dispose_op {
+            DisposeOp::None => Vec::new(),
+            // Reverting the first frame clears it to the background.
+            DisposeOp::Previous if index > 0 => self.canvas.clone(),
+            _ => vec![0; self.canvas.len()],
+        };
+        let frame_ihdr = IHDRData {
+            width: fctl.width,
+            height: fctl.height,
+            ..self.ihdr
+        };
+        let len = frame_ihdr.image_data_len()?;
+        let inflated = MinizInflater.inflate(data, len, self.limits.max_output_bytes, true)?;
+        if inflated.truncated {
+            return Err(format!("The zlib stream of frame {} is truncated", index).into());
+        }
+        let pixels = optimizer::rgba_pixels(
+            &frame_ihdr,
+            &inflated.data,
+            &self.palette,
+            self.transparency.as_ref(),
+        )?;
+        let frame_width = fctl.width as usize;
+        for (i, pixel) in pixels.iter().enumerate() {
+            let x = fctl.x_offset as usize + i % frame_width;
+            let y = fctl.y_offset as usize + i / frame_width;
+            let start = (y * self.width + x) * 4;
+            let source = pixel.map(|s| (s >> 8) as u8);
+            let target = &mut self.canvas[start..start + 4];
+            match fctl.blend_op {
+                BlendOp::Source => target.copy_from_slice(&source),
+                BlendOp::Over => blend_over(&source, target),
+            }
+        }
+        if fctl.dispose_op != DisposeOp::None {
+            self.dispose = Some((fctl, saved));
+        }
+        Ok(Frame {
+            width: self.width,
+            height: self.height,
+            data: self.canvas.clone(),
+            delay: fctl.delay(),
+        })
+    }
+
+    // Restore the region of a frame from the saved canvas.
+    fn dispose_region(&mut self, fctl: &FrameControl, saved: Vec<u8>) {
+        let x_start = fctl.x_offset as usize * 4;
+        let x_end = x_start + fctl.width as usize * 4;
+        for y in fctl.y_offset as usize..(fctl.y_offset + fctl.height) as usize {
+            let row = y * self.width * 4;
+            self.canvas[row + x_start..row + x_end]
+                .copy_from_slice(&saved[row + x_start..row + x_end]);
+        }
+    }
+}
+
+impl Iterator for Animation<'_> {
+    type Item = Result<Frame, Box<dyn Error>>;
+
+    /// Iteration stops after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next_frame;
+        if index >= self.frames.len() {
+            return None;
+        }
+        let frame = self.render_next(index);
+        self.next_frame = match frame {
+            Ok(_) => index + 1,
+            Err(_) => self.frames.len(),
+        };
+        Some(frame)
+    }
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions {
+            num_plays: 0,
+            compression_level: 6,
+            filter_strategy: FilterStrategy::MinSumAbsDiff,
+            optimize_regions: true,
+        }
+    }
+}
+
+/// Encode frames of the same size into an animated PNG.
+/// The first frame is also the default image, displayed by decoders
+/// not supporting animations.
+pub fn encode_animation(
+    frames: &[Frame],
+    options: &AnimationOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (width, height) = match frames.first() {
+        Some(frame) => (frame.width, frame.height),
+        None => return Err("An animation needs at least one frame".into()),
+    };
+    for (index, frame) in frames.iter().enumerate() {
+        let size_matches = (frame.width, frame.height) == (width, height);
+        if !size_matches || frame.data.len() != width * height * 4 {
+            return Err(format!(
+                "Frame {} does not match the {}x{} canvas",
+                index, width, height
+            )
+            .into());
+        }
+    }
+    let ihdr = IHDRData {
+        width: u32::try_from(width)?,
+        height: u32::try_from(height)?,
+        bit_depth: 8,
+        color_type: ColorType::RGBA,
+        compression_method: 0,
+        filter_method: 0,
+        interlace_method: 0,
+    };
+    ihdr.validate()?;
+
+    // Choose the region and operations of each frame.
+    // The canvas before the first frame is transparent black.
+    let full = (0, 0, width, height);
+    let cleared = vec![0; width * height * 4];
+    let mut controls = Vec::with_capacity(frames.len());
+    let mut base = cleared.clone();
+    for (index, frame) in frames.iter().enumerate() {
+        let region = match index {
+            0 => full,
+            _ if options.optimize_regions => changed_region(&base, &frame.data, width),
+            _ => full,
+        };
+        let mut dispose_op = DisposeOp::None;
+        let mut next_base = frame.data.clone();
+        if let Some(next) = frames.get(index + 1).filter(|_| options.optimize_regions) {
+            let mut background = frame.data.clone();
+            copy_region(&cleared, &mut background, region, width);
+            let mut candidates = vec![(DisposeOp::Background, background)];
+            // Reverting the first frame would clear it to the background.
+            if index > 0 {
+                candidates.push((DisposeOp::Previous, base.clone()));
+            }
+            let area = |canvas: &[u8]| {
+                let (_, _, w, h) = changed_region(canvas, &next.data, width);
+                w * h
+            };
+            let mut smallest = area(&next_base);
+            for (op, canvas) in candidates.into_iter() {
+                let canvas_area = area(&canvas);
+                if canvas_area < smallest {
+                    smallest = canvas_area;
+                    dispose_op = op;
+                    next_base = canvas;
+                }
+            }
+        }
+        controls.push((region, dispose_op, std::mem::replace(&mut base, next_base)));
+    }
+
+    let mut output = png::SIGNATURE.to_vec();
+    let write = |output: &mut Vec<u8>, data: ChunkData| {
+        if let Some(chunk_type) = data.chunk_type() {
+            encoder::write_chunk(output, chunk_type.name(), &data.to_bytes());
+        }
+    };
+    write(&mut output, ChunkData::IHDR(ihdr));
+    let actl = AnimationControl {
+        num_frames: u32::try_from(frames.len())?,
+        num_plays: options.num_plays,
+    };
+    write(&mut output, ChunkData::acTL(actl));
+    let mut sequence_number = 0;
+    for (frame, (region, dispose_op, base)) in frames.iter().zip(controls) {
+        let (x, y, w, h) = region;
+        let mut data = Vec::with_capacity(w * h * 4);
+        for row in y..y + h {
+            let start = (row * width + x) * 4;
+            data.extend_from_slice(&frame.data[start..start + w * 4]);
+        }
+        // Unchanged pixels can be transparent if the changed ones are opaque.
+        let mut blend_op = BlendOp::Source;
+        if options.optimize_regions && sequence_number > 0 {
+            let unchanged = |i: usize| {
+                let start = ((y + i / w) * width + x + i % w) * 4;
+                frame.data[start..start + 4] == base[start..start + 4]
+            };
+            let pixels = data.chunks(4).enumerate();
+            if pixels.clone().all(|(i, p)| p[3] == 255 || unchanged(i)) {
+                blend_op = BlendOp::Over;
+                data.chunks_mut(4)
+                    .enumerate()
+                    .filter(|(i, _)| unchanged(*i))
+                    .for_each(|(_, p)| p.copy_from_slice(&[0; 4]));
+            }
+        }
+        let (delay_num, delay_den) = delay_fraction(frame.delay);
+        let fctl = FrameControl {
+            sequence_number,
+            width: w as u32,
+            height: h as u32,
+            x_offset: x as u32,
+            y_offset: y as u32,
+            delay_num,
+            delay_den,
+            dispose_op,
+            blend_op,
+        };
+        write(&mut output, ChunkData::fcTL(fctl));
+        sequence_number += 1;
+        let frame_ihdr = IHDRData {
+            width: w as u32,
+            height: h as u32,
+            ..ihdr
+        };
+        let compressed = encoder::compress_rows(
+            &frame_ihdr,
+            &data,
+            options.compression_level,
+            options.filter_strategy,
+        )?;
+        if fctl.sequence_number == 0 {
+            encoder::write_chunk(&mut output, *b"IDAT", &compressed);
+        } else {
+            let fdat = FrameData {
+                sequence_number,
+                data: &compressed,
+            };
+            write(&mut output, ChunkData::fdAT(fdat));
+            sequence_number += 1;
+        }
+    }
+    write(&mut output, ChunkData::IEND);
+    Ok(output)
+}
+
+// Helpers #####################################################################
+
+fn parse<T>(result: nom::IResult<&[u8], T>) -> Result<T, String> {
+    result.map(|(_, t)| t).map_err(|e| format!("{:?}", e))
+}
+
+// Frames must be in the canvas, the first one covering it entirely.
+fn check_region(fctl: &FrameControl, ihdr: &IHDRData, first: bool) -> Result<(), String> {
+    let fits = |offset: u32, size: u32, max: u32| {
+        size > 0 && offset.checked_add(size).is_some_and(|end| end <= max)
+    };
+    if !fits(fctl.x_offset, fctl.width, ihdr.width)
+        || !fits(fctl.y_offset, fctl.height, ihdr.height)
+    {
+        return Err(format!(
+            "Frame {}x{} at ({}, {}) is outside the {}x{} image",
+            fctl.width, fctl.height, fctl.x_offset, fctl.y_offset, ihdr.width, ihdr.height
+        ));
+    }
+    if first && (fctl.width, fctl.height) != (ihdr.width, ihdr.height) {
+        return Err("The first frame must cover the whole image".to_string());
+    }
+    Ok(())
+}
+
+// Smallest region containing the pixels differing between two canvases,
+// a single pixel if they are identical since frames cannot be empty.
+fn changed_region(before: &[u8], after: &[u8], width: usize) -> Region {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    let pixels = before.chunks(4).zip(after.chunks(4)).enumerate();
+    for (i, _) in pixels.filter(|(_, (b, a))| b != a) {
+        let (x, y) = (i % width, i / width);
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        });
+    }
+    match bounds {
+        Some((x0, y0, x1, y1)) => (x0, y0, x1 - x0 + 1, y1 - y0 + 1),
+        None => (0, 0, 1, 1),
+    }
+}
+
+fn copy_region(source: &[u8], target: &mut [u8], region: Region, width: usize) {
+    let (x, y, w, h) = region;
+    for row in y..y + h {
+        let start = (row * width + x) * 4;
+        target[start..start + w * 4].copy_from_slice(&source[start..start + w * 4]);
+    }
+}
+
+// Delay as a fraction of a second, in milliseconds if it fits.
+fn delay_fraction(delay: Duration) -> (u16, u16) {
+    match u16::try_from(delay.as_millis()) {
+        Ok(millis) => (millis, 1000),
+        Err(_) => {
+            let seconds = delay.as_secs_f64().round();
+            (seconds.min(u16::MAX as f64) as u16, 1)
+        }
+    }
+}
+
+// Alpha compositing of non-premultiplied 8 bits RGBA pixels.
+fn blend_over(source: &[u8; 4], target: &mut [u8]) {
+    let alpha = source[3] as u32;
+    match alpha {
+        255 => target.copy_from_slice(source),
+        0 => {}
+        _ => {
+            let target_alpha = target[3] as u32 * (255 - alpha) / 255;
+            let out_alpha = alpha + target_alpha;
+            for c in 0..3 {
+                let color = source[c] as u32 * alpha + target[c] as u32 * target_alpha;
+                target[c] = (color / out_alpha) as u8;
+            }
+            target[3] = out_alpha as u8;
+        }
+    }
+}
@@ -1,8 +1,17 @@
 use lazy_static::lazy_static;
 use nom::bytes::complete::take;
+use nom::combinator::map_res;
 use nom::number::complete::be_u32;
 use nom::IResult;
 use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+// Internal imports
+use crate::crc;
+use crate::custom_chunk::{ChunkHandlers, ChunkOrdering};
+use crate::limits::Limits;
+use crate::validation;
 
 // TYPES #######################################################################
 
@@ -36,11 +45,24 @@ pub enum ChunkType {
     sPLT, // suggested palette
     hIST, // palette histogram
     tIME, // image last-modification time
+    eXIf, // exchangeable image file profile
+    cICP, // coding-independent code points
+    mDCv, // mastering display color volume
+    cLLi, // content light level information
+    oFFs, // image offset
+    pCAL, // pixel value calibration
+    sCAL, // physical scale of image subject
+    sTER, // stereo image indicator
+    gIFg, // GIF graphic control extension
+    gIFx, // GIF application extension
+    acTL, // animation control
+    fcTL, // frame control
+    fdAT, // frame data
     // Unknown
-    Unknown([char; 4]),
+    Unknown([u8; 4]),
 }
 
-type ValidationSets = (HashSet<ChunkType>, HashSet<ChunkType>);
+pub(crate) type ValidationSets = (HashSet<ChunkType>, HashSet<ChunkType>);
 
 // CHUNK #######################################################################
 
@@ -48,7 +70,7 @@ impl std::fmt::Display for Chunk<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{{length: {}, type: {:?}, crc: {:?}}}",
+            "{{length: {}, type: {}, crc: {:?}}}",
             self.length, self.chunk_type, self.crc
         )
     }
@@ -61,11 +83,11 @@ impl std::fmt::Debug for Chunk<'_> {
 }
 
 impl<'a> Chunk<'a> {
-    pub fn parse(input: &'a [u8]) -> IResult<&[u8], Self> {
+    pub fn parse(input: &'a [u8]) -> IResult<&'a [u8], Self> {
         let (input, length) = be_u32(input)?;
-        let (input, t) = take(4usize)(input)?;
-        let type_ = [t[0] as char, t[1] as char, t[2] as char, t[3] as char];
-        let chunk_type = ChunkType::from(type_);
+        let (input, chunk_type) = map_res(take(4usize), |t: &[u8]| {
+            ChunkType::try_from([t[0], t[1], t[2], t[3]])
+        })(input)?;
         let (input, data) = take(length)(input)?;
         let (input, crc_) = take(4usize)(input)?;
         let crc = [crc_[0], crc_[1], crc_[2], crc_[3]];
@@ -79,32 +101,161 @@ impl<'a> Chunk<'a> {
             },
         ))
     }
+
+    /// Build a chunk of the given data, computing its length and CRC.
+    pub fn new(chunk_type: ChunkType, data: &'a [u8]) -> Self {
+        Self {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc: crc::chunk_crc(chunk_type.name(), data).to_be_bytes(),
+        }
+    }
+
+    /// Append the chunk to `out` with its stored length and CRC,
+    /// the inverse of `parse`.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.length.to_be_bytes());
+        out.extend_from_slice(&self.chunk_type.name());
+        out.extend_from_slice(self.data);
+        out.extend_from_slice(&self.crc);
+    }
 }
 
 // CHUNKTYPE ###################################################################
 
-impl From<[char; 4]> for ChunkType {
-    fn from(name: [char; 4]) -> Self {
-        match name {
-            ['I', 'H', 'D', 'R'] => ChunkType::IHDR,
-            ['P', 'L', 'T', 'E'] => ChunkType::PLTE,
-            ['I', 'D', 'A', 'T'] => ChunkType::IDAT,
-            ['I', 'E', 'N', 'D'] => ChunkType::IEND,
-            ['t', 'R', 'N', 'S'] => ChunkType::tRNS,
-            ['g', 'A', 'M', 'A'] => ChunkType::gAMA,
-            ['c', 'H', 'R', 'M'] => ChunkType::cHRM,
-            ['s', 'R', 'G', 'B'] => ChunkType::sRGB,
-            ['i', 'C', 'C', 'P'] => ChunkType::iCCP,
-            ['t', 'E', 'X', 't'] => ChunkType::tEXt,
-            ['z', 'T', 'X', 't'] => ChunkType::zTXt,
-            ['i', 'T', 'X', 't'] => ChunkType::iTXt,
-            ['b', 'K', 'G', 'D'] => ChunkType::bKGD,
-            ['p', 'H', 'Y', 's'] => ChunkType::pHYs,
-            ['s', 'B', 'I', 'T'] => ChunkType::sBIT,
-            ['s', 'P', 'L', 'T'] => ChunkType::sPLT,
-            ['h', 'I', 'S', 'T'] => ChunkType::hIST,
-            ['t', 'I', 'M', 'E'] => ChunkType::tIME,
+impl TryFrom<[u8; 4]> for ChunkType {
+    type Error = String;
+    fn try_from(name: [u8; 4]) -> Result<Self, Self::Error> {
+        // Chunk types are restricted to ASCII letters.
+        if !name.iter().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!(
+                "Chunk type {:?} is not made of ASCII letters",
+                name
+            ));
+        }
+        let chunk_type = match &name {
+            b"IHDR" => ChunkType::IHDR,
+            b"PLTE" => ChunkType::PLTE,
+            b"IDAT" => ChunkType::IDAT,
+            b"IEND" => ChunkType::IEND,
+            b"tRNS" => ChunkType::tRNS,
+            b"gAMA" => ChunkType::gAMA,
+            b"cHRM" => ChunkType::cHRM,
+            b"sRGB" => ChunkType::sRGB,
+            b"iCCP" => ChunkType::iCCP,
+            b"tEXt" => ChunkType::tEXt,
+            b"zTXt" => ChunkType::zTXt,
+            b"iTXt" => ChunkType::iTXt,
+            b"bKGD" => ChunkType::bKGD,
+            b"pHYs" => ChunkType::pHYs,
+            b"sBIT" => ChunkType::sBIT,
+            b"sPLT" => ChunkType::sPLT,
+            b"hIST" => ChunkType::hIST,
+            b"tIME" => ChunkType::tIME,
+            b"eXIf" => ChunkType::eXIf,
+            b"cICP" => ChunkType::cICP,
+            b"mDCv" => ChunkType::mDCv,
+            b"cLLi" => ChunkType::cLLi,
+            b"oFFs" => ChunkType::oFFs,
+            b"pCAL" => ChunkType::pCAL,
+            b"sCAL" => ChunkType::sCAL,
+            b"sTER" => ChunkType::sTER,
+            b"gIFg" => ChunkType::gIFg,
+            b"gIFx" => ChunkType::gIFx,
+            b"acTL" => ChunkType::acTL,
+            b"fcTL" => ChunkType::fcTL,
+            b"fdAT" => ChunkType::fdAT,
             _ => ChunkType::Unknown(name),
+        };
+        Ok(chunk_type)
+    }
+}
+
+impl ChunkType {
+    pub fn name(&self) -> [u8; 4] {
+        match self {
+            ChunkType::IHDR => *b"IHDR",
+            ChunkType::PLTE => *b"PLTE",
+            ChunkType::IDAT => *b"IDAT",
+            ChunkType::IEND => *b"IEND",
+            ChunkType::tRNS => *b"tRNS",
+            ChunkType::gAMA => *b"gAMA",
+            ChunkType::cHRM => *b"cHRM",
+            ChunkType::sRGB => *b"sRGB",
+            ChunkType::iCCP => *b"iCCP",
+            ChunkType::tEXt => *b"tEXt",
+            ChunkType::zTXt => *b"zTXt",
+            ChunkType::iTXt => *b"iTXt",
+            ChunkType::bKGD => *b"bKGD",
+            ChunkType::pHYs => *b"pHYs",
+            ChunkType::sBIT => *b"sBIT",
+            ChunkType::sPLT => *b"sPLT",
+            ChunkType::hIST => *b"hIST",
+            ChunkType::tIME => *b"tIME",
+            ChunkType::eXIf => *b"eXIf",
+            ChunkType::cICP => *b"cICP",
+            ChunkType::mDCv => *b"mDCv",
+            ChunkType::cLLi => *b"cLLi",
+            ChunkType::oFFs => *b"oFFs",
+            ChunkType::pCAL => *b"pCAL",
+            ChunkType::sCAL => *b"sCAL",
+            ChunkType::sTER => *b"sTER",
+            ChunkType::gIFg => *b"gIFg",
+            ChunkType::gIFx => *b"gIFx",
+            ChunkType::acTL => *b"acTL",
+            ChunkType::fcTL => *b"fcTL",
+            ChunkType::fdAT => *b"fdAT",
+            ChunkType::Unknown(name) => *name,
+        }
+    }
+
+    // Spec: http://www.libpng.org/pub/png/spec/1.2/png-1.2-pdg.html#Chunk-naming-conventions
+    // Each property is given by bit 5 (lowercase) of one of the four bytes.
+
+    /// Ancillary bit (first byte): the chunk is not necessary to display the image.
+    pub fn is_ancillary(&self) -> bool {
+        property_bit(self.name()[0])
+    }
+
+    pub fn is_critical(&self) -> bool {
+        !self.is_ancillary()
+    }
+
+    /// Private bit (second byte): the chunk is not registered.
+    pub fn is_private(&self) -> bool {
+        property_bit(self.name()[1])
+    }
+
+    /// Reserved bit (third byte): must be 0 in the current version of PNG.
+    pub fn is_reserved_bit_set(&self) -> bool {
+        property_bit(self.name()[2])
+    }
+
+    /// Safe-to-copy bit (fourth byte): the chunk may be copied
+    /// by editors modifying critical chunks even if they don't recognize it.
+    pub fn is_safe_to_copy(&self) -> bool {
+        property_bit(self.name()[3])
+    }
+}
+
+fn property_bit(c: u8) -> bool {
+    c & 0x20 != 0
+}
+
+impl std::fmt::Display for ChunkType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.name();
+        write!(f, "{}", String::from_utf8_lossy(&name))
+    }
+}
+
+impl FromStr for ChunkType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[a, b, c, d] => ChunkType::try_from([a, b, c, d]),
+            _ => Err(format!("Chunk type {} must have 4 letters", s)),
         }
     }
 }
@@ -114,14 +265,40 @@ impl From<[char; 4]> for ChunkType {
 pub fn validate_chunk_constraints<'a, 'c>(
     chunks: &'a [Chunk<'c>],
 ) -> Result<&'a [Chunk<'c>], String> {
+    validate_chunk_constraints_with(chunks, &ChunkHandlers::new(), &Limits::default())
+}
+
+/// Validate chunks ordering, including the constraints
+/// declared by the handlers of custom chunks.
+/// Ancillary chunks with an invalid payload are accepted,
+/// they are reported by `lint` instead.
+/// Compressed chunks are not inflated beyond `limits.max_text_size`.
+pub fn validate_chunk_constraints_with<'a, 'c>(
+    chunks: &'a [Chunk<'c>],
+    handlers: &ChunkHandlers,
+    limits: &Limits,
+) -> Result<&'a [Chunk<'c>], String> {
+    match validation::validate_chunks_split(chunks, handlers, limits)
+        .0
+        .first()
+    {
+        Some(violation) => Err(violation.to_string()),
+        None => Ok(chunks),
+    }
+}
+
+/// Initial state of the ordering validation, only IHDR is authorized.
+pub(crate) fn start_validation_sets() -> ValidationSets {
     // let inner_chunks = ihdr_first(chunks).and_then(iend_last)?;
     // let authorized_set = START_CHUNKS.clone();
     let mut authorized_set = HashSet::new();
     authorized_set.insert(ChunkType::IHDR);
-    let _ = chunks
-        .iter()
-        .try_fold((HashSet::new(), authorized_set), validate_chunk)?;
-    Ok(chunks)
+    (HashSet::new(), authorized_set)
+}
+
+/// Whether a chunk of this type must be placed before PLTE.
+pub(crate) fn precedes_plte(chunk_type: ChunkType) -> bool {
+    chunk_type != ChunkType::PLTE && BEFORE_PLTE_CHUNKS.contains(&chunk_type)
 }
 
 lazy_static! {
@@ -133,6 +310,9 @@ lazy_static! {
         ChunkType::sRGB, // standard RGB color space
         ChunkType::iCCP, // embedded ICC profile
         ChunkType::sBIT, // significant bits
+        ChunkType::cICP, // coding-independent code points
+        ChunkType::mDCv, // mastering display color volume
+        ChunkType::cLLi, // content light level information
     ]
     .iter()
     .cloned()
@@ -151,6 +331,15 @@ lazy_static! {
         ChunkType::sBIT, // significant bits
         ChunkType::sPLT, // suggested palette
         ChunkType::hIST, // palette histogram
+        ChunkType::eXIf, // exchangeable image file profile
+        ChunkType::cICP, // coding-independent code points
+        ChunkType::mDCv, // mastering display color volume
+        ChunkType::cLLi, // content light level information
+        ChunkType::oFFs, // image offset
+        ChunkType::pCAL, // pixel value calibration
+        ChunkType::sCAL, // physical scale of image subject
+        ChunkType::sTER, // stereo image indicator
+        ChunkType::acTL, // animation control
     ]
     .iter()
     .cloned()
@@ -175,6 +364,18 @@ lazy_static! {
         ChunkType::sPLT, // suggested palette
         ChunkType::hIST, // palette histogram
         ChunkType::tIME, // image last-modification time
+        ChunkType::eXIf, // exchangeable image file profile
+        ChunkType::cICP, // coding-independent code points
+        ChunkType::mDCv, // mastering display color volume
+        ChunkType::cLLi, // content light level information
+        ChunkType::oFFs, // image offset
+        ChunkType::pCAL, // pixel value calibration
+        ChunkType::sCAL, // physical scale of image subject
+        ChunkType::sTER, // stereo image indicator
+        ChunkType::gIFg, // GIF graphic control extension
+        ChunkType::gIFx, // GIF application extension
+        ChunkType::acTL, // animation control
+        ChunkType::fcTL, // frame control
     ]
     .iter()
     .cloned()
@@ -212,8 +413,41 @@ lazy_static! {
 //         iTXt    Yes     None
 //         tEXt    Yes     None
 //         zTXt    Yes     None
-fn validate_chunk(acc: ValidationSets, chunk: &Chunk) -> Result<ValidationSets, String> {
+//
+// Extension chunks (PNG 1.5 and 3rd edition):
+//
+//         eXIf    No      Before IDAT
+//         cICP    No      Before PLTE and IDAT
+//         mDCv    No      Before PLTE and IDAT
+//         cLLi    No      Before PLTE and IDAT
+//
+// Registered extension chunks:
+//
+//         oFFs    No      Before IDAT
+//         pCAL    No      Before IDAT
+//         sCAL    No      Before IDAT
+//         sTER    No      Before IDAT
+//         gIFg    Yes     None
+//         gIFx    Yes     None
+//
+// Animation chunks (APNG):
+//
+//         acTL    No      Before IDAT
+//         fcTL    Yes     None
+//         fdAT    Yes     After IDAT
+//
+// Unknown ancillary chunks are ignored, wherever they are between IHDR and IEND.
+// Unknown critical chunks make the image undecodable.
+// Custom chunks with a registered handler follow their declared constraints.
+pub(crate) fn validate_chunk(
+    acc: ValidationSets,
+    chunk: &Chunk,
+    handlers: &ChunkHandlers,
+) -> Result<ValidationSets, String> {
     let (mut present, mut authorized) = acc;
+    if let ChunkType::Unknown(name) = chunk.chunk_type {
+        return validate_unknown_chunk(name, present, authorized, handlers);
+    }
     if authorized.contains(&chunk.chunk_type) {
         match chunk.chunk_type {
             // --- Critical chunks ---
@@ -238,6 +472,7 @@ fn validate_chunk(acc: ValidationSets, chunk: &Chunk) -> Result<ValidationSets,
                         .cloned()
                         .collect();
                     authorized.insert(ChunkType::IEND);
+                    authorized.insert(ChunkType::fdAT);
                     present.insert(ChunkType::IDAT);
                 }
                 Ok((present, authorized))
@@ -299,6 +534,51 @@ fn validate_chunk(acc: ValidationSets, chunk: &Chunk) -> Result<ValidationSets,
                 present.insert(ChunkType::pHYs);
                 Ok((present, authorized))
             }
+            ChunkType::cICP => {
+                authorized.remove(&ChunkType::cICP);
+                present.insert(ChunkType::cICP);
+                Ok((present, authorized))
+            }
+            ChunkType::mDCv => {
+                authorized.remove(&ChunkType::mDCv);
+                present.insert(ChunkType::mDCv);
+                Ok((present, authorized))
+            }
+            ChunkType::cLLi => {
+                authorized.remove(&ChunkType::cLLi);
+                present.insert(ChunkType::cLLi);
+                Ok((present, authorized))
+            }
+            ChunkType::eXIf => {
+                authorized.remove(&ChunkType::eXIf);
+                present.insert(ChunkType::eXIf);
+                Ok((present, authorized))
+            }
+            ChunkType::oFFs => {
+                authorized.remove(&ChunkType::oFFs);
+                present.insert(ChunkType::oFFs);
+                Ok((present, authorized))
+            }
+            ChunkType::pCAL => {
+                authorized.remove(&ChunkType::pCAL);
+                present.insert(ChunkType::pCAL);
+                Ok((present, authorized))
+            }
+            ChunkType::sCAL => {
+                authorized.remove(&ChunkType::sCAL);
+                present.insert(ChunkType::sCAL);
+                Ok((present, authorized))
+            }
+            ChunkType::sTER => {
+                authorized.remove(&ChunkType::sTER);
+                present.insert(ChunkType::sTER);
+                Ok((present, authorized))
+            }
+            ChunkType::acTL => {
+                authorized.remove(&ChunkType::acTL);
+                present.insert(ChunkType::acTL);
+                Ok((present, authorized))
+            }
             // before IDAT, multiple possible
             ChunkType::sPLT => {
                 present.insert(ChunkType::sPLT);
@@ -339,9 +619,83 @@ fn validate_chunk(acc: ValidationSets, chunk: &Chunk) -> Result<ValidationSets,
                 }
                 Ok((present, authorized))
             }
-            ChunkType::Unknown(name) => Err(format!("{:?} chunks are not handled for now", name)),
+            ChunkType::gIFg => {
+                present.insert(ChunkType::gIFg);
+                if present.contains(&ChunkType::IDAT) {
+                    // IDAT chunks must be consecutive
+                    authorized.remove(&ChunkType::IDAT);
+                }
+                Ok((present, authorized))
+            }
+            ChunkType::gIFx => {
+                present.insert(ChunkType::gIFx);
+                if present.contains(&ChunkType::IDAT) {
+                    // IDAT chunks must be consecutive
+                    authorized.remove(&ChunkType::IDAT);
+                }
+                Ok((present, authorized))
+            }
+            ChunkType::fcTL => {
+                present.insert(ChunkType::fcTL);
+                if present.contains(&ChunkType::IDAT) {
+                    // IDAT chunks must be consecutive
+                    authorized.remove(&ChunkType::IDAT);
+                }
+                Ok((present, authorized))
+            }
+            // after IDAT, multiple possible
+            ChunkType::fdAT => {
+                present.insert(ChunkType::fdAT);
+                authorized.remove(&ChunkType::IDAT);
+                Ok((present, authorized))
+            }
+            ChunkType::Unknown(_) => unreachable!(),
         }
     } else {
-        Err(format!("Unauthorized chunk: {:?}", &chunk.chunk_type))
+        Err(format!("Unauthorized chunk: {}", &chunk.chunk_type))
+    }
+}
+
+fn validate_unknown_chunk(
+    name: [u8; 4],
+    mut present: HashSet<ChunkType>,
+    mut authorized: HashSet<ChunkType>,
+    handlers: &ChunkHandlers,
+) -> Result<ValidationSets, String> {
+    let chunk_type = ChunkType::Unknown(name);
+    let handler = handlers.get(&chunk_type);
+    if handler.is_none() && chunk_type.is_critical() {
+        return Err(format!("Unknown critical chunk: {}", chunk_type));
+    }
+    if !present.contains(&ChunkType::IHDR) || present.contains(&ChunkType::IEND) {
+        return Err(format!("Unauthorized chunk: {}", chunk_type));
+    }
+    if let Some(handler) = handler {
+        if !handler.multiple && present.contains(&chunk_type) {
+            return Err(format!("Multiple {} chunks", chunk_type));
+        }
+        let after_plte = present.contains(&ChunkType::PLTE);
+        let after_idat = present.contains(&ChunkType::IDAT);
+        match handler.ordering {
+            ChunkOrdering::BeforePlte if after_plte || after_idat => {
+                return Err(format!("{} must be before PLTE and IDAT", chunk_type));
+            }
+            ChunkOrdering::AfterPlteBeforeIdat | ChunkOrdering::BeforeIdat if after_idat => {
+                return Err(format!("{} must be before IDAT", chunk_type));
+            }
+            ChunkOrdering::AfterPlteBeforeIdat => {
+                authorized.remove(&ChunkType::PLTE);
+            }
+            ChunkOrdering::AfterIdat if !after_idat => {
+                return Err(format!("{} must be after IDAT", chunk_type));
+            }
+            _ => {}
+        }
+        present.insert(chunk_type);
+    }
+    if present.contains(&ChunkType::IDAT) {
+        // IDAT chunks must be consecutive
+        authorized.remove(&ChunkType::IDAT);
     }
+    Ok((present, authorized))
 }
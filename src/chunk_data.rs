@@ -1,18 +1,27 @@
 use inflate::core::inflate_flags::{
-    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_PARSE_ZLIB_HEADER,
-    TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+    TINFL_FLAG_PARSE_ZLIB_HEADER, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
 };
+use miniz_oxide::deflate::compress_to_vec_zlib;
 use miniz_oxide::inflate;
 use nom::bytes::complete::{take, take_till};
 use nom::combinator::{map, map_res, rest};
-use nom::number::complete::{be_u16, be_u32, be_u8};
+use nom::error::ErrorKind;
+use nom::multi::many0;
+use nom::number::complete::{be_i32, be_u16, be_u32, be_u8};
+use nom::sequence::tuple;
 use nom::IResult;
 use std::convert::TryFrom;
+use std::error::Error;
 use std::io::Cursor;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // Internal imports
 use crate::chunk::{Chunk, ChunkType};
 use crate::color::ColorType;
+use crate::exif::Exif;
+use crate::inflater::{Inflater, MinizInflater, RawDeflateInflater};
+use crate::limits::Limits;
+use crate::zlib::ZlibHeader;
 
 // TYPES #######################################################################
 
@@ -25,25 +34,38 @@ pub enum ChunkData<'a> {
     // IDAT(IDATData), // image data
     IEND, // image trailer
     // Ancillary chunks
-    // tRNS, // transparency
-    // gAMA, // image gamma
-    // cHRM, // primary chromaticities
-    // sRGB, // standard RGB color space
-    // iCCP, // embedded ICC profile
-    tEXt(Text),           // textual data
-    zTXt(CompressedText), // compressed textual data
-    // iTXt, // international textual data
-    bKGD(Background),             // background color
-    pHYs(PhysicalPixelDimension), // physical pixel dimensions
-    sBIT(SignificantBits),        // significant bits
-    // sPLT, // suggested palette
-    // hIST, // palette histogram
-    tIME(LastModificationTime), // image last-modification time
+    // tRNS, // transparency (depends on IHDR, see parse_trns_data)
+    gAMA(Gamma),                       // image gamma
+    cHRM(Chromaticities),              // primary chromaticities
+    sRGB(RenderingIntent),             // standard RGB color space
+    iCCP(IccProfile),                  // embedded ICC profile
+    tEXt(Text),                        // textual data
+    zTXt(CompressedText),              // compressed textual data
+    iTXt(InternationalText),           // international textual data
+    bKGD(Background),                  // background color
+    pHYs(PhysicalPixelDimension),      // physical pixel dimensions
+    sBIT(SignificantBits),             // significant bits
+    sPLT(SuggestedPalette),            // suggested palette
+    hIST(Vec<u16>),                    // palette histogram
+    tIME(LastModificationTime),        // image last-modification time
+    eXIf(Exif),                        // exchangeable image file profile
+    cICP(CodingIndependentCodePoints), // coding-independent code points
+    mDCv(MasteringDisplayColorVolume), // mastering display color volume
+    cLLi(ContentLightLevel),           // content light level information
+    oFFs(ImageOffset),                 // image offset
+    pCAL(PixelCalibration),            // pixel value calibration
+    sCAL(PhysicalScale),               // physical scale of image subject
+    sTER(StereoMode),                  // stereo image indicator
+    gIFg(GifGraphicControl),           // GIF graphic control extension
+    gIFx(GifApplication),              // GIF application extension
+    acTL(AnimationControl),            // animation control
+    fcTL(FrameControl),                // frame control
+    fdAT(FrameData<'a>),               // frame data
     // Unknown
     Unknown(&'a [u8]),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct IHDRData {
     pub width: u32,
     pub height: u32,
@@ -54,7 +76,7 @@ pub struct IHDRData {
     pub interlace_method: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SignificantBits {
     Gray(u8),
     GrayAlpha([u8; 2]),
@@ -62,30 +84,232 @@ pub enum SignificantBits {
     RGBA([u8; 4]),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct PhysicalPixelDimension {
-    x: u32,
-    y: u32,
-    unit: DimensionUnit,
+    pub x: u32,
+    pub y: u32,
+    pub unit: DimensionUnit,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DimensionUnit {
     Unknown,
     Meter,
 }
 
-#[derive(Debug)]
+/// Gamma multiplied by 100000, as stored in the gAMA chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+/// Chromaticities multiplied by 100000, as stored in the cHRM chunk.
+/// Each pair is an (x, y) CIE 1931 coordinate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white_point: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// Video signal type of the image (ITU-T H.273 code points).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CodingIndependentCodePoints {
+    pub color_primaries: u8,
+    pub transfer_function: u8,
+    pub matrix_coefficients: u8,
+    pub video_full_range: bool,
+}
+
+/// Chromaticities in units of 0.00002 and luminances in units of 0.0001 cd/m2.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MasteringDisplayColorVolume {
+    pub red: (u16, u16),
+    pub green: (u16, u16),
+    pub blue: (u16, u16),
+    pub white_point: (u16, u16),
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+/// Light levels in units of 0.0001 cd/m2.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_content_light_level: u32,
+    pub max_frame_average_light_level: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageOffset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer,
+}
+
+/// Mapping of the stored samples to physical values.
+/// Original samples are in [x0, x1] and the equation
+/// maps them to physical values expressed in `unit`.
+#[derive(Debug, Clone)]
+pub struct PixelCalibration {
+    pub name: String,
+    pub x0: i32,
+    pub x1: i32,
+    pub equation: CalibrationEquation,
+    pub unit: String,
+    pub parameters: Vec<f64>,
+    // Parameters as written in the chunk, kept by `to_bytes`
+    // for the parameters whose value did not change.
+    parameters_text: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CalibrationEquation {
+    Linear,         // p0 + p1 * x / (x1 - x0)
+    BaseE,          // p0 + p1 * exp(p2 * x / (x1 - x0))
+    ArbitraryBase,  // p0 + p1 * pow(p2, x / (x1 - x0))
+    HyperbolicSine, // p0 + p1 * sinh(p2 * (x - p3) / (x1 - x0))
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysicalScale {
+    pub unit: ScaleUnit,
+    pub width: f64,
+    pub height: f64,
+    // Width and height as written in the chunk, kept by `to_bytes`
+    // while their value did not change.
+    width_text: String,
+    height_text: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScaleUnit {
+    Meter,
+    Radian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StereoMode {
+    CrossFuse,
+    DivergingFuse,
+}
+
+/// Delay time is in hundredths of a second.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GifGraphicControl {
+    pub disposal_method: u8,
+    pub user_input: bool,
+    pub delay_time: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GifApplication {
+    pub identifier: [u8; 8],
+    pub authentication_code: [u8; 3],
+    pub data: Vec<u8>,
+}
+
+/// Number of frames of an APNG animation,
+/// and number of times to play it, 0 meaning forever.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    pub num_plays: u32,
+}
+
+/// Region and timing of an APNG frame.
+/// The delay is `delay_num / delay_den` seconds, a zero denominator meaning 100.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+/// How the frame region is reset before rendering the next frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisposeOp {
+    None,       // left as is
+    Background, // cleared to transparent black
+    Previous,   // reverted to its content before the frame
+}
+
+/// How the frame is rendered into the region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendOp {
+    Source, // replaces the region
+    Over,   // alpha composited over the region
+}
+
+/// Compressed data of an APNG frame, after its sequence number.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameData<'a> {
+    pub sequence_number: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone)]
+pub struct IccProfile {
+    pub name: String,
+    pub method: u8,
+    pub profile: Vec<u8>,
+    /// Original zlib stream, kept by `to_bytes`
+    /// while it still inflates to the same content.
+    pub zlib_stream: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Text {
-    keyword: String,
-    text: String,
+    pub keyword: String,
+    pub text: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompressedText {
-    keyword: String,
-    method: u8,
-    text: String,
+    pub keyword: String,
+    pub method: u8,
+    pub text: String,
+    /// Original zlib stream, kept by `to_bytes`
+    /// while it still inflates to the same content.
+    pub zlib_stream: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalText {
+    pub keyword: String,
+    pub compressed: bool,
+    pub method: u8,
+    pub language_tag: String,
+    pub translated_keyword: String,
+    pub text: String,
+    /// Original zlib stream, kept by `to_bytes`
+    /// while it still inflates to the same content.
+    pub zlib_stream: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    Palette(Vec<u8>),
+    Gray(u16),
+    RGB([u16; 3]),
 }
 
 // #[derive(Debug)]
@@ -94,62 +318,448 @@ pub struct CompressedText {
 //     data: Vec<u8>,
 // }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedPalette {
+    pub name: String,
+    pub sample_depth: u8,
+    pub entries: Vec<SuggestedPaletteEntry>,
+}
+
+/// Samples of an sPLT entry, scaled to 0-255 or 0-65535
+/// depending on the palette sample depth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SuggestedPaletteEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    pub frequency: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Background {
     Palette(u8),
     Gray(u16),
     RGB([u16; 3]),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct LastModificationTime {
-    year: u16,
-    month: u8,
-    day: u8,
-    hour: u8,
-    minute: u8,
-    second: u8,
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Inflated image data and how the zlib stream ended.
+#[derive(Debug)]
+pub struct InflatedIdats {
+    pub data: Vec<u8>,
+    /// Whether the IDAT chunks ended before the end of the zlib stream.
+    pub truncated: bool,
+    /// Number of compressed bytes after the end of the zlib stream.
+    pub trailing_bytes: usize,
+    /// Header of the zlib stream, if there are at least its 2 bytes.
+    pub zlib_header: Option<ZlibHeader>,
+    /// Whether the Adler-32 checksum was computed and matched.
+    pub adler32_verified: bool,
 }
 
 // FUNCTIONS ###################################################################
 
+// Maximum width and height, 2^31 - 1.
+const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
 impl IHDRData {
-    pub fn scanline_width(&self) -> usize {
-        let nb_chanels = match self.color_type {
+    pub fn nb_channels(&self) -> usize {
+        match self.color_type {
             ColorType::Gray => 1,
             ColorType::GrayAlpha => 2,
             ColorType::RGB => 3,
+            ColorType::PLTE => 1,
             ColorType::RGBA => 4,
-            ColorType::PLTE => panic!("Palette type not handled"),
+        }
+    }
+
+    /// Check the IHDR fields against the spec.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.width > MAX_DIMENSION {
+            return Err(format!("IHDR width {} is not in 1-2^31-1", self.width));
+        }
+        if self.height == 0 || self.height > MAX_DIMENSION {
+            return Err(format!("IHDR height {} is not in 1-2^31-1", self.height));
+        }
+        let allowed_bit_depths: &[u8] = match self.color_type {
+            ColorType::Gray => &[1, 2, 4, 8, 16],
+            ColorType::RGB => &[8, 16],
+            ColorType::PLTE => &[1, 2, 4, 8],
+            ColorType::GrayAlpha => &[8, 16],
+            ColorType::RGBA => &[8, 16],
+        };
+        if !allowed_bit_depths.contains(&self.bit_depth) {
+            return Err(format!(
+                "IHDR bit depth {} is not allowed for color type {:?}, only {:?}",
+                self.bit_depth, self.color_type, allowed_bit_depths
+            ));
+        }
+        if self.compression_method != 0 {
+            return Err(format!(
+                "IHDR compression method {} is not valid, only 0 is",
+                self.compression_method
+            ));
+        }
+        if self.filter_method != 0 {
+            return Err(format!(
+                "IHDR filter method {} is not valid, only 0 is",
+                self.filter_method
+            ));
+        }
+        if self.interlace_method > 1 {
+            return Err(format!(
+                "IHDR interlace method {} is not valid, only 0 or 1 are",
+                self.interlace_method
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of bytes of a pixel as used by filters,
+    /// 1 for pixels of less than 8 bits.
+    pub fn bytes_per_pixel(&self) -> usize {
+        std::cmp::max(1, self.nb_channels() * self.bit_depth as usize / 8)
+    }
+
+    /// Size in bytes of a scanline, filter byte included.
+    /// Pixels of less than 8 bits are packed, rows are rounded up to a byte.
+    /// Fails if it does not fit in `usize`.
+    pub fn scanline_width(&self) -> Result<usize, String> {
+        let bits_per_pixel = self.nb_channels() * self.bit_depth as usize;
+        usize::try_from(self.width)
+            .ok()
+            .and_then(|w| w.checked_mul(bits_per_pixel))
+            .and_then(|bits| bits.div_ceil(8).checked_add(1))
+            .ok_or_else(|| format!("Scanline width overflows for image width {}", self.width))
+    }
+
+    /// Size in bytes of the inflated image data.
+    /// Fails if it does not fit in `usize`.
+    pub fn image_data_len(&self) -> Result<usize, String> {
+        let scanline_width = self.scanline_width()?;
+        usize::try_from(self.height)
+            .ok()
+            .and_then(|h| h.checked_mul(scanline_width))
+            .ok_or_else(|| {
+                format!(
+                    "Image data size overflows for image {}x{}",
+                    self.width, self.height
+                )
+            })
+    }
+}
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+impl PhysicalPixelDimension {
+    /// Build a pHYs value in pixels per meter from dots per inch.
+    pub fn from_dpi(x_dpi: f64, y_dpi: f64) -> Self {
+        PhysicalPixelDimension {
+            x: (x_dpi / METERS_PER_INCH).round() as u32,
+            y: (y_dpi / METERS_PER_INCH).round() as u32,
+            unit: DimensionUnit::Meter,
+        }
+    }
+
+    /// Horizontal and vertical dots per inch,
+    /// only available if the unit is the meter.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            DimensionUnit::Meter => Some((
+                self.x as f64 * METERS_PER_INCH,
+                self.y as f64 * METERS_PER_INCH,
+            )),
+            DimensionUnit::Unknown => None,
+        }
+    }
+}
+
+impl Gamma {
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / 100_000.0
+    }
+}
+
+const TRANSFER_PQ: u8 = 16;
+const TRANSFER_HLG: u8 = 18;
+
+impl CodingIndependentCodePoints {
+    /// SMPTE ST 2084 perceptual quantizer transfer function.
+    pub fn is_pq(&self) -> bool {
+        self.transfer_function == TRANSFER_PQ
+    }
+
+    /// ARIB STD-B67 hybrid log-gamma transfer function.
+    pub fn is_hlg(&self) -> bool {
+        self.transfer_function == TRANSFER_HLG
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.is_pq() || self.is_hlg()
+    }
+}
+
+impl MasteringDisplayColorVolume {
+    /// Maximum and minimum luminances in cd/m2.
+    pub fn luminance_nits(&self) -> (f64, f64) {
+        (
+            self.max_luminance as f64 / 10_000.0,
+            self.min_luminance as f64 / 10_000.0,
+        )
+    }
+}
+
+impl ContentLightLevel {
+    /// MaxCLL and MaxFALL in cd/m2.
+    pub fn light_levels_nits(&self) -> (f64, f64) {
+        (
+            self.max_content_light_level as f64 / 10_000.0,
+            self.max_frame_average_light_level as f64 / 10_000.0,
+        )
+    }
+}
+
+impl CalibrationEquation {
+    pub fn nb_parameters(self) -> usize {
+        match self {
+            CalibrationEquation::Linear => 2,
+            CalibrationEquation::BaseE => 3,
+            CalibrationEquation::ArbitraryBase => 3,
+            CalibrationEquation::HyperbolicSine => 4,
+        }
+    }
+}
+
+impl PixelCalibration {
+    pub fn new(
+        name: String,
+        x0: i32,
+        x1: i32,
+        equation: CalibrationEquation,
+        unit: String,
+        parameters: Vec<f64>,
+    ) -> Self {
+        PixelCalibration {
+            name,
+            x0,
+            x1,
+            equation,
+            unit,
+            parameters,
+            parameters_text: Vec::new(),
+        }
+    }
+
+    /// Physical value of a stored sample of an image with the given bit depth.
+    /// Fails if the bit depth is not in 1-16, the sample does not fit in it,
+    /// or the calibration is invalid.
+    pub fn physical_value(&self, sample: u16, bit_depth: u8) -> Result<f64, String> {
+        if bit_depth == 0 || bit_depth > 16 {
+            return Err(format!("Bit depth {} is not in 1-16", bit_depth));
+        }
+        let max = (1_i64 << bit_depth) - 1;
+        if sample as i64 > max {
+            return Err(format!(
+                "Sample {} does not fit in {} bits",
+                sample, bit_depth
+            ));
+        }
+        if self.parameters.len() != self.equation.nb_parameters() || self.x0 == self.x1 {
+            return Err("pCAL parameters do not match the equation".to_string());
+        }
+        let (x0, x1) = (self.x0 as i64, self.x1 as i64);
+        // Original sample, with the integer rounding of the specification.
+        let x = ((sample as i64 * (x1 - x0) + max / 2) / max + x0) as f64;
+        let range = (x1 - x0) as f64;
+        let p = &self.parameters;
+        Ok(match self.equation {
+            CalibrationEquation::Linear => p[0] + p[1] * x / range,
+            CalibrationEquation::BaseE => p[0] + p[1] * (p[2] * x / range).exp(),
+            CalibrationEquation::ArbitraryBase => p[0] + p[1] * p[2].powf(x / range),
+            CalibrationEquation::HyperbolicSine => p[0] + p[1] * (p[2] * (x - p[3]) / range).sinh(),
+        })
+    }
+
+    /// Physical values of all stored samples.
+    pub fn physical_values(&self, samples: &[u16], bit_depth: u8) -> Result<Vec<f64>, String> {
+        samples
+            .iter()
+            .map(|&s| self.physical_value(s, bit_depth))
+            .collect()
+    }
+}
+
+/// The numbers as written in the chunk are not compared.
+impl PartialEq for PixelCalibration {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && (self.x0, self.x1) == (other.x0, other.x1)
+            && self.equation == other.equation
+            && self.unit == other.unit
+            && self.parameters == other.parameters
+    }
+}
+
+impl PhysicalScale {
+    pub fn new(unit: ScaleUnit, width: f64, height: f64) -> Self {
+        PhysicalScale {
+            unit,
+            width,
+            height,
+            width_text: String::new(),
+            height_text: String::new(),
+        }
+    }
+}
+
+/// The numbers as written in the chunk are not compared.
+impl PartialEq for PhysicalScale {
+    fn eq(&self, other: &Self) -> bool {
+        self.unit == other.unit && self.width == other.width && self.height == other.height
+    }
+}
+
+impl LastModificationTime {
+    /// Check that all fields are in their valid range.
+    /// A second of 60 is authorized for leap seconds.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.month < 1 || self.month > 12 {
+            return Err(format!("tIME month {} is not in 1-12", self.month));
+        }
+        let max_day = days_in_month(self.year, self.month);
+        if self.day < 1 || self.day > max_day {
+            return Err(format!("tIME day {} is not in 1-{}", self.day, max_day));
+        }
+        if self.hour > 23 {
+            return Err(format!("tIME hour {} is not in 0-23", self.hour));
+        }
+        if self.minute > 59 {
+            return Err(format!("tIME minute {} is not in 0-59", self.minute));
+        }
+        if self.second > 60 {
+            return Err(format!("tIME second {} is not in 0-60", self.second));
+        }
+        Ok(())
+    }
+
+    /// Convert the UTC time of the tIME chunk into a `SystemTime`.
+    pub fn to_system_time(&self) -> Result<SystemTime, String> {
+        self.validate()?;
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let seconds =
+            days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        let time = if seconds >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
         };
-        let bytes_per_channel = std::cmp::max(1, self.bit_depth as u32 / 8);
-        (1 + self.width * nb_chanels * bytes_per_channel) as usize
+        time.ok_or_else(|| format!("tIME {:?} is not representable", self))
+    }
+}
+
+impl TryFrom<&LastModificationTime> for SystemTime {
+    type Error = String;
+    fn try_from(time: &LastModificationTime) -> Result<Self, Self::Error> {
+        time.to_system_time()
+    }
+}
+
+impl FrameControl {
+    /// Time during which the frame is displayed.
+    pub fn delay(&self) -> Duration {
+        let den = match self.delay_den {
+            0 => 100,
+            den => den,
+        };
+        Duration::from_secs_f64(self.delay_num as f64 / den as f64)
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
     }
 }
 
+// Number of days since 1970-01-01 of a proleptic Gregorian date.
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
 pub fn parse_chunk_data<'a>(chunk: &'a Chunk<'a>) -> IResult<&'a [u8], ChunkData<'a>> {
+    parse_chunk_data_with_limits(chunk, &Limits::default())
+}
+
+/// Parse chunk data, compressed text and ICC profiles are limited
+/// to `limits.max_text_size` decompressed bytes, with a `TooLarge` failure otherwise.
+pub fn parse_chunk_data_with_limits<'a>(
+    chunk: &'a Chunk<'a>,
+    limits: &Limits,
+) -> IResult<&'a [u8], ChunkData<'a>> {
+    let max_text_size = limits.max_text_size;
     match chunk.chunk_type {
         // --- Critical chunks ---
-        ChunkType::IHDR => map(parse_ihdr_data, ChunkData::IHDR)(&chunk.data),
-        ChunkType::PLTE => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::IDAT => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::IEND => map(take(0u8), |_| ChunkData::IEND)(&chunk.data),
+        ChunkType::IHDR => map(parse_ihdr_data, ChunkData::IHDR)(chunk.data),
+        ChunkType::PLTE => map(rest, ChunkData::Unknown)(chunk.data),
+        ChunkType::IDAT => map(rest, ChunkData::Unknown)(chunk.data),
+        ChunkType::IEND => map(take(0u8), |_| ChunkData::IEND)(chunk.data),
         // --- Ancillary chunks ---
-        ChunkType::cHRM => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::gAMA => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::iCCP => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::sBIT => map(|d| parse_sbit_data(d, chunk.length), ChunkData::sBIT)(&chunk.data),
-        ChunkType::sRGB => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::bKGD => map(|d| parse_bkgd_data(d, chunk.length), ChunkData::bKGD)(&chunk.data),
-        ChunkType::hIST => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::tRNS => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::pHYs => map(parse_phys_data, ChunkData::pHYs)(&chunk.data),
-        ChunkType::sPLT => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::tIME => map(parse_time_data, ChunkData::tIME)(&chunk.data),
-        ChunkType::iTXt => map(take(0u8), ChunkData::Unknown)(&chunk.data),
-        ChunkType::tEXt => map(parse_text_data, ChunkData::tEXt)(&chunk.data),
-        ChunkType::zTXt => map(parse_ztxt_data, ChunkData::zTXt)(&chunk.data),
-        ChunkType::Unknown(_) => map(take(0u8), ChunkData::Unknown)(&chunk.data),
+        ChunkType::cHRM => map(parse_chrm_data, ChunkData::cHRM)(chunk.data),
+        ChunkType::gAMA => map(be_u32, |g| ChunkData::gAMA(Gamma(g)))(chunk.data),
+        ChunkType::iCCP => map(|d| parse_iccp_data(d, max_text_size), ChunkData::iCCP)(chunk.data),
+        ChunkType::sBIT => map(|d| parse_sbit_data(d, chunk.length), ChunkData::sBIT)(chunk.data),
+        ChunkType::sRGB => map(parse_srgb_data, ChunkData::sRGB)(chunk.data),
+        ChunkType::bKGD => map(|d| parse_bkgd_data(d, chunk.length), ChunkData::bKGD)(chunk.data),
+        ChunkType::hIST => map(parse_hist_data, ChunkData::hIST)(chunk.data),
+        ChunkType::tRNS => map(rest, ChunkData::Unknown)(chunk.data),
+        ChunkType::pHYs => map(parse_phys_data, ChunkData::pHYs)(chunk.data),
+        ChunkType::sPLT => map(parse_splt_data, ChunkData::sPLT)(chunk.data),
+        ChunkType::tIME => map(parse_time_data, ChunkData::tIME)(chunk.data),
+        ChunkType::iTXt => map(|d| parse_itxt_data(d, max_text_size), ChunkData::iTXt)(chunk.data),
+        ChunkType::tEXt => map(parse_text_data, ChunkData::tEXt)(chunk.data),
+        ChunkType::zTXt => map(|d| parse_ztxt_data(d, max_text_size), ChunkData::zTXt)(chunk.data),
+        // --- Extension chunks ---
+        ChunkType::eXIf => {
+            map_res(rest, |d: &[u8]| Exif::new(d.to_vec()).map(ChunkData::eXIf))(chunk.data)
+        }
+        ChunkType::cICP => map(parse_cicp_data, ChunkData::cICP)(chunk.data),
+        ChunkType::mDCv => map(parse_mdcv_data, ChunkData::mDCv)(chunk.data),
+        ChunkType::cLLi => map(parse_clli_data, ChunkData::cLLi)(chunk.data),
+        ChunkType::oFFs => map(parse_offs_data, ChunkData::oFFs)(chunk.data),
+        ChunkType::pCAL => map(parse_pcal_data, ChunkData::pCAL)(chunk.data),
+        ChunkType::sCAL => map(parse_scal_data, ChunkData::sCAL)(chunk.data),
+        ChunkType::sTER => map(parse_ster_data, ChunkData::sTER)(chunk.data),
+        ChunkType::gIFg => map(parse_gifg_data, ChunkData::gIFg)(chunk.data),
+        ChunkType::gIFx => map(parse_gifx_data, ChunkData::gIFx)(chunk.data),
+        // --- Animation chunks ---
+        ChunkType::acTL => map(parse_actl_data, ChunkData::acTL)(chunk.data),
+        ChunkType::fcTL => map(parse_fctl_data, ChunkData::fcTL)(chunk.data),
+        ChunkType::fdAT => map(parse_fdat_data, ChunkData::fdAT)(chunk.data),
+        ChunkType::Unknown(_) => map(rest, ChunkData::Unknown)(chunk.data),
     }
 }
 
@@ -175,6 +785,19 @@ pub fn parse_ihdr_data(input: &[u8]) -> IResult<&[u8], IHDRData> {
     ))
 }
 
+/// Parse and validate IHDR data.
+pub fn parse_valid_ihdr_data(input: &[u8]) -> Result<IHDRData, String> {
+    if input.len() != 13 {
+        return Err(format!(
+            "IHDR data must be 13 bytes long, not {}",
+            input.len()
+        ));
+    }
+    let (_, ihdr) = parse_ihdr_data(input).map_err(|e| format!("Invalid IHDR: {:?}", e))?;
+    ihdr.validate()?;
+    Ok(ihdr)
+}
+
 fn parse_sbit_data(input: &[u8], length: u32) -> IResult<&[u8], SignificantBits> {
     match length {
         1 => map(be_u8, SignificantBits::Gray)(input),
@@ -209,6 +832,407 @@ fn parse_bkgd_data(input: &[u8], length: u32) -> IResult<&[u8], Background> {
     }
 }
 
+/// The tRNS layout depends on the color type of the image,
+/// so it cannot be parsed by `parse_chunk_data` alone.
+pub fn parse_trns_data(input: &[u8], color_type: ColorType) -> IResult<&[u8], Transparency> {
+    match color_type {
+        ColorType::PLTE => map(rest, |t: &[u8]| Transparency::Palette(t.to_vec()))(input),
+        ColorType::Gray => map(be_u16, Transparency::Gray)(input),
+        ColorType::RGB => {
+            let (input, red) = be_u16(input)?;
+            let (input, green) = be_u16(input)?;
+            let (input, blue) = be_u16(input)?;
+            Ok((input, Transparency::RGB([red, green, blue])))
+        }
+        ColorType::GrayAlpha | ColorType::RGBA => map_res(take(0u8), |_| {
+            Err("tRNS is forbidden for color types with an alpha channel")
+        })(input),
+    }
+}
+
+/// Palette entries and transparency of an image, needed to convert its pixels.
+/// An invalid tRNS chunk is ignored, like in `png::decode`.
+pub(crate) fn palette_and_transparency<'a>(
+    chunks: &[Chunk<'a>],
+    color_type: ColorType,
+) -> (Vec<&'a [u8]>, Option<Transparency>) {
+    let find = |chunk_type| chunks.iter().find(|c| c.chunk_type == chunk_type);
+    let palette = find(ChunkType::PLTE)
+        .map(|c| c.data.chunks(3).collect())
+        .unwrap_or_default();
+    let transparency = find(ChunkType::tRNS)
+        .and_then(|c| parse_trns_data(c.data, color_type).ok())
+        .map(|(_, trns)| trns);
+    (palette, transparency)
+}
+
+fn parse_chrm_data(input: &[u8]) -> IResult<&[u8], Chromaticities> {
+    let (input, white_point) = tuple((be_u32, be_u32))(input)?;
+    let (input, red) = tuple((be_u32, be_u32))(input)?;
+    let (input, green) = tuple((be_u32, be_u32))(input)?;
+    let (input, blue) = tuple((be_u32, be_u32))(input)?;
+    Ok((
+        input,
+        Chromaticities {
+            white_point,
+            red,
+            green,
+            blue,
+        },
+    ))
+}
+
+fn parse_srgb_data(input: &[u8]) -> IResult<&[u8], RenderingIntent> {
+    map_res(be_u8, |n| match n {
+        0 => Ok(RenderingIntent::Perceptual),
+        1 => Ok(RenderingIntent::RelativeColorimetric),
+        2 => Ok(RenderingIntent::Saturation),
+        3 => Ok(RenderingIntent::AbsoluteColorimetric),
+        _ => Err("sRGB rendering intent can only be 0 to 3"),
+    })(input)
+}
+
+fn parse_cicp_data(input: &[u8]) -> IResult<&[u8], CodingIndependentCodePoints> {
+    let (input, color_primaries) = be_u8(input)?;
+    let (input, transfer_function) = be_u8(input)?;
+    let (input, matrix_coefficients) = map_res(be_u8, |m| match m {
+        0 => Ok(m),
+        _ => Err("cICP matrix coefficients must be 0 (RGB)"),
+    })(input)?;
+    let (input, video_full_range) = map_res(be_u8, |f| match f {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err("cICP video full range flag can only be 0 or 1"),
+    })(input)?;
+    Ok((
+        input,
+        CodingIndependentCodePoints {
+            color_primaries,
+            transfer_function,
+            matrix_coefficients,
+            video_full_range,
+        },
+    ))
+}
+
+fn parse_mdcv_data(input: &[u8]) -> IResult<&[u8], MasteringDisplayColorVolume> {
+    let (input, red) = tuple((be_u16, be_u16))(input)?;
+    let (input, green) = tuple((be_u16, be_u16))(input)?;
+    let (input, blue) = tuple((be_u16, be_u16))(input)?;
+    let (input, white_point) = tuple((be_u16, be_u16))(input)?;
+    let (input, max_luminance) = be_u32(input)?;
+    let (input, min_luminance) = be_u32(input)?;
+    Ok((
+        input,
+        MasteringDisplayColorVolume {
+            red,
+            green,
+            blue,
+            white_point,
+            max_luminance,
+            min_luminance,
+        },
+    ))
+}
+
+fn parse_clli_data(input: &[u8]) -> IResult<&[u8], ContentLightLevel> {
+    let (input, max_content_light_level) = be_u32(input)?;
+    let (input, max_frame_average_light_level) = be_u32(input)?;
+    Ok((
+        input,
+        ContentLightLevel {
+            max_content_light_level,
+            max_frame_average_light_level,
+        },
+    ))
+}
+
+fn parse_offs_data(input: &[u8]) -> IResult<&[u8], ImageOffset> {
+    let (input, x) = be_i32(input)?;
+    let (input, y) = be_i32(input)?;
+    let (input, unit) = map_res(be_u8, |n| match n {
+        0 => Ok(OffsetUnit::Pixel),
+        1 => Ok(OffsetUnit::Micrometer),
+        _ => Err("oFFs unit specifier can only be 0 or 1"),
+    })(input)?;
+    Ok((input, ImageOffset { x, y, unit }))
+}
+
+fn parse_pcal_data(input: &[u8]) -> IResult<&[u8], PixelCalibration> {
+    let (input, name) = latin1_till_null(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, x0) = be_i32(input)?;
+    let (input, x1) = map_res(be_i32, |x1| {
+        if x1 == x0 {
+            Err("pCAL original zero and maximum must be different")
+        } else {
+            Ok(x1)
+        }
+    })(input)?;
+    let (input, equation) = map_res(be_u8, |n| match n {
+        0 => Ok(CalibrationEquation::Linear),
+        1 => Ok(CalibrationEquation::BaseE),
+        2 => Ok(CalibrationEquation::ArbitraryBase),
+        3 => Ok(CalibrationEquation::HyperbolicSine),
+        _ => Err("pCAL equation type can only be 0 to 3"),
+    })(input)?;
+    let (input, nb_parameters) = map_res(be_u8, |n| {
+        if n as usize == equation.nb_parameters() {
+            Ok(n)
+        } else {
+            Err("pCAL number of parameters does not match the equation type")
+        }
+    })(input)?;
+    let (input, unit) = latin1_till_null(input)?;
+    let (input, (parameters, parameters_text)) = map_res(rest, |params: &[u8]| {
+        let texts: Vec<&[u8]> = params
+            .split(|&c| c == 0)
+            .skip(1) // null separator after the unit name
+            .collect();
+        if texts.len() != nb_parameters as usize {
+            return Err("pCAL parameters count does not match the equation type");
+        }
+        let parameters = texts
+            .iter()
+            .map(|t| parse_float(t))
+            .collect::<Result<Vec<f64>, _>>()?;
+        Ok((
+            parameters,
+            texts.iter().map(|t| latin1_to_string(t)).collect(),
+        ))
+    })(input)?;
+    Ok((
+        input,
+        PixelCalibration {
+            name,
+            x0,
+            x1,
+            equation,
+            unit,
+            parameters,
+            parameters_text,
+        },
+    ))
+}
+
+fn parse_scal_data(input: &[u8]) -> IResult<&[u8], PhysicalScale> {
+    let (input, unit) = map_res(be_u8, |n| match n {
+        1 => Ok(ScaleUnit::Meter),
+        2 => Ok(ScaleUnit::Radian),
+        _ => Err("sCAL unit specifier can only be 1 or 2"),
+    })(input)?;
+    let (input, (width, width_text)) = map_res(till_null, parse_float_text)(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, (height, height_text)) = map_res(rest, parse_float_text)(input)?;
+    Ok((
+        input,
+        PhysicalScale {
+            unit,
+            width,
+            height,
+            width_text,
+            height_text,
+        },
+    ))
+}
+
+fn parse_ster_data(input: &[u8]) -> IResult<&[u8], StereoMode> {
+    map_res(be_u8, |n| match n {
+        0 => Ok(StereoMode::CrossFuse),
+        1 => Ok(StereoMode::DivergingFuse),
+        _ => Err("sTER mode can only be 0 or 1"),
+    })(input)
+}
+
+fn parse_gifg_data(input: &[u8]) -> IResult<&[u8], GifGraphicControl> {
+    let (input, disposal_method) = be_u8(input)?;
+    let (input, user_input) = map(be_u8, |flag| flag != 0)(input)?;
+    let (input, delay_time) = be_u16(input)?;
+    Ok((
+        input,
+        GifGraphicControl {
+            disposal_method,
+            user_input,
+            delay_time,
+        },
+    ))
+}
+
+fn parse_gifx_data(input: &[u8]) -> IResult<&[u8], GifApplication> {
+    let (input, id) = take(8u8)(input)?;
+    let (input, code) = take(3u8)(input)?;
+    let (input, data) = rest(input)?;
+    let mut identifier = [0; 8];
+    identifier.copy_from_slice(id);
+    Ok((
+        input,
+        GifApplication {
+            identifier,
+            authentication_code: [code[0], code[1], code[2]],
+            data: data.to_vec(),
+        },
+    ))
+}
+
+fn parse_actl_data(input: &[u8]) -> IResult<&[u8], AnimationControl> {
+    let (input, num_frames) = map_res(be_u32, |n| match n {
+        0 => Err("acTL number of frames cannot be 0"),
+        n => Ok(n),
+    })(input)?;
+    let (input, num_plays) = be_u32(input)?;
+    Ok((
+        input,
+        AnimationControl {
+            num_frames,
+            num_plays,
+        },
+    ))
+}
+
+fn parse_fctl_data(input: &[u8]) -> IResult<&[u8], FrameControl> {
+    let (input, sequence_number) = be_u32(input)?;
+    let (input, width) = be_u32(input)?;
+    let (input, height) = be_u32(input)?;
+    let (input, x_offset) = be_u32(input)?;
+    let (input, y_offset) = be_u32(input)?;
+    let (input, delay_num) = be_u16(input)?;
+    let (input, delay_den) = be_u16(input)?;
+    let (input, dispose_op) = map_res(be_u8, |n| match n {
+        0 => Ok(DisposeOp::None),
+        1 => Ok(DisposeOp::Background),
+        2 => Ok(DisposeOp::Previous),
+        _ => Err("fcTL dispose operation can only be 0 to 2"),
+    })(input)?;
+    let (input, blend_op) = map_res(be_u8, |n| match n {
+        0 => Ok(BlendOp::Source),
+        1 => Ok(BlendOp::Over),
+        _ => Err("fcTL blend operation can only be 0 or 1"),
+    })(input)?;
+    Ok((
+        input,
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset,
+            y_offset,
+            delay_num,
+            delay_den,
+            dispose_op,
+            blend_op,
+        },
+    ))
+}
+
+fn parse_fdat_data(input: &[u8]) -> IResult<&[u8], FrameData<'_>> {
+    let (input, sequence_number) = be_u32(input)?;
+    let (input, data) = rest(input)?;
+    Ok((
+        input,
+        FrameData {
+            sequence_number,
+            data,
+        },
+    ))
+}
+
+// Floating-point numbers are stored as ASCII strings in pCAL and sCAL.
+fn parse_float(input: &[u8]) -> Result<f64, &'static str> {
+    std::str::from_utf8(input)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or("Invalid floating-point string")
+}
+
+fn parse_float_text(input: &[u8]) -> Result<(f64, String), &'static str> {
+    parse_float(input).map(|f| (f, latin1_to_string(input)))
+}
+
+fn parse_iccp_data(input: &[u8], max_len: usize) -> IResult<&[u8], IccProfile> {
+    let (input, name) = latin1_till_null(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, method) = be_u8(input)?;
+    let zlib_stream = Some(input.to_vec());
+    let (input, profile) = inflate_limited(input, max_len)?;
+    Ok((
+        input,
+        IccProfile {
+            name,
+            method,
+            profile,
+            zlib_stream,
+        },
+    ))
+}
+
+fn parse_splt_data(input: &[u8]) -> IResult<&[u8], SuggestedPalette> {
+    let (input, name) = latin1_till_null(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, sample_depth) = be_u8(input)?;
+    let entry_size = match sample_depth {
+        8 => 6,
+        16 => 10,
+        _ => {
+            return map_res(take(0u8), |_| Err("sPLT sample depth can only be 8 or 16"))(input);
+        }
+    };
+    if input.len() % entry_size != 0 {
+        return map_res(take(0u8), |_| {
+            Err("sPLT data length is not a multiple of the entry size")
+        })(input);
+    }
+    let (input, entries) = if sample_depth == 8 {
+        many0(parse_splt_entry_8)(input)?
+    } else {
+        many0(parse_splt_entry_16)(input)?
+    };
+    Ok((
+        input,
+        SuggestedPalette {
+            name,
+            sample_depth,
+            entries,
+        },
+    ))
+}
+
+fn parse_splt_entry_8(input: &[u8]) -> IResult<&[u8], SuggestedPaletteEntry> {
+    let (input, (red, green, blue, alpha)) = tuple((be_u8, be_u8, be_u8, be_u8))(input)?;
+    let (input, frequency) = be_u16(input)?;
+    Ok((
+        input,
+        SuggestedPaletteEntry {
+            red: red as u16,
+            green: green as u16,
+            blue: blue as u16,
+            alpha: alpha as u16,
+            frequency,
+        },
+    ))
+}
+
+fn parse_splt_entry_16(input: &[u8]) -> IResult<&[u8], SuggestedPaletteEntry> {
+    let (input, (red, green, blue, alpha)) = tuple((be_u16, be_u16, be_u16, be_u16))(input)?;
+    let (input, frequency) = be_u16(input)?;
+    Ok((
+        input,
+        SuggestedPaletteEntry {
+            red,
+            green,
+            blue,
+            alpha,
+            frequency,
+        },
+    ))
+}
+
+fn parse_hist_data(input: &[u8]) -> IResult<&[u8], Vec<u16>> {
+    if input.len() % 2 != 0 {
+        return map_res(take(0u8), |_| Err("hIST data length must be even"))(input);
+    }
+    many0(be_u16)(input)
+}
+
 fn parse_phys_data(input: &[u8]) -> IResult<&[u8], PhysicalPixelDimension> {
     let (input, x) = be_u32(input)?;
     let (input, y) = be_u32(input)?;
@@ -221,90 +1245,190 @@ fn parse_phys_data(input: &[u8]) -> IResult<&[u8], PhysicalPixelDimension> {
 }
 
 fn parse_text_data(input: &[u8]) -> IResult<&[u8], Text> {
-    let (input, keyword) = map(str_till_null, String::from)(input)?;
+    let (input, keyword) = latin1_till_null(input)?;
     let (input, _) = take(1_u8)(input)?;
-    let (input, text) = map(str_till_null, String::from)(input)?;
+    let (input, text) = map(till_null, latin1_to_string)(input)?;
     Ok((input, Text { keyword, text }))
 }
 
-fn parse_ztxt_data(input: &[u8]) -> IResult<&[u8], CompressedText> {
-    let (input, keyword) = map(str_till_null, String::from)(input)?;
+fn parse_ztxt_data(input: &[u8], max_len: usize) -> IResult<&[u8], CompressedText> {
+    let (input, keyword) = latin1_till_null(input)?;
     let (input, _) = take(1_u8)(input)?;
     let (input, method) = be_u8(input)?;
-    let (input, text) = map_res(
-        map_res(rest, miniz_oxide::inflate::decompress_to_vec_zlib),
-        String::from_utf8,
-    )(input)?;
+    let zlib_stream = Some(input.to_vec());
+    let (input, text) = map(|i| inflate_limited(i, max_len), |t| latin1_to_string(&t))(input)?;
     Ok((
         input,
         CompressedText {
             keyword,
             method,
             text,
+            zlib_stream,
         },
     ))
 }
 
-fn str_till_null(input: &[u8]) -> IResult<&[u8], &str> {
-    map_res(till_null, std::str::from_utf8)(input)
-}
-
-fn till_null(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    take_till(|c| c == 0)(input)
+fn parse_itxt_data(input: &[u8], max_len: usize) -> IResult<&[u8], InternationalText> {
+    let (input, keyword) = latin1_till_null(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, compressed) = map(be_u8, |flag| flag != 0)(input)?;
+    let (input, method) = be_u8(input)?;
+    let (input, language_tag) = map(str_till_null, String::from)(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let (input, translated_keyword) = map(str_till_null, String::from)(input)?;
+    let (input, _) = take(1_u8)(input)?;
+    let zlib_stream = if compressed {
+        Some(input.to_vec())
+    } else {
+        None
+    };
+    let (input, text) = if compressed {
+        map_res(|i| inflate_limited(i, max_len), String::from_utf8)(input)?
+    } else {
+        map(map_res(rest, std::str::from_utf8), String::from)(input)?
+    };
+    Ok((
+        input,
+        InternationalText {
+            keyword,
+            compressed,
+            method,
+            language_tag,
+            translated_keyword,
+            text,
+            zlib_stream,
+        },
+    ))
 }
 
-pub fn inflate_idats(idats: &[&Chunk], len: usize) -> Result<Vec<u8>, String> {
+// Inflate a zlib stream with at most max_len output bytes.
+fn inflate_limited(input: &[u8], max_len: usize) -> IResult<&[u8], Vec<u8>> {
     let flags = TINFL_FLAG_PARSE_ZLIB_HEADER | TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
     let mut decomp = inflate::core::DecompressorOxide::new();
     decomp.init();
-    let mut ret: Vec<u8> = vec![0; len];
-
-    let nb_chunk = idats.len();
+    let mut ret: Vec<u8> = vec![0; std::cmp::min(max_len, 2 * input.len())];
+    let mut in_pos = 0;
     let mut out_pos = 0;
-    for (id, chunk) in idats.iter().enumerate() {
-        let mut in_pos = 0;
-        let input = chunk.data;
-        let chunk_flags = if id == nb_chunk - 1 {
-            flags
-        } else {
-            flags | TINFL_FLAG_HAS_MORE_INPUT
+    loop {
+        let (status, in_consumed, out_consumed) = {
+            let mut c = Cursor::new(ret.as_mut_slice());
+            c.set_position(out_pos as u64);
+            inflate::core::decompress(&mut decomp, &input[in_pos..], &mut c, flags)
         };
-        loop {
-            let (status, in_consumed, out_consumed) = {
-                // Wrap the whole output slice so we know we have enough of the
-                // decompressed data for matches.
-                let mut c = Cursor::new(ret.as_mut_slice());
-                c.set_position(out_pos as u64);
-                inflate::core::decompress(&mut decomp, &input[in_pos..], &mut c, chunk_flags)
-            };
-            in_pos += in_consumed;
-            out_pos += out_consumed;
-
-            match status {
-                inflate::TINFLStatus::Done => {
-                    ret.truncate(out_pos);
-                    return Ok(ret);
-                }
+        in_pos += in_consumed;
+        out_pos += out_consumed;
+        match status {
+            inflate::TINFLStatus::Done => {
+                ret.truncate(out_pos);
+                return Ok((&input[input.len()..], ret));
+            }
+            inflate::TINFLStatus::HasMoreOutput if ret.len() < max_len => {
+                let new_len = std::cmp::min(max_len, std::cmp::max(1, 2 * ret.len()));
+                ret.resize(new_len, 0);
+            }
+            inflate::TINFLStatus::HasMoreOutput => {
+                return Err(nom::Err::Failure((input, ErrorKind::TooLarge)));
+            }
+            _ => return Err(nom::Err::Error((input, ErrorKind::MapRes))),
+        }
+    }
+}
 
-                inflate::TINFLStatus::HasMoreOutput => {
-                    // We need more space so extend the buffer.
-                    ret.extend(&vec![0; out_pos]);
-                }
+fn str_till_null(input: &[u8]) -> IResult<&[u8], &str> {
+    map_res(till_null, std::str::from_utf8)(input)
+}
 
-                inflate::TINFLStatus::NeedsMoreInput => {
-                    // normal if we are not at the last chunk.
-                    if id == nb_chunk - 1 {
-                        return Err(format!("{:?}", status));
-                    } else {
-                        break;
-                    }
-                }
+// Keywords, names and the text of tEXt and zTXt chunks are Latin-1.
+fn latin1_till_null(input: &[u8]) -> IResult<&[u8], String> {
+    map(till_null, latin1_to_string)(input)
+}
 
-                _ => return Err(format!("{:?}", status)),
-            }
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn till_null(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    take_till(|c| c == 0)(input)
+}
+
+/// Inflate the image data, expected to be `len` bytes long.
+/// More output is accepted until `max_len` bytes.
+pub fn inflate_idats(
+    idats: &[&Chunk],
+    len: usize,
+    max_len: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let inflated = inflate_idats_full(idats, len, max_len, true)?;
+    if inflated.truncated {
+        Err("The zlib stream of the image data is truncated".into())
+    } else {
+        Ok(inflated.data)
+    }
+}
+
+/// Inflate the image data like `inflate_idats`,
+/// keeping what was inflated of a truncated zlib stream.
+/// The Adler-32 checksum is skipped if `verify_adler32` is false.
+pub fn inflate_idats_full(
+    idats: &[&Chunk],
+    len: usize,
+    max_len: usize,
+    verify_adler32: bool,
+) -> Result<InflatedIdats, Box<dyn Error>> {
+    inflate_idats_with(&MinizInflater, idats, len, max_len, verify_adler32)
+}
+
+/// Inflate the image data like `inflate_idats_full` with the given backend.
+pub fn inflate_idats_with(
+    inflater: &dyn Inflater,
+    idats: &[&Chunk],
+    len: usize,
+    max_len: usize,
+    verify_adler32: bool,
+) -> Result<InflatedIdats, Box<dyn Error>> {
+    let mut header_bytes = idats.iter().flat_map(|c| c.data.iter());
+    let zlib_header = match (header_bytes.next(), header_bytes.next()) {
+        (Some(cmf), Some(flg)) => ZlibHeader::parse(*cmf, *flg)?,
+        _ => {
+            return Ok(InflatedIdats {
+                data: Vec::new(),
+                truncated: true,
+                trailing_bytes: 0,
+                zlib_header: None,
+                adler32_verified: false,
+            })
         }
+    };
+    if zlib_header.preset_dictionary {
+        return Err("The zlib stream of the image data uses a preset dictionary".into());
     }
-    Ok(ret)
+    let input: Vec<&[u8]> = idats.iter().map(|c| c.data).collect();
+    let inflated = inflater.inflate(&input, len, max_len, verify_adler32)?;
+    Ok(InflatedIdats {
+        data: inflated.data,
+        truncated: inflated.truncated,
+        trailing_bytes: inflated.trailing_bytes,
+        zlib_header: Some(zlib_header),
+        adler32_verified: verify_adler32 && !inflated.truncated,
+    })
+}
+
+/// Inflate the raw deflate stream of the image data of an Apple CgBI file,
+/// which has no zlib header nor Adler-32 checksum.
+pub fn inflate_raw_idats(
+    idats: &[&Chunk],
+    len: usize,
+    max_len: usize,
+) -> Result<InflatedIdats, Box<dyn Error>> {
+    let input: Vec<&[u8]> = idats.iter().map(|c| c.data).collect();
+    let inflated = RawDeflateInflater.inflate(&input, len, max_len, false)?;
+    Ok(InflatedIdats {
+        data: inflated.data,
+        truncated: inflated.truncated,
+        trailing_bytes: inflated.trailing_bytes,
+        zlib_header: None,
+        adler32_verified: false,
+    })
 }
 
 fn parse_time_data(input: &[u8]) -> IResult<&[u8], LastModificationTime> {
@@ -326,3 +1450,278 @@ fn parse_time_data(input: &[u8]) -> IResult<&[u8], LastModificationTime> {
         },
     ))
 }
+
+// SERIALIZATION ###############################################################
+
+impl ChunkData<'_> {
+    /// Type of the chunk holding this data, `None` if unknown.
+    pub fn chunk_type(&self) -> Option<ChunkType> {
+        let chunk_type = match self {
+            ChunkData::IHDR(_) => ChunkType::IHDR,
+            ChunkData::IEND => ChunkType::IEND,
+            ChunkData::gAMA(_) => ChunkType::gAMA,
+            ChunkData::cHRM(_) => ChunkType::cHRM,
+            ChunkData::sRGB(_) => ChunkType::sRGB,
+            ChunkData::iCCP(_) => ChunkType::iCCP,
+            ChunkData::tEXt(_) => ChunkType::tEXt,
+            ChunkData::zTXt(_) => ChunkType::zTXt,
+            ChunkData::iTXt(_) => ChunkType::iTXt,
+            ChunkData::bKGD(_) => ChunkType::bKGD,
+            ChunkData::pHYs(_) => ChunkType::pHYs,
+            ChunkData::sBIT(_) => ChunkType::sBIT,
+            ChunkData::sPLT(_) => ChunkType::sPLT,
+            ChunkData::hIST(_) => ChunkType::hIST,
+            ChunkData::tIME(_) => ChunkType::tIME,
+            ChunkData::eXIf(_) => ChunkType::eXIf,
+            ChunkData::cICP(_) => ChunkType::cICP,
+            ChunkData::mDCv(_) => ChunkType::mDCv,
+            ChunkData::cLLi(_) => ChunkType::cLLi,
+            ChunkData::oFFs(_) => ChunkType::oFFs,
+            ChunkData::pCAL(_) => ChunkType::pCAL,
+            ChunkData::sCAL(_) => ChunkType::sCAL,
+            ChunkData::sTER(_) => ChunkType::sTER,
+            ChunkData::gIFg(_) => ChunkType::gIFg,
+            ChunkData::gIFx(_) => ChunkType::gIFx,
+            ChunkData::acTL(_) => ChunkType::acTL,
+            ChunkData::fcTL(_) => ChunkType::fcTL,
+            ChunkData::fdAT(_) => ChunkType::fdAT,
+            ChunkData::Unknown(_) => return None,
+        };
+        Some(chunk_type)
+    }
+
+    /// Serialize the data, the inverse of `parse_chunk_data`.
+    /// The output is identical to the parsed data.
+    /// Edited compressed content (iCCP, zTXt and compressed iTXt) is recompressed,
+    /// and edited pCAL and sCAL numbers are written in their shortest decimal form.
+    /// Latin-1 strings (keywords, names, units, tEXt and zTXt texts) cannot
+    /// hold other characters, these are written as '?'.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+
+    /// Append the serialized data to `out`, see `to_bytes`.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            ChunkData::IHDR(ihdr) => {
+                out.extend_from_slice(&ihdr.width.to_be_bytes());
+                out.extend_from_slice(&ihdr.height.to_be_bytes());
+                out.push(ihdr.bit_depth);
+                out.push(ihdr.color_type.into());
+                out.push(ihdr.compression_method);
+                out.push(ihdr.filter_method);
+                out.push(ihdr.interlace_method);
+            }
+            ChunkData::IEND => {}
+            ChunkData::gAMA(Gamma(gamma)) => out.extend_from_slice(&gamma.to_be_bytes()),
+            ChunkData::cHRM(chrm) => {
+                for (x, y) in [chrm.white_point, chrm.red, chrm.green, chrm.blue].iter() {
+                    out.extend_from_slice(&x.to_be_bytes());
+                    out.extend_from_slice(&y.to_be_bytes());
+                }
+            }
+            ChunkData::sRGB(intent) => out.push(*intent as u8),
+            ChunkData::iCCP(icc) => {
+                write_latin1(out, &icc.name);
+                out.push(0);
+                out.push(icc.method);
+                write_zlib(out, &icc.profile, icc.zlib_stream.as_deref());
+            }
+            ChunkData::tEXt(text) => {
+                write_latin1(out, &text.keyword);
+                out.push(0);
+                write_latin1(out, &text.text);
+            }
+            ChunkData::zTXt(ztxt) => {
+                write_latin1(out, &ztxt.keyword);
+                out.push(0);
+                out.push(ztxt.method);
+                let mut text = Vec::with_capacity(ztxt.text.len());
+                write_latin1(&mut text, &ztxt.text);
+                write_zlib(out, &text, ztxt.zlib_stream.as_deref());
+            }
+            ChunkData::iTXt(itxt) => {
+                write_latin1(out, &itxt.keyword);
+                out.push(0);
+                out.push(itxt.compressed as u8);
+                out.push(itxt.method);
+                write_null_terminated(out, &itxt.language_tag);
+                write_null_terminated(out, &itxt.translated_keyword);
+                if itxt.compressed {
+                    write_zlib(out, itxt.text.as_bytes(), itxt.zlib_stream.as_deref());
+                } else {
+                    out.extend_from_slice(itxt.text.as_bytes());
+                }
+            }
+            ChunkData::bKGD(Background::Palette(index)) => out.push(*index),
+            ChunkData::bKGD(Background::Gray(gray)) => out.extend_from_slice(&gray.to_be_bytes()),
+            ChunkData::bKGD(Background::RGB(rgb)) => write_u16s(out, rgb),
+            ChunkData::pHYs(phys) => {
+                out.extend_from_slice(&phys.x.to_be_bytes());
+                out.extend_from_slice(&phys.y.to_be_bytes());
+                out.push(phys.unit as u8);
+            }
+            ChunkData::sBIT(SignificantBits::Gray(bits)) => out.push(*bits),
+            ChunkData::sBIT(SignificantBits::GrayAlpha(bits)) => out.extend_from_slice(bits),
+            ChunkData::sBIT(SignificantBits::RGB(bits)) => out.extend_from_slice(bits),
+            ChunkData::sBIT(SignificantBits::RGBA(bits)) => out.extend_from_slice(bits),
+            ChunkData::sPLT(splt) => {
+                write_latin1(out, &splt.name);
+                out.push(0);
+                out.push(splt.sample_depth);
+                for e in splt.entries.iter() {
+                    let samples = [e.red, e.green, e.blue, e.alpha];
+                    if splt.sample_depth == 8 {
+                        out.extend(samples.iter().map(|s| *s as u8));
+                    } else {
+                        write_u16s(out, &samples);
+                    }
+                    out.extend_from_slice(&e.frequency.to_be_bytes());
+                }
+            }
+            ChunkData::hIST(frequencies) => write_u16s(out, frequencies),
+            ChunkData::tIME(time) => {
+                out.extend_from_slice(&time.year.to_be_bytes());
+                out.extend_from_slice(&[time.month, time.day, time.hour, time.minute, time.second]);
+            }
+            ChunkData::eXIf(Exif(exif)) => out.extend_from_slice(exif),
+            ChunkData::cICP(cicp) => out.extend_from_slice(&[
+                cicp.color_primaries,
+                cicp.transfer_function,
+                cicp.matrix_coefficients,
+                cicp.video_full_range as u8,
+            ]),
+            ChunkData::mDCv(mdcv) => {
+                let (rx, ry) = mdcv.red;
+                let (gx, gy) = mdcv.green;
+                let (bx, by) = mdcv.blue;
+                let (wx, wy) = mdcv.white_point;
+                write_u16s(out, &[rx, ry, gx, gy, bx, by, wx, wy]);
+                out.extend_from_slice(&mdcv.max_luminance.to_be_bytes());
+                out.extend_from_slice(&mdcv.min_luminance.to_be_bytes());
+            }
+            ChunkData::cLLi(clli) => {
+                out.extend_from_slice(&clli.max_content_light_level.to_be_bytes());
+                out.extend_from_slice(&clli.max_frame_average_light_level.to_be_bytes());
+            }
+            ChunkData::oFFs(offs) => {
+                out.extend_from_slice(&offs.x.to_be_bytes());
+                out.extend_from_slice(&offs.y.to_be_bytes());
+                out.push(offs.unit as u8);
+            }
+            ChunkData::pCAL(pcal) => {
+                write_latin1(out, &pcal.name);
+                out.push(0);
+                out.extend_from_slice(&pcal.x0.to_be_bytes());
+                out.extend_from_slice(&pcal.x1.to_be_bytes());
+                out.push(pcal.equation as u8);
+                out.push(pcal.parameters.len() as u8);
+                write_latin1(out, &pcal.unit);
+                for (i, p) in pcal.parameters.iter().enumerate() {
+                    out.push(0);
+                    write_float(out, *p, pcal.parameters_text.get(i));
+                }
+            }
+            ChunkData::sCAL(scal) => {
+                out.push(scal.unit as u8 + 1);
+                write_float(out, scal.width, Some(&scal.width_text));
+                out.push(0);
+                write_float(out, scal.height, Some(&scal.height_text));
+            }
+            ChunkData::sTER(mode) => out.push(*mode as u8),
+            ChunkData::gIFg(gifg) => {
+                out.push(gifg.disposal_method);
+                out.push(gifg.user_input as u8);
+                out.extend_from_slice(&gifg.delay_time.to_be_bytes());
+            }
+            ChunkData::gIFx(gifx) => {
+                out.extend_from_slice(&gifx.identifier);
+                out.extend_from_slice(&gifx.authentication_code);
+                out.extend_from_slice(&gifx.data);
+            }
+            ChunkData::acTL(actl) => {
+                out.extend_from_slice(&actl.num_frames.to_be_bytes());
+                out.extend_from_slice(&actl.num_plays.to_be_bytes());
+            }
+            ChunkData::fcTL(fctl) => {
+                out.extend_from_slice(&fctl.sequence_number.to_be_bytes());
+                out.extend_from_slice(&fctl.width.to_be_bytes());
+                out.extend_from_slice(&fctl.height.to_be_bytes());
+                out.extend_from_slice(&fctl.x_offset.to_be_bytes());
+                out.extend_from_slice(&fctl.y_offset.to_be_bytes());
+                write_u16s(out, &[fctl.delay_num, fctl.delay_den]);
+                out.push(fctl.dispose_op as u8);
+                out.push(fctl.blend_op as u8);
+            }
+            ChunkData::fdAT(fdat) => {
+                out.extend_from_slice(&fdat.sequence_number.to_be_bytes());
+                out.extend_from_slice(fdat.data);
+            }
+            ChunkData::Unknown(data) => out.extend_from_slice(data),
+        }
+    }
+}
+
+impl Transparency {
+    /// Serialize the tRNS data, the inverse of `parse_trns_data`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Transparency::Palette(alphas) => alphas.clone(),
+            Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::RGB(rgb) => {
+                let mut out = Vec::with_capacity(6);
+                write_u16s(&mut out, rgb);
+                out
+            }
+        }
+    }
+}
+
+// zlib level of recompressed chunks.
+const COMPRESSION_LEVEL: u8 = 9;
+
+// Write the original stream if it still inflates to `content`, recompress otherwise.
+fn write_zlib(out: &mut Vec<u8>, content: &[u8], original: Option<&[u8]>) {
+    match original {
+        Some(stream) if inflates_to(stream, content) => out.extend_from_slice(stream),
+        _ => out.extend(compress_to_vec_zlib(content, COMPRESSION_LEVEL)),
+    }
+}
+
+fn inflates_to(stream: &[u8], content: &[u8]) -> bool {
+    match inflate_limited(stream, content.len()) {
+        Ok((_, inflated)) => inflated == content,
+        Err(_) => false,
+    }
+}
+
+// Write the original text if it still has the same value, the shortest form otherwise.
+fn write_float(out: &mut Vec<u8>, value: f64, original: Option<&String>) {
+    match original {
+        Some(text) if parse_float(text.as_bytes()).map(f64::to_bits) == Ok(value.to_bits()) => {
+            out.extend_from_slice(text.as_bytes())
+        }
+        _ => out.extend_from_slice(value.to_string().as_bytes()),
+    }
+}
+
+fn write_null_terminated(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+}
+
+// Characters outside of Latin-1 cannot be stored and are written as '?'.
+fn write_latin1(out: &mut Vec<u8>, s: &str) {
+    out.extend(
+        s.chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')),
+    );
+}
+
+fn write_u16s(out: &mut Vec<u8>, values: &[u16]) {
+    for v in values.iter() {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+}
@@ -1,6 +1,6 @@
 use std::convert::TryFrom;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ColorType {
     Gray,
     RGB,
@@ -23,3 +23,14 @@ impl TryFrom<u8> for ColorType {
     }
 }
 
+impl From<ColorType> for u8 {
+    fn from(color_type: ColorType) -> Self {
+        match color_type {
+            ColorType::Gray => 0,
+            ColorType::RGB => 2,
+            ColorType::PLTE => 3,
+            ColorType::GrayAlpha => 4,
+            ColorType::RGBA => 6,
+        }
+    }
+}
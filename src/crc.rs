@@ -0,0 +1,29 @@
+use lazy_static::lazy_static;
+
+// FUNCTIONS ###################################################################
+
+lazy_static! {
+    // CRC of every byte value, for the polynomial 0xEDB88320.
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        table
+    };
+}
+
+/// CRC of a chunk, computed on its type and data.
+pub fn chunk_crc(chunk_type: [u8; 4], data: &[u8]) -> u32 {
+    !update(update(0xFFFF_FFFF, &chunk_type), data)
+}
+
+fn update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |c, b| {
+        CRC_TABLE[((c ^ u32::from(*b)) & 0xFF) as usize] ^ (c >> 8)
+    })
+}
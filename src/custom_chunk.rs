@@ -0,0 +1,127 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+// Internal imports
+use crate::chunk::ChunkType;
+
+// TYPES #######################################################################
+
+/// Ordering constraint of a custom chunk, similar to the ones of the spec.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkOrdering {
+    BeforePlte, // Before PLTE and IDAT
+    AfterPlteBeforeIdat,
+    BeforeIdat,
+    AfterIdat,
+    Anywhere,
+}
+
+type ParseFn = dyn Fn(&[u8]) -> Result<Arc<dyn Any + Send + Sync>, String> + Send + Sync;
+
+#[derive(Clone)]
+pub struct ChunkHandler {
+    pub ordering: ChunkOrdering,
+    pub multiple: bool,
+    parse: Arc<ParseFn>,
+}
+
+/// Handlers of chunk types unknown to this crate, keyed by chunk type.
+#[derive(Clone, Default)]
+pub struct ChunkHandlers {
+    handlers: HashMap<ChunkType, ChunkHandler>,
+}
+
+/// Value produced by a chunk handler.
+#[derive(Clone)]
+pub struct CustomChunkData {
+    pub chunk_type: ChunkType,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+// FUNCTIONS ###################################################################
+
+impl ChunkHandler {
+    pub fn parse(&self, data: &[u8]) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        (self.parse)(data)
+    }
+}
+
+impl ChunkHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a parser for a chunk type unknown to this crate.
+    /// Chunk types known to this crate or not made of ASCII letters are rejected.
+    /// Registering a chunk type twice replaces the previous handler.
+    pub fn register<T, F>(
+        &mut self,
+        chunk_type: ChunkType,
+        ordering: ChunkOrdering,
+        multiple: bool,
+        parse: F,
+    ) -> Result<(), String>
+    where
+        T: Any + Send + Sync,
+        F: Fn(&[u8]) -> Result<T, String> + Send + Sync + 'static,
+    {
+        // `Unknown(*b"IHDR")` would never match a parsed chunk, which is `IHDR`.
+        let chunk_type = ChunkType::try_from(chunk_type.name())?;
+        if let ChunkType::Unknown(_) = chunk_type {
+            let parse =
+                move |data: &[u8]| parse(data).map(|v| Arc::new(v) as Arc<dyn Any + Send + Sync>);
+            let handler = ChunkHandler {
+                ordering,
+                multiple,
+                parse: Arc::new(parse),
+            };
+            self.handlers.insert(chunk_type, handler);
+            Ok(())
+        } else {
+            Err(format!("{} chunks are already handled", chunk_type))
+        }
+    }
+
+    pub fn get(&self, chunk_type: &ChunkType) -> Option<&ChunkHandler> {
+        self.handlers.get(chunk_type)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+impl CustomChunkData {
+    pub fn new(chunk_type: ChunkType, value: Arc<dyn Any + Send + Sync>) -> Self {
+        CustomChunkData { chunk_type, value }
+    }
+
+    /// The parsed value, if it has type `T`.
+    pub fn value<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl std::fmt::Debug for ChunkHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{ordering: {:?}, multiple: {}}}",
+            self.ordering, self.multiple
+        )
+    }
+}
+
+impl std::fmt::Debug for ChunkHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.handlers.iter()).finish()
+    }
+}
+
+impl std::fmt::Debug for CustomChunkData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{type: {}}}", self.chunk_type)
+    }
+}
@@ -0,0 +1,197 @@
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use std::convert::TryFrom;
+use std::error::Error;
+
+// Internal imports
+use crate::chunk::{self, ChunkType};
+use crate::chunk_data::{ChunkData, IHDRData, Transparency};
+use crate::color::ColorType;
+use crate::crc;
+use crate::filter::{self, FilterStrategy};
+use crate::png::SIGNATURE;
+
+// TYPES #######################################################################
+
+/// Options of `encode`.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Palette entries, required for palette images.
+    pub palette: Option<Vec<[u8; 3]>>,
+    pub transparency: Option<Transparency>,
+    /// zlib compression level, from 0 (none) to 10 (best).
+    pub compression_level: u8,
+    /// Maximum size of the data of each IDAT chunk.
+    pub idat_size: usize,
+    pub filter_strategy: FilterStrategy,
+    /// Ancillary chunks as type and data, written in order before IDAT.
+    /// Chunks that must precede PLTE come right after IHDR,
+    /// the others after PLTE and tRNS.
+    pub ancillary_chunks: Vec<([u8; 4], Vec<u8>)>,
+}
+
+// FUNCTIONS ###################################################################
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            palette: None,
+            transparency: None,
+            compression_level: 6,
+            idat_size: 8192,
+            filter_strategy: FilterStrategy::MinSumAbsDiff,
+            ancillary_chunks: Vec::new(),
+        }
+    }
+}
+
+/// Encode the rows of pixels in `data` into a PNG file.
+/// Pixels are packed at the IHDR bit depth, each row starting on a new byte,
+/// and 16 bits samples are big endian.
+pub fn encode(
+    ihdr: &IHDRData,
+    data: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    ihdr.validate()?;
+    if ihdr.interlace_method != 0 {
+        return Err("Encoding interlaced images is not supported".into());
+    }
+    let row_len = row_len(ihdr)?;
+    let expected_len = row_len
+        .checked_mul(ihdr.height as usize)
+        .ok_or("Image data size overflows")?;
+    if data.len() != expected_len {
+        return Err(format!(
+            "Image data is {} bytes instead of {}",
+            data.len(),
+            expected_len
+        )
+        .into());
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, *b"IHDR", &ChunkData::IHDR(*ihdr).to_bytes());
+    let (before_plte, after_plte): (Vec<_>, Vec<_>) =
+        options
+            .ancillary_chunks
+            .iter()
+            .partition(|(chunk_type, _)| {
+                ChunkType::try_from(*chunk_type).is_ok_and(chunk::precedes_plte)
+            });
+    for (chunk_type, data) in before_plte {
+        write_chunk(&mut png, *chunk_type, data);
+    }
+    match (&options.palette, ihdr.color_type) {
+        (None, ColorType::PLTE) => return Err("Palette images require a palette".into()),
+        (Some(_), ColorType::Gray) | (Some(_), ColorType::GrayAlpha) => {
+            return Err("Gray images cannot have a palette".into())
+        }
+        (Some(palette), _) => {
+            check_palette(palette, ihdr)?;
+            let plte: Vec<u8> = palette.iter().flatten().cloned().collect();
+            write_chunk(&mut png, *b"PLTE", &plte);
+        }
+        (None, _) => {}
+    }
+    if let Some(transparency) = &options.transparency {
+        let trns = trns_bytes(transparency, ihdr, options.palette.as_ref())?;
+        write_chunk(&mut png, *b"tRNS", &trns);
+    }
+    for (chunk_type, data) in after_plte {
+        write_chunk(&mut png, *chunk_type, data);
+    }
+    let compressed = compress_rows(
+        ihdr,
+        data,
+        options.compression_level,
+        options.filter_strategy,
+    )?;
+    for idat in compressed.chunks(std::cmp::max(1, options.idat_size)) {
+        write_chunk(&mut png, *b"IDAT", idat);
+    }
+    write_chunk(&mut png, *b"IEND", &[]);
+    Ok(png)
+}
+
+/// Append a chunk with its length and CRC.
+pub fn write_chunk(out: &mut Vec<u8>, chunk_type: [u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&chunk_type);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc::chunk_crc(chunk_type, data).to_be_bytes());
+}
+
+// Helpers #####################################################################
+
+/// Size in bytes of a row of pixels, without the filter byte.
+pub(crate) fn row_len(ihdr: &IHDRData) -> Result<usize, String> {
+    Ok(ihdr.scanline_width()? - 1)
+}
+
+/// Filter and compress the rows of pixels of an image.
+pub(crate) fn compress_rows(
+    ihdr: &IHDRData,
+    data: &[u8],
+    level: u8,
+    strategy: FilterStrategy,
+) -> Result<Vec<u8>, String> {
+    let row_len = row_len(ihdr)?;
+    let bpp = ihdr.bytes_per_pixel();
+    let filtered = filter::filter(bpp, row_len, data, strategy)?;
+    Ok(compress_to_vec_zlib(&filtered, level))
+}
+
+fn check_palette(palette: &[[u8; 3]], ihdr: &IHDRData) -> Result<(), String> {
+    let max_len = match ihdr.color_type {
+        ColorType::PLTE => 1 << ihdr.bit_depth,
+        _ => 256,
+    };
+    if palette.is_empty() || palette.len() > max_len {
+        Err(format!(
+            "Palette has {} entries instead of 1 to {}",
+            palette.len(),
+            max_len
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn trns_bytes(
+    transparency: &Transparency,
+    ihdr: &IHDRData,
+    palette: Option<&Vec<[u8; 3]>>,
+) -> Result<Vec<u8>, String> {
+    let max_sample = (1u32 << ihdr.bit_depth) - 1;
+    let check_sample = |s: &u16| {
+        if u32::from(*s) > max_sample {
+            Err(format!(
+                "tRNS sample {} does not fit in {} bits",
+                s, ihdr.bit_depth
+            ))
+        } else {
+            Ok(())
+        }
+    };
+    match (transparency, ihdr.color_type) {
+        (Transparency::Palette(alphas), ColorType::PLTE) => {
+            let palette_len = palette.map_or(0, |p| p.len());
+            if alphas.len() > palette_len {
+                return Err(format!(
+                    "tRNS has {} entries but the palette only {}",
+                    alphas.len(),
+                    palette_len
+                ));
+            }
+        }
+        (Transparency::Gray(gray), ColorType::Gray) => check_sample(gray)?,
+        (Transparency::RGB(rgb), ColorType::RGB) => rgb.iter().try_for_each(check_sample)?,
+        (_, color_type) => {
+            return Err(format!(
+                "tRNS {:?} does not match color type {:?}",
+                transparency, color_type
+            ))
+        }
+    }
+    Ok(transparency.to_bytes())
+}
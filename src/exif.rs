@@ -0,0 +1,133 @@
+use std::convert::TryFrom;
+
+// TYPES #######################################################################
+
+/// Raw EXIF data of an eXIf chunk, starting with the TIFF header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exif(pub Vec<u8>);
+
+/// EXIF orientation tag (0x0112) values.
+/// Each variant is named after the transformation to apply
+/// to the stored pixels to display them correctly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Orientation {
+    Normal,         // 1: top-left
+    FlipHorizontal, // 2: top-right
+    Rotate180,      // 3: bottom-right
+    FlipVertical,   // 4: bottom-left
+    Transpose,      // 5: left-top
+    Rotate90,       // 6: right-top
+    Transverse,     // 7: right-bottom
+    Rotate270,      // 8: left-bottom
+}
+
+#[derive(Copy, Clone)]
+enum Endianness {
+    Little,
+    Big,
+}
+
+const ORIENTATION_TAG: u16 = 0x0112;
+const SHORT_TYPE: u16 = 3;
+
+// FUNCTIONS ###################################################################
+
+impl TryFrom<u16> for Orientation {
+    type Error = String;
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Orientation::Normal),
+            2 => Ok(Orientation::FlipHorizontal),
+            3 => Ok(Orientation::Rotate180),
+            4 => Ok(Orientation::FlipVertical),
+            5 => Ok(Orientation::Transpose),
+            6 => Ok(Orientation::Rotate90),
+            7 => Ok(Orientation::Transverse),
+            8 => Ok(Orientation::Rotate270),
+            _ => Err(format!("EXIF orientation {} is not valid", value)),
+        }
+    }
+}
+
+impl Orientation {
+    /// Whether width and height are exchanged by the transformation.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            Orientation::Transpose
+                | Orientation::Rotate90
+                | Orientation::Transverse
+                | Orientation::Rotate270
+        )
+    }
+
+    /// Position (x, y) of the stored pixel (x, y)
+    /// in the transformed image of an image of size (width, height).
+    pub fn transform(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::FlipHorizontal => (width - 1 - x, y),
+            Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+            Orientation::FlipVertical => (x, height - 1 - y),
+            Orientation::Transpose => (y, x),
+            Orientation::Rotate90 => (height - 1 - y, x),
+            Orientation::Transverse => (height - 1 - y, width - 1 - x),
+            Orientation::Rotate270 => (y, width - 1 - x),
+        }
+    }
+}
+
+impl Exif {
+    /// Check the TIFF header of the EXIF data.
+    pub fn new(data: Vec<u8>) -> Result<Exif, String> {
+        endianness(&data)?;
+        Ok(Exif(data))
+    }
+
+    /// Orientation tag of the first image file directory (IFD0), if any.
+    pub fn orientation(&self) -> Option<Orientation> {
+        let data = self.0.as_slice();
+        let endian = endianness(data).ok()?;
+        let ifd_offset = read_u32(data, 4, endian)? as usize;
+        let nb_entries = read_u16(data, ifd_offset, endian)? as usize;
+        // Offsets come from the file and may overflow on 32-bit targets.
+        let first_entry = ifd_offset.checked_add(2)?;
+        (0..nb_entries)
+            .map_while(|i| first_entry.checked_add(i.checked_mul(12)?))
+            .find(|&entry| read_u16(data, entry, endian) == Some(ORIENTATION_TAG))
+            .and_then(|entry| {
+                if read_u16(data, entry.checked_add(2)?, endian)? != SHORT_TYPE {
+                    return None;
+                }
+                // The value of a single SHORT is stored in the value field.
+                let value = read_u16(data, entry.checked_add(8)?, endian)?;
+                Orientation::try_from(value).ok()
+            })
+    }
+}
+
+fn endianness(data: &[u8]) -> Result<Endianness, String> {
+    match data.get(0..4) {
+        Some(b"II*\0") => Ok(Endianness::Little),
+        Some(b"MM\0*") => Ok(Endianness::Big),
+        _ => Err("EXIF data must start with a TIFF header".to_string()),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize, endian: Endianness) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    let bytes = [bytes[0], bytes[1]];
+    match endian {
+        Endianness::Little => Some(u16::from_le_bytes(bytes)),
+        Endianness::Big => Some(u16::from_be_bytes(bytes)),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize, endian: Endianness) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    match endian {
+        Endianness::Little => Some(u32::from_le_bytes(bytes)),
+        Endianness::Big => Some(u32::from_be_bytes(bytes)),
+    }
+}
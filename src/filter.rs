@@ -1,6 +1,7 @@
+use miniz_oxide::deflate::compress_to_vec;
 use std::convert::TryFrom;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Filter {
     None,
     Sub,
@@ -9,6 +10,28 @@ pub enum Filter {
     Paeth,
 }
 
+/// How to choose the filter of each scanline when encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// Same filter for all scanlines.
+    Fixed(Filter),
+    /// Filter with the minimum sum of absolute differences,
+    /// bytes being taken as signed values.
+    MinSumAbsDiff,
+    /// Filter with the lowest entropy of the filtered bytes.
+    Entropy,
+    /// Filter whose scanline compresses best at the given zlib level.
+    BruteForce(u8),
+}
+
+const ALL_FILTERS: [Filter; 5] = [
+    Filter::None,
+    Filter::Sub,
+    Filter::Up,
+    Filter::Average,
+    Filter::Paeth,
+];
+
 impl TryFrom<u8> for Filter {
     type Error = String;
     fn try_from(value: u8) -> Result<Self, Self::Error> {
@@ -23,9 +46,9 @@ impl TryFrom<u8> for Filter {
     }
 }
 
-impl Into<u8> for Filter {
-    fn into(self) -> u8 {
-        match self {
+impl From<Filter> for u8 {
+    fn from(filter: Filter) -> u8 {
+        match filter {
             Filter::None => 0,
             Filter::Sub => 1,
             Filter::Up => 2,
@@ -113,7 +136,7 @@ pub fn decode_none_bis(
     inflated: &mut [u8],
     data_start: usize,
     data: &mut [u8],
-) -> () {
+) {
     data[data_start..data_start + len].copy_from_slice(&inflated[start..start + len]);
 }
 
@@ -125,7 +148,7 @@ pub fn decode_sub_bis(
     inflated: &mut [u8],
     data_start: usize,
     data: &mut [u8],
-) -> () {
+) {
     let current = &inflated[start..start + len];
     let data_line = &mut data[data_start..];
     data_line[..bpp].copy_from_slice(&current[..bpp]);
@@ -141,7 +164,7 @@ pub fn decode_up_bis(
     data_start: usize,
     data: &mut [u8],
     prev_buff: &mut [u8],
-) -> () {
+) {
     if data_start == 0 {
         decode_none_bis(len, start, inflated, data_start, data);
     } else {
@@ -150,7 +173,7 @@ pub fn decode_up_bis(
         for i in 0..len {
             current[i] = current[i].wrapping_add(prev_buff[i]);
         }
-        data[data_start..data_start + len].copy_from_slice(&current);
+        data[data_start..data_start + len].copy_from_slice(current);
     }
 }
 
@@ -162,9 +185,14 @@ pub fn decode_average_bis(
     data_start: usize,
     data: &mut [u8],
     prev_buff: &mut [u8],
-) -> () {
+) {
     if data_start == 0 {
-        decode_sub_bis(bpp, len, start, inflated, data_start, data);
+        // The previous line is made of zeros.
+        let current = &mut inflated[start..start + len];
+        for i in bpp..len {
+            current[i] = current[i].wrapping_add(current[i - bpp] / 2);
+        }
+        data[..len].copy_from_slice(current);
     } else {
         prev_buff.copy_from_slice(&data[data_start - len..data_start]);
         let current = &mut inflated[start..start + len];
@@ -176,7 +204,7 @@ pub fn decode_average_bis(
             let left = current[i - bpp] as u16;
             current[i] = current[i].wrapping_add(((up + left) / 2) as u8);
         }
-        data[data_start..data_start + len].copy_from_slice(&current);
+        data[data_start..data_start + len].copy_from_slice(current);
     }
 }
 
@@ -188,7 +216,7 @@ pub fn decode_paeth_bis(
     data_start: usize,
     data: &mut [u8],
     prev_buff: &mut [u8],
-) -> () {
+) {
     if data_start == 0 {
         decode_sub_bis(bpp, len, start, inflated, data_start, data);
     } else {
@@ -203,7 +231,7 @@ pub fn decode_paeth_bis(
             let left = current[i - bpp];
             current[i] = current[i].wrapping_add(paeth_predictor(left, up, up_left));
         }
-        data[data_start..data_start + len].copy_from_slice(&current);
+        data[data_start..data_start + len].copy_from_slice(current);
     }
 }
 
@@ -239,7 +267,12 @@ pub fn decode_average(
     previous: &mut [u8],
 ) {
     if line_start == 0 {
-        decode_sub(bpp, line, line_start, data)
+        // The previous line is made of zeros.
+        let data_line = &mut data[..line.len()];
+        data_line[..bpp].copy_from_slice(&line[..bpp]);
+        for i in bpp..line.len() {
+            data_line[i] = line[i].wrapping_add(data_line[i - bpp] / 2);
+        }
     } else {
         previous.copy_from_slice(&data[line_start - line.len()..line_start]);
         let data_line = &mut data[line_start..line_start + line.len()];
@@ -318,3 +351,145 @@ fn paeth_predictor(left: u8, up: u8, up_left: u8) -> u8 {
         up_left // c
     }
 }
+
+// Forward filters #############################################################
+
+/// Filter the rows of `data`, `row_len` bytes each,
+/// prefixing each one with its filter type.
+/// Rows must hold at least one pixel of `bpp` bytes.
+pub fn filter(
+    bpp: usize,
+    row_len: usize,
+    data: &[u8],
+    strategy: FilterStrategy,
+) -> Result<Vec<u8>, String> {
+    if bpp == 0 || row_len < bpp {
+        return Err(format!(
+            "Rows of {} bytes cannot hold pixels of {} bytes",
+            row_len, bpp
+        ));
+    }
+    if data.len() % row_len != 0 {
+        return Err(format!(
+            "Data of {} bytes is not made of rows of {} bytes",
+            data.len(),
+            row_len
+        ));
+    }
+    let mut filtered = Vec::with_capacity(data.len() + data.len() / row_len);
+    let zeros = vec![0; row_len];
+    let mut candidate = vec![0; row_len];
+    let mut best = vec![0; row_len];
+    let mut prev: &[u8] = &zeros;
+    for line in data.chunks(row_len) {
+        let filter = match strategy {
+            FilterStrategy::Fixed(filter) => {
+                encode_line(filter, bpp, line, prev, &mut best);
+                filter
+            }
+            FilterStrategy::MinSumAbsDiff => {
+                let cost = |f: &[u8]| f.iter().map(|x| (*x as i8).unsigned_abs() as u64).sum();
+                best_filter(bpp, line, prev, &mut candidate, &mut best, cost)
+            }
+            FilterStrategy::Entropy => {
+                best_filter(bpp, line, prev, &mut candidate, &mut best, entropy)
+            }
+            FilterStrategy::BruteForce(level) => {
+                let cost = |f: &[u8]| compress_to_vec(f, level).len() as u64;
+                best_filter(bpp, line, prev, &mut candidate, &mut best, cost)
+            }
+        };
+        filtered.push(filter.into());
+        filtered.extend_from_slice(&best);
+        prev = line;
+    }
+    Ok(filtered)
+}
+
+/// Filter `line` with every filter type, keeping the one of lowest cost in `best`.
+fn best_filter<F: Fn(&[u8]) -> u64>(
+    bpp: usize,
+    line: &[u8],
+    prev: &[u8],
+    candidate: &mut [u8],
+    best: &mut [u8],
+    cost: F,
+) -> Filter {
+    let mut best_filter = Filter::None;
+    let mut best_cost = u64::MAX;
+    for filter in ALL_FILTERS.iter() {
+        encode_line(*filter, bpp, line, prev, candidate);
+        let c = cost(candidate);
+        if c < best_cost {
+            best_cost = c;
+            best_filter = *filter;
+            best.copy_from_slice(candidate);
+        }
+    }
+    best_filter
+}
+
+// Shannon entropy of the bytes, scaled to an integer cost.
+fn entropy(bytes: &[u8]) -> u64 {
+    let mut counts = [0u32; 256];
+    bytes.iter().for_each(|b| counts[*b as usize] += 1);
+    let total = bytes.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    (bits * total * 1000.0) as u64
+}
+
+/// Filter `line`, the previous row being `prev`, into `out`.
+pub fn encode_line(filter: Filter, bpp: usize, line: &[u8], prev: &[u8], out: &mut [u8]) {
+    match filter {
+        Filter::None => encode_none(line, out),
+        Filter::Sub => encode_sub(bpp, line, out),
+        Filter::Up => encode_up(line, prev, out),
+        Filter::Average => encode_average(bpp, line, prev, out),
+        Filter::Paeth => encode_paeth(bpp, line, prev, out),
+    }
+}
+
+#[inline]
+pub fn encode_none(line: &[u8], out: &mut [u8]) {
+    out.copy_from_slice(line);
+}
+
+pub fn encode_sub(bpp: usize, line: &[u8], out: &mut [u8]) {
+    out[..bpp].copy_from_slice(&line[..bpp]);
+    for i in bpp..line.len() {
+        out[i] = line[i].wrapping_sub(line[i - bpp]);
+    }
+}
+
+pub fn encode_up(line: &[u8], prev: &[u8], out: &mut [u8]) {
+    for i in 0..line.len() {
+        out[i] = line[i].wrapping_sub(prev[i]);
+    }
+}
+
+pub fn encode_average(bpp: usize, line: &[u8], prev: &[u8], out: &mut [u8]) {
+    for i in 0..bpp {
+        out[i] = line[i].wrapping_sub(prev[i] / 2);
+    }
+    for i in bpp..line.len() {
+        let average = (line[i - bpp] as u16 + prev[i] as u16) / 2;
+        out[i] = line[i].wrapping_sub(average as u8);
+    }
+}
+
+pub fn encode_paeth(bpp: usize, line: &[u8], prev: &[u8], out: &mut [u8]) {
+    for i in 0..bpp {
+        out[i] = line[i].wrapping_sub(prev[i]);
+    }
+    for i in bpp..line.len() {
+        let predictor = paeth_predictor(line[i - bpp], prev[i], prev[i - bpp]);
+        out[i] = line[i].wrapping_sub(predictor);
+    }
+}
@@ -0,0 +1,247 @@
+use inflate::core::inflate_flags::{
+    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_PARSE_ZLIB_HEADER,
+    TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+};
+use miniz_oxide::inflate;
+use std::error::Error;
+use std::io::Cursor;
+#[cfg(feature = "zlib-rs")]
+use zlib_rs::{Inflate, InflateFlush, Status};
+
+// Internal imports
+use crate::limits::LimitError;
+
+// TYPES #######################################################################
+
+/// Decompression backend for the zlib stream of the image data.
+pub trait Inflater: std::fmt::Debug + Send + Sync {
+    /// Inflate the zlib stream split into the `input` slices.
+    /// The output is expected to be `len` bytes long,
+    /// more is accepted until `max_len` bytes.
+    fn inflate(
+        &self,
+        input: &[&[u8]],
+        len: usize,
+        max_len: usize,
+        verify_adler32: bool,
+    ) -> Result<Inflated, Box<dyn Error>>;
+}
+
+/// Output of an `Inflater`.
+#[derive(Debug)]
+pub struct Inflated {
+    pub data: Vec<u8>,
+    /// Whether the input ended before the end of the zlib stream.
+    pub truncated: bool,
+    /// Number of bytes after the end of the zlib stream, Adler-32 excluded.
+    pub trailing_bytes: usize,
+}
+
+/// Streaming inflater of miniz_oxide, the default backend.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MinizInflater;
+
+/// Streaming inflater of raw deflate data, without zlib header and checksum,
+/// as found in the image data of Apple CgBI files.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RawDeflateInflater;
+
+/// Streaming inflater of zlib-rs, a port of zlib-ng.
+#[cfg(feature = "zlib-rs")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ZlibRsInflater;
+
+// FUNCTIONS ###################################################################
+
+impl Inflater for MinizInflater {
+    fn inflate(
+        &self,
+        input: &[&[u8]],
+        len: usize,
+        max_len: usize,
+        verify_adler32: bool,
+    ) -> Result<Inflated, Box<dyn Error>> {
+        // Without verification, the header is skipped and the raw deflate data
+        // is inflated, which does not compute the checksum.
+        if verify_adler32 {
+            inflate_miniz(input, len, max_len, TINFL_FLAG_PARSE_ZLIB_HEADER, 0, 0)
+        } else {
+            inflate_miniz(input, len, max_len, 0, 2, 4)
+        }
+    }
+}
+
+impl Inflater for RawDeflateInflater {
+    /// There is no checksum to verify in raw deflate data.
+    fn inflate(
+        &self,
+        input: &[&[u8]],
+        len: usize,
+        max_len: usize,
+        _verify_adler32: bool,
+    ) -> Result<Inflated, Box<dyn Error>> {
+        inflate_miniz(input, len, max_len, 0, 0, 0)
+    }
+}
+
+#[cfg(feature = "zlib-rs")]
+impl Inflater for ZlibRsInflater {
+    fn inflate(
+        &self,
+        input: &[&[u8]],
+        len: usize,
+        max_len: usize,
+        verify_adler32: bool,
+    ) -> Result<Inflated, Box<dyn Error>> {
+        // Same as miniz_oxide, raw deflate data skips the checksum.
+        if verify_adler32 {
+            inflate_zlib_rs(input, len, max_len, true, 0, 0)
+        } else {
+            inflate_zlib_rs(input, len, max_len, false, 2, 4)
+        }
+    }
+}
+
+// Helpers #####################################################################
+
+// Inflate with miniz_oxide, skipping `header_left` bytes of input
+// and not counting the `trailer_len` bytes after the deflate data as trailing.
+fn inflate_miniz(
+    input: &[&[u8]],
+    len: usize,
+    max_len: usize,
+    flags: u32,
+    mut header_left: usize,
+    trailer_len: usize,
+) -> Result<Inflated, Box<dyn Error>> {
+    let flags = flags | TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let mut decomp = inflate::core::DecompressorOxide::new();
+    decomp.init();
+    let mut ret: Vec<u8> = vec![0; len];
+
+    let nb_slices = input.len();
+    let mut out_pos = 0;
+    for (id, slice) in input.iter().enumerate() {
+        let mut in_pos = std::cmp::min(header_left, slice.len());
+        header_left -= in_pos;
+        let slice_flags = if id == nb_slices - 1 {
+            flags
+        } else {
+            flags | TINFL_FLAG_HAS_MORE_INPUT
+        };
+        loop {
+            let (status, in_consumed, out_consumed) = {
+                // Wrap the whole output slice so we know we have enough of the
+                // decompressed data for matches.
+                let mut c = Cursor::new(ret.as_mut_slice());
+                c.set_position(out_pos as u64);
+                inflate::core::decompress(&mut decomp, &slice[in_pos..], &mut c, slice_flags)
+            };
+            in_pos += in_consumed;
+            out_pos += out_consumed;
+
+            match status {
+                inflate::TINFLStatus::Done => {
+                    ret.truncate(out_pos);
+                    let next_slices = input[id + 1..].iter().map(|s| s.len());
+                    let trailing_bytes = slice.len() - in_pos + next_slices.sum::<usize>();
+                    return Ok(Inflated {
+                        data: ret,
+                        truncated: false,
+                        trailing_bytes: trailing_bytes.saturating_sub(trailer_len),
+                    });
+                }
+
+                inflate::TINFLStatus::HasMoreOutput => {
+                    // We need more space so extend the buffer.
+                    if ret.len() >= max_len {
+                        return Err(Box::new(LimitError::OutputBytes {
+                            bytes: ret.len() as u64 + 1,
+                            max: max_len,
+                        }));
+                    }
+                    let new_len = std::cmp::min(max_len, std::cmp::max(1, ret.len() + out_pos));
+                    ret.resize(new_len, 0);
+                }
+
+                // Normal if we are not at the last slice,
+                // otherwise the stream is truncated.
+                inflate::TINFLStatus::NeedsMoreInput
+                | inflate::TINFLStatus::FailedCannotMakeProgress => break,
+
+                inflate::TINFLStatus::Adler32Mismatch => {
+                    return Err("The Adler-32 checksum of the image data does not match".into())
+                }
+
+                _ => return Err(format!("{:?}", status).into()),
+            }
+        }
+    }
+    // The zlib stream did not reach its end.
+    ret.truncate(out_pos);
+    Ok(Inflated {
+        data: ret,
+        truncated: true,
+        trailing_bytes: 0,
+    })
+}
+
+// Inflate with zlib-rs, skipping `header_left` bytes of input
+// and not counting the `trailer_len` bytes after the deflate data as trailing.
+#[cfg(feature = "zlib-rs")]
+fn inflate_zlib_rs(
+    input: &[&[u8]],
+    len: usize,
+    max_len: usize,
+    zlib_header: bool,
+    mut header_left: usize,
+    trailer_len: usize,
+) -> Result<Inflated, Box<dyn Error>> {
+    let mut stream = Inflate::new(zlib_header, 15);
+    // One more byte than `max_len` tells that the limit is exceeded.
+    let max_buffer_len = max_len.saturating_add(1);
+    let mut ret: Vec<u8> = vec![0; std::cmp::min(len, max_buffer_len)];
+    let mut out_pos = 0;
+    for (id, slice) in input.iter().enumerate() {
+        let mut in_pos = std::cmp::min(header_left, slice.len());
+        header_left -= in_pos;
+        loop {
+            let (total_in, total_out) = (stream.total_in(), stream.total_out());
+            let status = stream
+                .decompress(&slice[in_pos..], &mut ret[out_pos..], InflateFlush::NoFlush)
+                .map_err(|e| stream.error_message().unwrap_or_else(|| e.as_str()))?;
+            in_pos += (stream.total_in() - total_in) as usize;
+            out_pos += (stream.total_out() - total_out) as usize;
+            if out_pos > max_len {
+                return Err(Box::new(LimitError::OutputBytes {
+                    bytes: out_pos as u64,
+                    max: max_len,
+                }));
+            }
+            if status == Status::StreamEnd {
+                ret.truncate(out_pos);
+                let next_slices = input[id + 1..].iter().map(|s| s.len());
+                let trailing_bytes = slice.len() - in_pos + next_slices.sum::<usize>();
+                return Ok(Inflated {
+                    data: ret,
+                    truncated: false,
+                    trailing_bytes: trailing_bytes.saturating_sub(trailer_len),
+                });
+            }
+            if out_pos == ret.len() {
+                // We need more space so extend the buffer.
+                let new_len = std::cmp::min(max_buffer_len, std::cmp::max(1, 2 * ret.len()));
+                ret.resize(new_len, 0);
+            } else if in_pos == slice.len() {
+                break;
+            }
+        }
+    }
+    // The zlib stream did not reach its end.
+    ret.truncate(out_pos);
+    Ok(Inflated {
+        data: ret,
+        truncated: true,
+        trailing_bytes: 0,
+    })
+}
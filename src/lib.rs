@@ -1,5 +1,18 @@
+pub mod apng;
 pub mod chunk;
 pub mod chunk_data;
 pub mod color;
+pub mod crc;
+pub mod custom_chunk;
+pub mod encoder;
+pub mod exif;
 pub mod filter;
+pub mod inflater;
+pub mod limits;
+pub mod lint;
+pub mod metadata;
+pub mod optimizer;
 pub mod png;
+pub mod rewriter;
+pub mod validation;
+pub mod zlib;
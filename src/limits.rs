@@ -0,0 +1,125 @@
+// Internal imports
+use crate::chunk_data::IHDRData;
+
+// TYPES #######################################################################
+
+/// Resource limits enforced while decoding untrusted images,
+/// before allocating the corresponding buffers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    /// Size of the inflated image data.
+    pub max_output_bytes: usize,
+    /// Decompressed size of each zTXt, iTXt or iCCP chunk.
+    pub max_text_size: usize,
+    pub max_chunks: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    Width { width: u32, max: u32 },
+    Height { height: u32, max: u32 },
+    Pixels { pixels: u64, max: u64 },
+    OutputBytes { bytes: u64, max: usize },
+    TextSize { max: usize },
+    Chunks { max: usize },
+}
+
+// FUNCTIONS ###################################################################
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_width: (1 << 31) - 1,
+            max_height: (1 << 31) - 1,
+            max_pixels: 1 << 28,
+            max_output_bytes: 1 << 30,
+            max_text_size: 1 << 23,
+            max_chunks: 1 << 20,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Limits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: u64::MAX,
+            max_output_bytes: usize::MAX,
+            max_text_size: usize::MAX,
+            max_chunks: usize::MAX,
+        }
+    }
+
+    /// Check the image dimensions and the size of its inflated data.
+    pub fn check_ihdr(&self, ihdr: &IHDRData) -> Result<(), LimitError> {
+        if ihdr.width > self.max_width {
+            return Err(LimitError::Width {
+                width: ihdr.width,
+                max: self.max_width,
+            });
+        }
+        if ihdr.height > self.max_height {
+            return Err(LimitError::Height {
+                height: ihdr.height,
+                max: self.max_height,
+            });
+        }
+        let pixels = ihdr.width as u64 * ihdr.height as u64;
+        if pixels > self.max_pixels {
+            return Err(LimitError::Pixels {
+                pixels,
+                max: self.max_pixels,
+            });
+        }
+        // Filter byte plus the pixels bits of each scanline, rounded up.
+        let bits_per_pixel = ihdr.nb_channels() as u64 * ihdr.bit_depth as u64;
+        let scanline = 1 + (ihdr.width as u64 * bits_per_pixel).div_ceil(8);
+        let bytes = scanline.saturating_mul(ihdr.height as u64);
+        if bytes > self.max_output_bytes as u64 {
+            return Err(LimitError::OutputBytes {
+                bytes,
+                max: self.max_output_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_chunks(&self, nb_chunks: usize) -> Result<(), LimitError> {
+        if nb_chunks > self.max_chunks {
+            Err(LimitError::Chunks {
+                max: self.max_chunks,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitError::Width { width, max } => {
+                write!(f, "Image width {} exceeds the limit {}", width, max)
+            }
+            LimitError::Height { height, max } => {
+                write!(f, "Image height {} exceeds the limit {}", height, max)
+            }
+            LimitError::Pixels { pixels, max } => {
+                write!(f, "Image pixels count {} exceeds the limit {}", pixels, max)
+            }
+            LimitError::OutputBytes { bytes, max } => {
+                write!(f, "Image data size {} exceeds the limit {}", bytes, max)
+            }
+            LimitError::TextSize { max } => {
+                write!(f, "Decompressed chunk size exceeds the limit {}", max)
+            }
+            LimitError::Chunks { max } => write!(f, "Chunks count exceeds the limit {}", max),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+// Internal imports
+use crate::chunk::{Chunk, ChunkType};
+use crate::chunk_data::{self, Chromaticities, ChunkData};
+use crate::custom_chunk::ChunkHandlers;
+use crate::limits::Limits;
+use crate::png;
+use crate::validation::{self, Violation};
+
+// TYPES #######################################################################
+
+/// Spec violations are errors,
+/// questionable but legal things are warnings.
+#[derive(Debug, Clone, Default)]
+pub struct LintReport {
+    pub errors: Vec<Violation>,
+    pub warnings: Vec<Violation>,
+}
+
+const MAX_KEYWORD_LENGTH: usize = 79;
+
+// Text chunks bigger than this are reported.
+const LARGE_TEXT_SIZE: usize = 1 << 16;
+
+// IDAT chunks smaller than this on average are reported, except the last one.
+const TINY_IDAT_SIZE: usize = 1024;
+
+// Tolerance of gAMA and cHRM values (multiplied by 100000).
+const TOLERANCE: u32 = 1000;
+
+// Usual gamma values: 1/2.2, 1/1.8 and 1.0
+const USUAL_GAMMAS: [u32; 3] = [45455, 55556, 100_000];
+
+const SRGB_GAMMA: u32 = 45455;
+const SRGB_CHROMATICITIES: Chromaticities = Chromaticities {
+    white_point: (31270, 32900),
+    red: (64000, 33000),
+    green: (30000, 60000),
+    blue: (15000, 6000),
+};
+
+// FUNCTIONS ###################################################################
+
+impl LintReport {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+}
+
+/// Report spec violations and questionable things of a PNG file.
+pub fn lint(input: &[u8], handlers: &ChunkHandlers) -> Result<LintReport, String> {
+    let (trailing, chunks) = png::parse_chunks(input).map_err(|e| format!("{:?}", e))?;
+    png::reject_cgbi(&chunks)?;
+    let offsets = validation::chunk_offsets(&chunks);
+    let located = |(index, message): (usize, String)| Violation {
+        chunk_index: index,
+        offset: offsets[index],
+        chunk_type: chunks.get(index).map(|c| c.chunk_type),
+        message,
+    };
+    // Ancillary chunks with an invalid payload are ignored by decoders.
+    let (mut errors, mut warnings) =
+        validation::validate_chunks_split(&chunks, handlers, &Limits::default());
+    let mut error = |index: usize, message: String| errors.push(located((index, message)));
+    let mut warn = |index: usize, message: String| warnings.push(located((index, message)));
+    lint_texts(&chunks, &mut error, &mut warn);
+    lint_color_space(&chunks, &mut warn);
+    lint_idats(&chunks, &mut warn);
+    lint_suggested_palettes(&chunks, &mut warn);
+    if lint_bit_depth(&chunks, input) {
+        warn(0, "16-bit samples could be stored with 8 bits".to_string());
+    }
+    if !trailing.is_empty() {
+        let message = format!("{} bytes after the last chunk", trailing.len());
+        warn(chunks.len(), message);
+    }
+    Ok(LintReport { errors, warnings })
+}
+
+fn lint_texts<E, W>(chunks: &[Chunk], error: &mut E, warn: &mut W)
+where
+    E: FnMut(usize, String),
+    W: FnMut(usize, String),
+{
+    let mut keywords = HashSet::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let (keyword, text_len) = match chunk_data::parse_chunk_data(chunk) {
+            Ok((_, ChunkData::tEXt(t))) => (t.keyword, t.text.len()),
+            Ok((_, ChunkData::zTXt(t))) => (t.keyword, t.text.len()),
+            Ok((_, ChunkData::iTXt(t))) => (t.keyword, t.text.len()),
+            _ => continue,
+        };
+        let keyword_len = keyword.chars().count();
+        if keyword_len == 0 || keyword_len > MAX_KEYWORD_LENGTH {
+            error(
+                index,
+                format!("Keyword {:?} is not 1 to 79 bytes long", keyword),
+            );
+        }
+        if text_len > LARGE_TEXT_SIZE {
+            warn(
+                index,
+                format!("{} text is {} bytes long", keyword, text_len),
+            );
+        }
+        if !keywords.insert(keyword.clone()) {
+            warn(index, format!("Duplicate text keyword {}", keyword));
+        }
+    }
+}
+
+fn lint_color_space<F: FnMut(usize, String)>(chunks: &[Chunk], warn: &mut F) {
+    let has_srgb = chunks.iter().any(|c| c.chunk_type == ChunkType::sRGB);
+    for (index, chunk) in chunks.iter().enumerate() {
+        match chunk_data::parse_chunk_data(chunk) {
+            Ok((_, ChunkData::gAMA(gamma))) => {
+                if has_srgb && !is_close(gamma.0, SRGB_GAMMA) {
+                    warn(index, format!("gAMA {} is inconsistent with sRGB", gamma.0));
+                } else if !USUAL_GAMMAS.iter().any(|&g| is_close(gamma.0, g)) {
+                    warn(index, format!("gAMA {} is not a usual value", gamma.0));
+                }
+            }
+            Ok((_, ChunkData::cHRM(chrm)))
+                if has_srgb && !chromaticities_are_close(&chrm, &SRGB_CHROMATICITIES) =>
+            {
+                warn(index, "cHRM is inconsistent with sRGB".to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lint_idats<F: FnMut(usize, String)>(chunks: &[Chunk], warn: &mut F) {
+    let idats: Vec<_> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type == ChunkType::IDAT)
+        .collect();
+    if idats.len() > 1 {
+        let not_last = &idats[..idats.len() - 1];
+        let total: usize = not_last.iter().map(|(_, c)| c.data.len()).sum();
+        if total / not_last.len() < TINY_IDAT_SIZE {
+            let message = format!(
+                "{} IDAT chunks of {} bytes on average",
+                idats.len(),
+                total / not_last.len()
+            );
+            warn(idats[0].0, message);
+        }
+    }
+}
+
+// sPLT names must be unique, decoders keep the first palette of a name.
+fn lint_suggested_palettes<F: FnMut(usize, String)>(chunks: &[Chunk], warn: &mut F) {
+    let mut names = HashSet::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        if let Ok((_, ChunkData::sPLT(splt))) = chunk_data::parse_chunk_data(chunk) {
+            if !names.insert(splt.name.clone()) {
+                warn(index, format!("Multiple sPLT chunks named {}", splt.name));
+            }
+        }
+    }
+}
+
+// Whether all 16-bit samples have equal high and low bytes.
+fn lint_bit_depth(chunks: &[Chunk], input: &[u8]) -> bool {
+    let ihdr = match chunks.first() {
+        Some(c) if c.chunk_type == ChunkType::IHDR => chunk_data::parse_valid_ihdr_data(c.data),
+        _ => return false,
+    };
+    match ihdr {
+        Ok(ihdr) if ihdr.bit_depth == 16 && ihdr.interlace_method == 0 => {
+            match png::decode_no_check(input) {
+                Ok(img) => img.data.chunks_exact(2).all(|s| s[0] == s[1]),
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn is_close(value: u32, reference: u32) -> bool {
+    (value as i64 - reference as i64).abs() <= TOLERANCE as i64
+}
+
+fn chromaticities_are_close(a: &Chromaticities, b: &Chromaticities) -> bool {
+    let pairs = [
+        (a.white_point, b.white_point),
+        (a.red, b.red),
+        (a.green, b.green),
+        (a.blue, b.blue),
+    ];
+    pairs
+        .iter()
+        .all(|((ax, ay), (bx, by))| is_close(*ax, *bx) && is_close(*ay, *by))
+}
@@ -8,7 +8,7 @@ fn main() {
     }
 }
 
-fn run(args: &[String]) -> Result<(), Box<Error>> {
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
     let data = fs::read(&args[1])?;
     // let _ = png::decode_no_check_timed_bis(&data)?;
     let _ = png::decode_no_check_timed(&data)?;
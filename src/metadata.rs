@@ -0,0 +1,214 @@
+use nom::error::ErrorKind;
+use std::any::Any;
+use std::error::Error;
+use std::time::SystemTime;
+
+// Internal imports
+use crate::chunk::{Chunk, ChunkType};
+use crate::chunk_data::{
+    self, AnimationControl, Background, Chromaticities, ChunkData, CompressedText, Gamma,
+};
+use crate::chunk_data::{CodingIndependentCodePoints, ContentLightLevel};
+use crate::chunk_data::{GifApplication, GifGraphicControl, ImageOffset, PhysicalScale};
+use crate::chunk_data::{IccProfile, InternationalText, LastModificationTime};
+use crate::chunk_data::{MasteringDisplayColorVolume, SuggestedPalette, Transparency};
+use crate::chunk_data::{PhysicalPixelDimension, RenderingIntent, SignificantBits, Text};
+use crate::chunk_data::{PixelCalibration, StereoMode};
+use crate::color::ColorType;
+use crate::custom_chunk::{ChunkHandlers, CustomChunkData};
+use crate::exif::{Exif, Orientation};
+use crate::limits::{LimitError, Limits};
+
+// TYPES #######################################################################
+
+/// All ancillary information of an image.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub transparency: Option<Transparency>,
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub srgb: Option<RenderingIntent>,
+    pub icc_profile: Option<IccProfile>,
+    pub texts: Vec<Text>,
+    pub compressed_texts: Vec<CompressedText>,
+    pub international_texts: Vec<InternationalText>,
+    pub background: Option<Background>,
+    pub physical_dimension: Option<PhysicalPixelDimension>,
+    pub significant_bits: Option<SignificantBits>,
+    pub suggested_palettes: Vec<SuggestedPalette>,
+    pub histogram: Option<Vec<u16>>,
+    pub last_modification_time: Option<LastModificationTime>,
+    pub exif: Option<Exif>,
+    pub cicp: Option<CodingIndependentCodePoints>,
+    pub mastering_display: Option<MasteringDisplayColorVolume>,
+    pub content_light_level: Option<ContentLightLevel>,
+    pub offset: Option<ImageOffset>,
+    pub pixel_calibration: Option<PixelCalibration>,
+    pub physical_scale: Option<PhysicalScale>,
+    pub stereo_mode: Option<StereoMode>,
+    pub gif_graphic_controls: Vec<GifGraphicControl>,
+    pub gif_applications: Vec<GifApplication>,
+    /// Present in animated images, whose frames are decoded by the `apng` module.
+    pub animation_control: Option<AnimationControl>,
+    pub custom_chunks: Vec<CustomChunkData>,
+}
+
+// FUNCTIONS ###################################################################
+
+impl Metadata {
+    /// Collect ancillary chunks data.
+    /// The first chunk must be the IHDR chunk.
+    pub fn from_chunks(chunks: &[Chunk]) -> Result<Metadata, String> {
+        Metadata::from_chunks_with(chunks, &ChunkHandlers::new(), &Limits::default())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Collect ancillary chunks data and the values
+    /// produced by the handlers of custom chunks.
+    /// Fails with a `LimitError` if a compressed chunk is too big.
+    pub fn from_chunks_with(
+        chunks: &[Chunk],
+        handlers: &ChunkHandlers,
+        limits: &Limits,
+    ) -> Result<Metadata, Box<dyn Error>> {
+        let color_type = match chunks.first() {
+            Some(c) if c.chunk_type == ChunkType::IHDR => {
+                chunk_data::parse_ihdr_data(c.data)
+                    .map_err(|e| format!("{:?}", e))?
+                    .1
+                    .color_type
+            }
+            _ => return Err("The first chunk must be IHDR".into()),
+        };
+        let palette_len = chunks
+            .iter()
+            .find(|c| c.chunk_type == ChunkType::PLTE)
+            .map(|c| c.data.len() / 3);
+        let mut metadata = Metadata::default();
+        for chunk in chunks.iter() {
+            if let Some(handler) = handlers.get(&chunk.chunk_type) {
+                let value = handler.parse(chunk.data)?;
+                let custom = CustomChunkData::new(chunk.chunk_type, value);
+                metadata.custom_chunks.push(custom);
+            } else {
+                metadata.add_chunk(chunk, color_type, palette_len, limits)?;
+            }
+        }
+        Ok(metadata)
+    }
+
+    fn add_chunk(
+        &mut self,
+        chunk: &Chunk,
+        color_type: ColorType,
+        palette_len: Option<usize>,
+        limits: &Limits,
+    ) -> Result<(), Box<dyn Error>> {
+        // Invalid ancillary chunks are ignored, they are reported by `lint`.
+        if chunk.chunk_type == ChunkType::tRNS {
+            if let Ok((_, trns)) = chunk_data::parse_trns_data(chunk.data, color_type) {
+                self.transparency = Some(trns);
+            }
+            return Ok(());
+        }
+        let (_, data) = match chunk_data::parse_chunk_data_with_limits(chunk, limits) {
+            Ok(parsed) => parsed,
+            Err(nom::Err::Failure((_, ErrorKind::TooLarge))) => {
+                let max = limits.max_text_size;
+                return Err(Box::new(LimitError::TextSize { max }));
+            }
+            Err(_) if chunk.chunk_type.is_ancillary() => return Ok(()),
+            Err(e) => return Err(format!("{:?}", e).into()),
+        };
+        match data {
+            ChunkData::gAMA(gamma) => self.gamma = Some(gamma),
+            ChunkData::cHRM(chrm) => self.chromaticities = Some(chrm),
+            ChunkData::sRGB(intent) => self.srgb = Some(intent),
+            ChunkData::iCCP(profile) => self.icc_profile = Some(profile),
+            ChunkData::tEXt(text) => self.texts.push(text),
+            ChunkData::zTXt(text) => self.compressed_texts.push(text),
+            ChunkData::iTXt(text) => self.international_texts.push(text),
+            ChunkData::bKGD(bkgd) => self.background = Some(bkgd),
+            ChunkData::pHYs(phys) => self.physical_dimension = Some(phys),
+            ChunkData::sBIT(sbit) => self.significant_bits = Some(sbit),
+            ChunkData::sPLT(splt) => {
+                if self.suggested_palettes.iter().all(|p| p.name != splt.name) {
+                    self.suggested_palettes.push(splt)
+                }
+            }
+            ChunkData::hIST(hist) => {
+                if palette_len == Some(hist.len()) {
+                    self.histogram = Some(hist)
+                }
+            }
+            ChunkData::tIME(time) => self.last_modification_time = Some(time),
+            ChunkData::eXIf(exif) => self.exif = Some(exif),
+            ChunkData::cICP(cicp) => self.cicp = Some(cicp),
+            ChunkData::mDCv(mdcv) => self.mastering_display = Some(mdcv),
+            ChunkData::cLLi(clli) => self.content_light_level = Some(clli),
+            ChunkData::oFFs(offs) => self.offset = Some(offs),
+            ChunkData::pCAL(pcal) => self.pixel_calibration = Some(pcal),
+            ChunkData::sCAL(scal) => self.physical_scale = Some(scal),
+            ChunkData::sTER(ster) => self.stereo_mode = Some(ster),
+            ChunkData::gIFg(gifg) => self.gif_graphic_controls.push(gifg),
+            ChunkData::gIFx(gifx) => self.gif_applications.push(gifx),
+            ChunkData::acTL(actl) => self.animation_control = Some(actl),
+            ChunkData::IHDR(_) | ChunkData::IEND | ChunkData::Unknown(_) => {}
+            ChunkData::fcTL(_) | ChunkData::fdAT(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Dots per inch of the image, if the physical unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        self.physical_dimension.as_ref().and_then(|p| p.dpi())
+    }
+
+    /// Last modification time, if present and valid.
+    pub fn modification_time(&self) -> Option<SystemTime> {
+        self.last_modification_time
+            .as_ref()
+            .and_then(|t| t.to_system_time().ok())
+    }
+
+    /// Orientation stored in the EXIF data, if any.
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.exif.as_ref().and_then(|e| e.orientation())
+    }
+
+    /// Whether the cICP chunk signals a PQ or HLG transfer function.
+    pub fn is_hdr(&self) -> bool {
+        self.cicp.is_some_and(|c| c.is_hdr())
+    }
+
+    /// First value of type `T` produced by the handler of a custom chunk type.
+    pub fn custom<T: Any>(&self, chunk_type: ChunkType) -> Option<&T> {
+        self.customs(chunk_type).next()
+    }
+
+    /// All values of type `T` produced by the handler of a custom chunk type.
+    pub fn customs<T: Any>(&self, chunk_type: ChunkType) -> impl Iterator<Item = &T> {
+        self.custom_chunks
+            .iter()
+            .filter(move |c| c.chunk_type == chunk_type)
+            .filter_map(|c| c.value())
+    }
+
+    /// Suggested palette with the given name.
+    pub fn suggested_palette(&self, name: &str) -> Option<&SuggestedPalette> {
+        self.suggested_palettes.iter().find(|p| p.name == name)
+    }
+
+    /// Value of the first textual chunk (tEXt, zTXt or iTXt) with this keyword.
+    pub fn text(&self, keyword: &str) -> Option<&str> {
+        let text = self.texts.iter().find(|t| t.keyword == keyword);
+        let ztxt = self.compressed_texts.iter().find(|t| t.keyword == keyword);
+        let itxt = self
+            .international_texts
+            .iter()
+            .find(|t| t.keyword == keyword);
+        text.map(|t| t.text.as_str())
+            .or_else(|| ztxt.map(|t| t.text.as_str()))
+            .or_else(|| itxt.map(|t| t.text.as_str()))
+    }
+}
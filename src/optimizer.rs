@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+
+// Internal imports
+use crate::chunk::{self, Chunk, ChunkType};
+use crate::chunk_data::{self, IHDRData, Transparency};
+use crate::color::ColorType;
+use crate::encoder::{self, EncodeOptions};
+use crate::filter::{self, Filter, FilterStrategy};
+use crate::limits::Limits;
+use crate::png;
+
+// TYPES #######################################################################
+
+/// Options of `optimize`.
+#[derive(Debug, Clone)]
+pub struct OptimizeOptions {
+    /// zlib compression levels tried.
+    pub levels: Vec<u8>,
+    /// Filter strategies tried.
+    pub strategies: Vec<FilterStrategy>,
+    /// Keep the ancillary chunks that do not depend on the color type.
+    pub keep_metadata: bool,
+}
+
+/// Pixels as 16 bits RGBA samples, whatever the encoding.
+type Pixels = Vec<[u16; 4]>;
+
+// Chunks as type and data.
+type RawChunks = Vec<([u8; 4], Vec<u8>)>;
+
+// One way to encode the image: header, rows, palette and transparency.
+struct Candidate {
+    ihdr: IHDRData,
+    data: Vec<u8>,
+    palette: Option<Vec<[u8; 3]>>,
+    transparency: Option<Transparency>,
+}
+
+// FUNCTIONS ###################################################################
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            levels: vec![9, 10],
+            strategies: vec![
+                FilterStrategy::Fixed(Filter::None),
+                FilterStrategy::MinSumAbsDiff,
+                FilterStrategy::Entropy,
+            ],
+            keep_metadata: true,
+        }
+    }
+}
+
+/// Re-encode a PNG image into the smallest file with the same pixels.
+/// Tries lower bit depths, gray, palette, no alpha, and each filter strategy
+/// and compression level, keeping the smallest output that decodes to
+/// the same pixels. The input is returned if nothing is smaller.
+/// Animated images are rejected since only the default image would be kept.
+pub fn optimize(input: &[u8], options: &OptimizeOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (ihdr, pixels) = decode_pixels(input)?;
+    let chunks = png::parse_chunks_with_limits(input, &Limits::default())?;
+    let animation_chunks = [ChunkType::acTL, ChunkType::fcTL, ChunkType::fdAT];
+    if chunks
+        .iter()
+        .any(|c| animation_chunks.contains(&c.chunk_type))
+    {
+        return Err("Animated PNG images are not supported".into());
+    }
+    let ancillary_chunks = if options.keep_metadata {
+        color_independent_chunks(&chunks)
+    } else {
+        Vec::new()
+    };
+    // An ICC profile is either for gray or for color images.
+    let keeps_profile = ancillary_chunks.iter().any(|(t, _)| t == b"iCCP");
+    let mut outputs = Vec::new();
+    for candidate in candidates(&ihdr, &pixels).iter() {
+        if keeps_profile && is_gray(candidate.ihdr.color_type) != is_gray(ihdr.color_type) {
+            continue;
+        }
+        for level in options.levels.iter() {
+            for strategy in options.strategies.iter() {
+                let encode_options = EncodeOptions {
+                    palette: candidate.palette.clone(),
+                    transparency: candidate.transparency.clone(),
+                    compression_level: *level,
+                    idat_size: 1 << 20,
+                    filter_strategy: *strategy,
+                    ancillary_chunks: ancillary_chunks.clone(),
+                };
+                outputs.push(encoder::encode(
+                    &candidate.ihdr,
+                    &candidate.data,
+                    &encode_options,
+                )?);
+            }
+        }
+    }
+    outputs.sort_by_key(|o| o.len());
+    let smallest = outputs
+        .into_iter()
+        .take_while(|o| o.len() < input.len())
+        .find(|o| decode_pixels(o).ok().map(|(_, p)| p).as_ref() == Some(&pixels));
+    Ok(smallest.unwrap_or_else(|| input.to_vec()))
+}
+
+/// Decode the pixels of any non-interlaced PNG image,
+/// samples being scaled to 16 bits.
+pub fn decode_pixels(input: &[u8]) -> Result<(IHDRData, Pixels), Box<dyn Error>> {
+    let limits = Limits::default();
+    let chunks = png::parse_chunks_with_limits(input, &limits)?;
+    png::reject_cgbi(&chunks)?;
+    let chunks = chunk::validate_chunk_constraints(&chunks)?;
+    let ihdr = chunk_data::parse_valid_ihdr_data(chunks[0].data)?;
+    limits.check_ihdr(&ihdr)?;
+    if ihdr.interlace_method != 0 {
+        return Err("Interlaced images are not supported".into());
+    }
+    let (palette, transparency) = chunk_data::palette_and_transparency(chunks, ihdr.color_type);
+
+    let idats: Vec<&Chunk> = chunks
+        .iter()
+        .filter(|c| c.chunk_type == ChunkType::IDAT)
+        .collect();
+    let len = ihdr.image_data_len()?;
+    let inflated = chunk_data::inflate_idats(&idats, len, len)?;
+    let pixels = rgba_pixels(&ihdr, &inflated, &palette, transparency.as_ref())?;
+    Ok((ihdr, pixels))
+}
+
+/// Unfilter the inflated data of a non-interlaced image
+/// and convert its pixels to RGBA, samples being scaled to 16 bits.
+pub(crate) fn rgba_pixels(
+    ihdr: &IHDRData,
+    inflated: &[u8],
+    palette: &[&[u8]],
+    transparency: Option<&Transparency>,
+) -> Result<Pixels, Box<dyn Error>> {
+    // Unfilter the rows.
+    let row_len = encoder::row_len(ihdr)?;
+    let height = ihdr.height as usize;
+    let len = ihdr.image_data_len()?;
+    if inflated.len() != len {
+        return Err(format!("Image data is {} bytes instead of {}", inflated.len(), len).into());
+    }
+    let mut scanlines = Vec::with_capacity(height);
+    for line in inflated.chunks(row_len + 1) {
+        scanlines.push((Filter::try_from(line[0])?, &line[1..]));
+    }
+    let bpp = ihdr.bytes_per_pixel();
+    let rows = filter::unfilter(row_len / bpp, height, bpp, scanlines);
+
+    // Convert samples to RGBA.
+    let nb_channels = ihdr.nb_channels();
+    let max_index = (1u32 << ihdr.bit_depth) - 1;
+    let width = ihdr.width as usize;
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in rows.chunks(row_len) {
+        let samples = unpack(row, ihdr.bit_depth, width * nb_channels);
+        for s in samples.chunks(nb_channels) {
+            let pixel = match ihdr.color_type {
+                ColorType::Gray => [s[0], s[0], s[0], u16::MAX],
+                ColorType::GrayAlpha => [s[0], s[0], s[0], s[1]],
+                ColorType::RGB => [s[0], s[1], s[2], u16::MAX],
+                ColorType::RGBA => [s[0], s[1], s[2], s[3]],
+                ColorType::PLTE => {
+                    let index = (s[0] as u32 * max_index / 65535) as usize;
+                    let rgb = palette
+                        .get(index)
+                        .ok_or_else(|| format!("Palette index {} is out of range", index))?;
+                    let alpha = match transparency {
+                        Some(Transparency::Palette(alphas)) => alphas.get(index).cloned(),
+                        _ => None,
+                    };
+                    [rgb[0], rgb[1], rgb[2], alpha.unwrap_or(255)].map(|v| v as u16 * 257)
+                }
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    // Apply the transparent color of gray and RGB images.
+    let scale = |v: u16| scale_to_16(v, ihdr.bit_depth);
+    let key = match transparency {
+        Some(Transparency::Gray(g)) => Some([scale(*g), scale(*g), scale(*g)]),
+        Some(Transparency::RGB(rgb)) => Some(rgb.map(scale)),
+        _ => None,
+    };
+    if let Some(key) = key {
+        pixels
+            .iter_mut()
+            .filter(|p| p[..3] == key)
+            .for_each(|p| p[3] = 0);
+    }
+    Ok(pixels)
+}
+
+// Samples of a row at the given bit depth, scaled to 16 bits.
+fn unpack(row: &[u8], bit_depth: u8, nb_samples: usize) -> Vec<u16> {
+    match bit_depth {
+        16 => row
+            .chunks(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect(),
+        8 => row.iter().map(|b| *b as u16 * 257).collect(),
+        _ => {
+            let per_byte = 8 / bit_depth as usize;
+            let mask = (1u8 << bit_depth) - 1;
+            (0..nb_samples)
+                .map(|i| {
+                    let shift = 8 - bit_depth as usize * (i % per_byte + 1);
+                    let value = (row[i / per_byte] >> shift) & mask;
+                    scale_to_16(value as u16, bit_depth)
+                })
+                .collect()
+        }
+    }
+}
+
+fn scale_to_16(value: u16, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => value,
+        _ => (value as u32 * 65535 / ((1 << bit_depth) - 1)) as u16,
+    }
+}
+
+// Pack samples given at 16 bits into rows at the given bit depth.
+fn pack(samples: &[u16], bit_depth: u8, samples_per_row: usize) -> Vec<u8> {
+    let max = (1u32 << bit_depth) - 1;
+    let mut data = Vec::new();
+    for row in samples.chunks(samples_per_row) {
+        match bit_depth {
+            16 => row
+                .iter()
+                .for_each(|s| data.extend_from_slice(&s.to_be_bytes())),
+            _ => {
+                let per_byte = 8 / bit_depth as usize;
+                for byte_samples in row.chunks(per_byte) {
+                    let mut byte = 0u8;
+                    for (i, s) in byte_samples.iter().enumerate() {
+                        let value = (*s as u32 * max / 65535) as u8;
+                        byte |= value << (8 - bit_depth as usize * (i + 1));
+                    }
+                    data.push(byte);
+                }
+            }
+        }
+    }
+    data
+}
+
+// Whether a 16 bits sample is exactly representable at a bit depth.
+fn fits(sample: u16, bit_depth: u8) -> bool {
+    let step = 65535 / ((1u32 << bit_depth) - 1);
+    sample as u32 % step == 0
+}
+
+// Ways to encode the pixels, from the simplest to the most reduced.
+fn candidates(ihdr: &IHDRData, pixels: &[[u16; 4]]) -> Vec<Candidate> {
+    let is_gray = pixels.iter().all(|p| p[0] == p[1] && p[1] == p[2]);
+    let is_opaque = pixels.iter().all(|p| p[3] == u16::MAX);
+    let fits_8 = pixels.iter().all(|p| p.iter().all(|s| fits(*s, 8)));
+    let bit_depth = if fits_8 { 8 } else { 16 };
+    let header = |color_type, bit_depth| IHDRData {
+        color_type,
+        bit_depth,
+        ..*ihdr
+    };
+    let width = ihdr.width as usize;
+    let mut candidates = Vec::new();
+
+    // Gray or RGB, with or without alpha.
+    let (color_type, channels): (ColorType, &[usize]) = match (is_gray, is_opaque) {
+        (true, true) => (ColorType::Gray, &[0]),
+        (true, false) => (ColorType::GrayAlpha, &[0, 3]),
+        (false, true) => (ColorType::RGB, &[0, 1, 2]),
+        (false, false) => (ColorType::RGBA, &[0, 1, 2, 3]),
+    };
+    let samples: Vec<u16> = pixels
+        .iter()
+        .flat_map(|p| channels.iter().map(move |c| p[*c]))
+        .collect();
+    let samples_per_row = width * channels.len();
+    candidates.push(Candidate {
+        ihdr: header(color_type, bit_depth),
+        data: pack(&samples, bit_depth, samples_per_row),
+        palette: None,
+        transparency: None,
+    });
+
+    // Gray with less than 8 bits.
+    if color_type == ColorType::Gray {
+        if let Some(depth) = [1, 2, 4]
+            .iter()
+            .find(|d| samples.iter().all(|s| fits(*s, **d)))
+        {
+            candidates.push(Candidate {
+                ihdr: header(ColorType::Gray, *depth),
+                data: pack(&samples, *depth, samples_per_row),
+                palette: None,
+                transparency: None,
+            });
+        }
+    }
+
+    // Palette, transparent colors first to shorten tRNS.
+    if fits_8 {
+        let mut colors: Vec<[u16; 4]> = Vec::new();
+        let mut indices: HashMap<[u16; 4], usize> = HashMap::new();
+        for p in pixels.iter() {
+            if !indices.contains_key(p) {
+                if colors.len() == 256 {
+                    return candidates;
+                }
+                indices.insert(*p, colors.len());
+                colors.push(*p);
+            }
+        }
+        colors.sort_by_key(|c| (c[3] == u16::MAX, *c));
+        let indices: HashMap<[u16; 4], u16> = colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (*c, i as u16))
+            .collect();
+        let depth = [1, 2, 4, 8]
+            .iter()
+            .cloned()
+            .find(|d| colors.len() <= 1 << d)
+            .unwrap_or(8);
+        let max = (1u32 << depth) - 1;
+        // Indices are scaled to 16 bits for packing.
+        let samples: Vec<u16> = pixels
+            .iter()
+            .map(|p| (indices[p] as u32 * 65535 / max) as u16)
+            .collect();
+        let palette = colors
+            .iter()
+            .map(|c| [(c[0] / 257) as u8, (c[1] / 257) as u8, (c[2] / 257) as u8])
+            .collect();
+        let alphas: Vec<u8> = colors
+            .iter()
+            .take_while(|c| c[3] != u16::MAX)
+            .map(|c| (c[3] / 257) as u8)
+            .collect();
+        candidates.push(Candidate {
+            ihdr: header(ColorType::PLTE, depth),
+            data: pack(&samples, depth, width),
+            palette: Some(palette),
+            transparency: if alphas.is_empty() {
+                None
+            } else {
+                Some(Transparency::Palette(alphas))
+            },
+        });
+    }
+    candidates
+}
+
+// Ancillary chunks whose content does not depend on the color type,
+// and unknown chunks safe to copy.
+fn color_independent_chunks(chunks: &[Chunk]) -> RawChunks {
+    let kept = chunks.iter().filter(|c| match c.chunk_type {
+        ChunkType::IHDR | ChunkType::PLTE | ChunkType::IDAT | ChunkType::IEND => false,
+        ChunkType::tRNS | ChunkType::bKGD | ChunkType::sBIT | ChunkType::hIST => false,
+        ChunkType::Unknown(_) => c.chunk_type.is_safe_to_copy(),
+        _ => true,
+    });
+    kept.map(|c| (c.chunk_type.name(), c.data.to_vec()))
+        .collect()
+}
+
+fn is_gray(color_type: ColorType) -> bool {
+    matches!(color_type, ColorType::Gray | ColorType::GrayAlpha)
+}
@@ -1,15 +1,22 @@
 use nom::bytes::complete::tag;
-use nom::multi::many1;
+use nom::multi::{many1, many_m_n};
 use nom::IResult;
 use std::convert::TryFrom;
 use std::error::Error;
+use std::sync::Arc;
 use std::time::Instant;
 
 // inner modules
 use crate::chunk::{self, Chunk, ChunkType};
-use crate::chunk_data::{self, ChunkData, IHDRData};
+use crate::chunk_data::{self, Background, ChunkData, IHDRData, InflatedIdats};
 use crate::color::ColorType;
+use crate::custom_chunk::ChunkHandlers;
+use crate::exif::Orientation;
 use crate::filter::{self, Filter};
+use crate::inflater::{Inflater, MinizInflater};
+use crate::limits::Limits;
+use crate::metadata::Metadata;
+use crate::zlib::ZlibHeader;
 
 // TYPES #######################################################################
 
@@ -19,6 +26,59 @@ pub struct Png {
     pub color_type: ColorType,
     pub bytes_per_pixel: usize,
     pub data: Vec<u8>,
+    /// Length mismatches of the image data tolerated while decoding.
+    pub data_mismatches: Vec<DataMismatch>,
+    /// Number of rows decoded from the available data of a truncated file,
+    /// set when decoding in recovery mode.
+    pub recovered_rows: Option<usize>,
+    /// Header of the zlib stream of the image data,
+    /// `None` for Apple CgBI files and if the image data is empty.
+    pub zlib_header: Option<ZlibHeader>,
+    /// Whether the Adler-32 checksum of the image data was verified.
+    pub adler32_verified: bool,
+}
+
+/// Options of `decode_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Rotate or flip the image according to the EXIF orientation tag.
+    pub apply_exif_orientation: bool,
+    /// Parsers of custom chunks, their results are in `Metadata::custom_chunks`.
+    pub chunk_handlers: ChunkHandlers,
+    pub limits: Limits,
+    /// Accept image data shorter than required by the IHDR dimensions,
+    /// missing rows are left at zero.
+    pub tolerate_short_data: bool,
+    /// Accept and ignore image data after the last scanline.
+    pub tolerate_long_data: bool,
+    /// Accept and ignore compressed data after the end of the zlib stream.
+    pub tolerate_trailing_zlib_data: bool,
+    /// Skip the Adler-32 checksum of the image data, for speed.
+    pub skip_adler32: bool,
+    /// Backend inflating the image data, `MinizInflater` by default.
+    /// Not used for the raw deflate data of Apple CgBI files.
+    pub inflater: Option<Arc<dyn Inflater>>,
+    /// Decode truncated files, with as many rows as the available data allows.
+    pub recover_truncated: bool,
+    /// Pixel bytes filling the rows missing from a truncated file.
+    /// Defaults to the bKGD color if present, or zeros.
+    pub recovery_background: Option<Vec<u8>>,
+}
+
+/// Mismatch between the image data and the IHDR dimensions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataMismatch {
+    /// Not enough inflated data for all scanlines,
+    /// `zlib_truncated` if the zlib stream did not reach its end.
+    TooShort {
+        expected: usize,
+        actual: usize,
+        zlib_truncated: bool,
+    },
+    /// More inflated data than needed for all scanlines.
+    TooLong { expected: usize, actual: usize },
+    /// Compressed bytes after the end of the zlib stream.
+    TrailingZlibData { bytes: usize },
 }
 
 impl Png {
@@ -28,44 +88,232 @@ impl Png {
         let end = start + self.bytes_per_pixel;
         &self.data.as_slice()[start..end]
     }
+
+    /// Fill all rows starting at `first_row` with the `pixel` value.
+    pub fn fill_rows_from(&mut self, first_row: usize, pixel: &[u8]) -> Result<(), String> {
+        if pixel.len() != self.bytes_per_pixel {
+            return Err(format!(
+                "Background pixel has {} bytes instead of {}",
+                pixel.len(),
+                self.bytes_per_pixel
+            ));
+        }
+        let start = first_row * self.width * self.bytes_per_pixel;
+        if let Some(rest) = self.data.get_mut(start..) {
+            rest.chunks_exact_mut(pixel.len())
+                .for_each(|p| p.copy_from_slice(pixel));
+        }
+        Ok(())
+    }
+
+    /// Convert the premultiplied BGRA or BGR pixels of an Apple CgBI file
+    /// to straight RGBA or RGB.
+    pub fn convert_from_cgbi(&mut self) -> Result<(), String> {
+        match (self.color_type, self.bytes_per_pixel) {
+            (ColorType::RGB, 3) => {
+                self.data.chunks_exact_mut(3).for_each(|px| px.swap(0, 2));
+            }
+            (ColorType::RGBA, 4) => {
+                for px in self.data.chunks_exact_mut(4) {
+                    px.swap(0, 2);
+                    let alpha = px[3] as u16;
+                    for c in px[..3].iter_mut() {
+                        *c = match alpha {
+                            0 => 0,
+                            _ => std::cmp::min(255, (*c as u16 * 255 + alpha / 2) / alpha) as u8,
+                        };
+                    }
+                }
+            }
+            _ => return Err("CgBI images must be 8-bit RGB or RGBA".to_string()),
+        }
+        Ok(())
+    }
+
+    // Information about the zlib stream the image was inflated from.
+    fn set_zlib_stream(&mut self, inflated: &InflatedIdats) {
+        self.zlib_header = inflated.zlib_header;
+        self.adler32_verified = inflated.adler32_verified;
+    }
+
+    /// Rotate or flip the image pixels.
+    pub fn apply_orientation(self, orientation: Orientation) -> Png {
+        if orientation == Orientation::Normal {
+            return self;
+        }
+        let (width, height) = if orientation.swaps_dimensions() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let bpp = self.bytes_per_pixel;
+        let mut data = vec![0; self.data.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (new_x, new_y) = orientation.transform(x, y, self.width, self.height);
+                let start = (new_y * width + new_x) * bpp;
+                data[start..start + bpp].copy_from_slice(self.get(x, y));
+            }
+        }
+        Png {
+            width,
+            height,
+            data,
+            ..self
+        }
+    }
 }
 
-const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+impl DecodeOptions {
+    fn tolerates(&self, mismatch: &DataMismatch) -> bool {
+        match mismatch {
+            DataMismatch::TooShort { .. } => self.tolerate_short_data || self.recover_truncated,
+            DataMismatch::TooLong { .. } => self.tolerate_long_data,
+            DataMismatch::TrailingZlibData { .. } => self.tolerate_trailing_zlib_data,
+        }
+    }
+}
+
+impl std::fmt::Display for DataMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataMismatch::TooShort {
+                expected,
+                actual,
+                zlib_truncated,
+            } => {
+                write!(f, "Image data is {} bytes instead of {}", actual, expected)?;
+                if *zlib_truncated {
+                    write!(f, ", the zlib stream is truncated")?;
+                }
+                Ok(())
+            }
+            DataMismatch::TooLong { expected, actual } => write!(
+                f,
+                "Image data is {} bytes instead of {}, extra bytes after the last scanline",
+                actual, expected
+            ),
+            DataMismatch::TrailingZlibData { bytes } => {
+                write!(
+                    f,
+                    "{} compressed bytes after the end of the zlib stream",
+                    bytes
+                )
+            }
+        }
+    }
+}
+
+impl Error for DataMismatch {}
+
+pub(crate) const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
 // const EXTENDED_SIGNATURE: [u8; 12] = [137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13];
 
 // FUNCTIONS ###################################################################
 
-pub fn decode_no_check(input: &[u8]) -> Result<Png, Box<Error>> {
+pub fn decode(input: &[u8]) -> Result<(Png, Metadata), Box<dyn Error>> {
+    decode_with_options(input, &DecodeOptions::default())
+}
+
+pub fn decode_with_options(
+    input: &[u8],
+    options: &DecodeOptions,
+) -> Result<(Png, Metadata), Box<dyn Error>> {
+    let limits = &options.limits;
+    let chunks = if options.recover_truncated {
+        parse_chunks_truncated(input, limits)?
+    } else {
+        parse_chunks_with_limits(input, limits)?
+    };
+    let (chunks, cgbi) = strip_cgbi(&chunks);
+    let handlers = &options.chunk_handlers;
+    let chunks_valid = chunk::validate_chunk_constraints_with(chunks, handlers, limits)?;
+    let metadata = Metadata::from_chunks_with(chunks_valid, handlers, limits)?;
+    let inflater = options.inflater.as_deref().unwrap_or(&MinizInflater);
+    let verify_adler32 = !options.skip_adler32;
+    let (ihdr_data, mut inflated) =
+        ihdr_and_inflated_idats(chunks_valid, limits, inflater, verify_adler32, cgbi)?;
+    let data_mismatches = data_mismatches(ihdr_data.image_data_len()?, &mut inflated);
+    if let Some(mismatch) = data_mismatches.iter().find(|m| !options.tolerates(m)) {
+        return Err(Box::new(*mismatch));
+    }
+    let scanlines = lines_slices(&inflated.data, ihdr_data.scanline_width()?)?;
+    let nb_rows = scanlines.len();
+    let mut png_img = unfilter(&ihdr_data, scanlines);
+    png_img.set_zlib_stream(&inflated);
+    png_img.data_mismatches = data_mismatches;
+    if cgbi {
+        png_img.convert_from_cgbi()?;
+    }
+    if options.recover_truncated {
+        let background = match &options.recovery_background {
+            Some(pixel) => pixel.clone(),
+            None => background_pixel(&ihdr_data, &metadata),
+        };
+        png_img.fill_rows_from(nb_rows, &background)?;
+        png_img.recovered_rows = Some(nb_rows);
+    }
+    if options.apply_exif_orientation {
+        if let Some(orientation) = metadata.orientation() {
+            png_img = png_img.apply_orientation(orientation);
+        }
+    }
+    Ok((png_img, metadata))
+}
+
+pub fn decode_metadata(input: &[u8]) -> Result<Metadata, Box<dyn Error>> {
+    let limits = Limits::default();
+    let chunks = parse_chunks_with_limits(input, &limits)?;
+    let (chunks, _) = strip_cgbi(&chunks);
+    let handlers = ChunkHandlers::new();
+    let chunks_valid = chunk::validate_chunk_constraints_with(chunks, &handlers, &limits)?;
+    Metadata::from_chunks_with(chunks_valid, &handlers, &limits)
+}
+
+pub fn decode_no_check(input: &[u8]) -> Result<Png, Box<dyn Error>> {
     match parse_chunks(input) {
         Ok((_, chunks)) => {
-            let (ihdr_data, inflated_idats) = ihdr_and_idats(&chunks)?;
-            let scanlines = lines_slices(&inflated_idats, ihdr_data.scanline_width());
-            let png_img = unfilter(&ihdr_data, scanlines);
+            let (ihdr_data, inflated) = ihdr_and_idats(&chunks, &Limits::default())?;
+            let scanlines = lines_slices(&inflated.data, ihdr_data.scanline_width()?)?;
+            let mut png_img = unfilter(&ihdr_data, scanlines);
+            png_img.set_zlib_stream(&inflated);
             Ok(png_img)
         }
         Err(e) => Err(format!("{:?}", e).into()),
     }
 }
 
-pub fn decode_no_check_bis(input: &[u8]) -> Result<Png, Box<Error>> {
+pub fn decode_no_check_bis(input: &[u8]) -> Result<Png, Box<dyn Error>> {
     match parse_chunks(input) {
         Ok((_, chunks)) => {
-            let (ihdr_data, mut inflated_idats) = ihdr_and_idats(&chunks)?;
-            let scanlines = lines_num(&inflated_idats, ihdr_data.scanline_width());
-            let png_img = unfilter_bis(&ihdr_data, scanlines, &mut inflated_idats);
+            let (ihdr_data, mut inflated) = ihdr_and_idats(&chunks, &Limits::default())?;
+            let scanlines = lines_num(&inflated.data, ihdr_data.scanline_width()?)?;
+            let mut png_img = unfilter_bis(&ihdr_data, scanlines, &mut inflated.data);
+            png_img.set_zlib_stream(&inflated);
             Ok(png_img)
         }
         Err(e) => Err(format!("{:?}", e).into()),
     }
 }
 
-pub fn decode_verbose(data: &[u8]) -> Result<(), Box<Error>> {
+pub fn decode_verbose(data: &[u8]) -> Result<(), Box<dyn Error>> {
     match parse_chunks(data) {
         Ok((_, chunks)) => {
             let chunks_valid = chunk::validate_chunk_constraints(&chunks)?;
-            let (ihdr_data, inflated_idats) = ihdr_and_idats(&chunks_valid)?;
-            let scanlines = lines_slices(&inflated_idats, ihdr_data.scanline_width());
+            let (ihdr_data, inflated) = ihdr_and_inflated_idats(
+                chunks_valid,
+                &Limits::default(),
+                &MinizInflater,
+                true,
+                false,
+            )?;
+            let inflated_idats = inflated.data;
+            let scanlines = lines_slices(&inflated_idats, ihdr_data.scanline_width()?)?;
+            if let Some(zlib_header) = inflated.zlib_header {
+                println!("{}", zlib_header);
+            }
+            println!("Adler-32 checksum verified: {}", inflated.adler32_verified);
             println!("Inflate image data size: {}", inflated_idats.len());
             display_filters(&scanlines);
             let img = unfilter(&ihdr_data, scanlines);
@@ -91,16 +339,17 @@ pub fn decode_verbose(data: &[u8]) -> Result<(), Box<Error>> {
     Ok(())
 }
 
-pub fn decode_no_check_timed(input: &[u8]) -> Result<Png, Box<Error>> {
+pub fn decode_no_check_timed(input: &[u8]) -> Result<Png, Box<dyn Error>> {
     let mut now = Instant::now();
     match parse_chunks(input) {
         Ok((_, chunks)) => {
-            let (ihdr_data, inflated_idats) = ihdr_and_idats_timed(&chunks, &mut now)?;
+            let (ihdr_data, inflated) = ihdr_and_idats_timed(&chunks, &mut now)?;
             now = Instant::now();
-            let scanlines = lines_slices(&inflated_idats, ihdr_data.scanline_width());
+            let scanlines = lines_slices(&inflated.data, ihdr_data.scanline_width()?)?;
             println!("get_scanlines: {} us", now.elapsed().as_micros());
             now = Instant::now();
-            let png_img = unfilter(&ihdr_data, scanlines);
+            let mut png_img = unfilter(&ihdr_data, scanlines);
+            png_img.set_zlib_stream(&inflated);
             println!("unfilter: {} us", now.elapsed().as_micros());
             Ok(png_img)
         }
@@ -119,16 +368,17 @@ pub fn decode_no_check_timed(input: &[u8]) -> Result<Png, Box<Error>> {
 //     }};
 // }
 
-pub fn decode_no_check_timed_bis(input: &[u8]) -> Result<Png, Box<Error>> {
+pub fn decode_no_check_timed_bis(input: &[u8]) -> Result<Png, Box<dyn Error>> {
     let mut now = Instant::now();
     match parse_chunks(input) {
         Ok((_, chunks)) => {
-            let (ihdr_data, mut inflated_idats) = ihdr_and_idats_timed(&chunks, &mut now)?;
+            let (ihdr_data, mut inflated) = ihdr_and_idats_timed(&chunks, &mut now)?;
             now = Instant::now();
-            let scanlines = lines_num(&inflated_idats, ihdr_data.scanline_width());
+            let scanlines = lines_num(&inflated.data, ihdr_data.scanline_width()?)?;
             println!("get_scanlines: {} us", now.elapsed().as_micros());
             now = Instant::now();
-            let png_img = unfilter_bis(&ihdr_data, scanlines, &mut inflated_idats);
+            let mut png_img = unfilter_bis(&ihdr_data, scanlines, &mut inflated.data);
+            png_img.set_zlib_stream(&inflated);
             println!("unfilter: {} us", now.elapsed().as_micros());
             Ok(png_img)
         }
@@ -136,112 +386,275 @@ pub fn decode_no_check_timed_bis(input: &[u8]) -> Result<Png, Box<Error>> {
     }
 }
 
-pub fn parse_chunks(input: &[u8]) -> IResult<&[u8], Vec<Chunk>> {
+pub fn parse_chunks(input: &[u8]) -> IResult<&[u8], Vec<Chunk<'_>>> {
     let (input, _) = tag(SIGNATURE)(input)?;
     many1(Chunk::parse)(input)
 }
 
+/// Parse chunks, failing with a `LimitError` if there are too many.
+pub fn parse_chunks_with_limits<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+) -> Result<Vec<Chunk<'a>>, Box<dyn Error>> {
+    let max = limits.max_chunks.saturating_add(1);
+    let parsed = tag(SIGNATURE)(input).and_then(|(input, _)| {
+        // One more chunk than the limit to detect it is exceeded.
+        many_m_n(1, max, Chunk::parse)(input)
+    });
+    match parsed {
+        Ok((_, chunks)) => {
+            limits.check_chunks(chunks.len())?;
+            Ok(chunks)
+        }
+        Err(e) => Err(format!("{:?}", e).into()),
+    }
+}
+
+/// Parse the chunks of a possibly truncated file.
+/// The available data of an incomplete last IDAT chunk is kept
+/// and a missing IEND chunk is added.
+pub fn parse_chunks_truncated<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+) -> Result<Vec<Chunk<'a>>, Box<dyn Error>> {
+    let mut chunks = parse_chunks_with_limits(input, limits)?;
+    // Chunks have 12 bytes for their length, type and CRC.
+    let parsed_len: usize = chunks.iter().map(|c| 12 + c.data.len()).sum();
+    let rest = &input[SIGNATURE.len() + parsed_len..];
+    if rest.len() > 8 && &rest[4..8] == b"IDAT" {
+        // The file may be cut inside the CRC, which is not image data.
+        let declared = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        let available = rest.len() - 8;
+        let data = &rest[8..8 + std::cmp::min(declared as usize, available)];
+        let length = u32::try_from(data.len())?;
+        let chunk_type = ChunkType::IDAT;
+        let crc = [0; 4];
+        chunks.push(Chunk {
+            length,
+            chunk_type,
+            data,
+            crc,
+        });
+    }
+    if chunks.last().map(|c| c.chunk_type) != Some(ChunkType::IEND) {
+        chunks.push(Chunk {
+            length: 0,
+            chunk_type: ChunkType::IEND,
+            data: &[],
+            crc: [0xAE, 0x42, 0x60, 0x82],
+        });
+        limits.check_chunks(chunks.len())?;
+    }
+    Ok(chunks)
+}
+
+/// Unfilter the scanlines into pixels,
+/// samples of less than 8 bits being unpacked to one byte each.
 pub fn unfilter(ihdr: &IHDRData, scanlines: Vec<(Filter, &[u8])>) -> Png {
     let width = ihdr.width as usize;
     let height = ihdr.height as usize;
-    let bytes_per_channel = std::cmp::max(1, ihdr.bit_depth as usize / 8);
-    let bpp = bytes_per_channel
-        * match ihdr.color_type {
-            ColorType::Gray => 1,
-            ColorType::GrayAlpha => 2,
-            ColorType::RGB => 3,
-            ColorType::RGBA => 4,
-            ColorType::PLTE => unimplemented!(),
-        };
-    let data = filter::unfilter(width, height, bpp, scanlines);
+    let (row_len, bpp) = filter_layout(ihdr);
+    let rows = filter::unfilter(row_len / bpp, height, bpp, scanlines);
     Png {
         width,
         height,
         color_type: ihdr.color_type,
-        bytes_per_pixel: bpp,
-        data,
+        bytes_per_pixel: ihdr.nb_channels() * std::cmp::max(1, ihdr.bit_depth as usize / 8),
+        data: unpack_samples(ihdr, row_len, rows),
+        data_mismatches: Vec::new(),
+        recovered_rows: None,
+        zlib_header: None,
+        adler32_verified: false,
     }
 }
 
 pub fn unfilter_bis(ihdr: &IHDRData, scanlines: Vec<(Filter, usize)>, inflated: &mut [u8]) -> Png {
     let width = ihdr.width as usize;
     let height = ihdr.height as usize;
-    let bytes_per_channel = std::cmp::max(1, ihdr.bit_depth as usize / 8);
-    let bpp = bytes_per_channel
-        * match ihdr.color_type {
-            ColorType::Gray => 1,
-            ColorType::GrayAlpha => 2,
-            ColorType::RGB => 3,
-            ColorType::RGBA => 4,
-            ColorType::PLTE => unimplemented!(),
-        };
-    let data = filter::unfilter_bis(width, height, bpp, scanlines, inflated);
+    let (row_len, bpp) = filter_layout(ihdr);
+    let rows = filter::unfilter_bis(row_len / bpp, height, bpp, scanlines, inflated);
     Png {
         width,
         height,
         color_type: ihdr.color_type,
-        bytes_per_pixel: bpp,
-        data,
+        bytes_per_pixel: ihdr.nb_channels() * std::cmp::max(1, ihdr.bit_depth as usize / 8),
+        data: unpack_samples(ihdr, row_len, rows),
+        data_mismatches: Vec::new(),
+        recovered_rows: None,
+        zlib_header: None,
+        adler32_verified: false,
     }
 }
 
 // Helpers #####################################################################
 
-pub fn lines_slices(data: &[u8], scanline_width: usize) -> Vec<(Filter, &[u8])> {
+// Bytes of a row without the filter byte, and bytes per pixel of the filters.
+// Filters work on whole bytes, so pixels of less than 8 bits stay packed.
+fn filter_layout(ihdr: &IHDRData) -> (usize, usize) {
+    let row_len = ihdr.scanline_width().map_or(0, |w| w - 1);
+    (row_len, ihdr.bytes_per_pixel())
+}
+
+// Unpack the samples of less than 8 bits of unfiltered rows, one byte each.
+fn unpack_samples(ihdr: &IHDRData, row_len: usize, rows: Vec<u8>) -> Vec<u8> {
+    let bit_depth = ihdr.bit_depth as usize;
+    if bit_depth >= 8 || row_len == 0 {
+        return rows;
+    }
+    let width = ihdr.width as usize;
+    let mask = (1 << bit_depth) - 1;
+    let mut samples = Vec::with_capacity(width * ihdr.height as usize);
+    for row in rows.chunks(row_len) {
+        samples.extend((0..width).map(|x| {
+            let bit = x * bit_depth;
+            (row[bit / 8] >> (8 - bit_depth - bit % 8)) & mask
+        }));
+    }
+    samples
+}
+
+/// Split the image data in scanlines, failing on an invalid filter type.
+pub fn lines_slices(data: &[u8], scanline_width: usize) -> Result<Vec<(Filter, &[u8])>, String> {
     let nb_scanlines = data.len() / scanline_width;
     (0..nb_scanlines)
         .map(|i| i * scanline_width)
         .map(|start| {
-            (
-                Filter::try_from(data[start]).expect("Incorrect filter type"),
+            Ok((
+                Filter::try_from(data[start])?,
                 &data[start + 1..start + scanline_width],
-            )
+            ))
         })
         .collect()
 }
 
-pub fn lines_num(data: &[u8], scanline_width: usize) -> Vec<(Filter, usize)> {
+/// Same as `lines_slices`, with the start index of each scanline.
+pub fn lines_num(data: &[u8], scanline_width: usize) -> Result<Vec<(Filter, usize)>, String> {
     let nb_scanlines = data.len() / scanline_width;
     (0..nb_scanlines)
         .map(|i| i * scanline_width)
-        .map(|start| {
-            (
-                Filter::try_from(data[start]).expect("Incorrect filter type"),
-                start + 1,
-            )
-        })
+        .map(|start| Ok((Filter::try_from(data[start])?, start + 1)))
         .collect()
 }
 
-fn display_filters(scanlines: &[(Filter, &[u8])]) -> () {
+fn display_filters(scanlines: &[(Filter, &[u8])]) {
     scanlines
         .iter()
         .enumerate()
         .for_each(|(i, (filter, _))| print!("{} {:?}, ", i, filter));
-    println!("");
+    println!();
 }
 
-fn ihdr_and_idats(chunks: &[Chunk]) -> Result<(IHDRData, Vec<u8>), Box<Error>> {
-    let ihdr_chunk = &chunks[0];
-    let ihdr_data = chunk_data::parse_ihdr_data(ihdr_chunk.data).unwrap().1;
+/// Pixel bytes of the bKGD color, opaque, or zeros without bKGD.
+fn background_pixel(ihdr: &IHDRData, metadata: &Metadata) -> Vec<u8> {
+    let sample = |v: u16| match ihdr.bit_depth {
+        16 => v.to_be_bytes().to_vec(),
+        _ => vec![v as u8],
+    };
+    let opaque = sample(u16::MAX >> (16 - ihdr.bit_depth.min(16)));
+    let mut pixel = match (&metadata.background, ihdr.color_type) {
+        (Some(Background::Gray(v)), ColorType::Gray | ColorType::GrayAlpha) => sample(*v),
+        (Some(Background::RGB(rgb)), ColorType::RGB | ColorType::RGBA) => {
+            rgb.iter().flat_map(|v| sample(*v)).collect()
+        }
+        (Some(Background::Palette(index)), ColorType::PLTE) => vec![*index],
+        _ => return vec![0; ihdr.nb_channels() * opaque.len()],
+    };
+    if matches!(ihdr.color_type, ColorType::GrayAlpha | ColorType::RGBA) {
+        pixel.extend(opaque);
+    }
+    pixel
+}
+
+/// Length mismatches of the inflated data, cut to the `expected` length.
+fn data_mismatches(expected: usize, inflated: &mut InflatedIdats) -> Vec<DataMismatch> {
+    let mut mismatches = Vec::new();
+    let actual = inflated.data.len();
+    if actual < expected || inflated.truncated {
+        mismatches.push(DataMismatch::TooShort {
+            expected,
+            actual,
+            zlib_truncated: inflated.truncated,
+        });
+    }
+    if actual > expected {
+        mismatches.push(DataMismatch::TooLong { expected, actual });
+        inflated.data.truncate(expected);
+    }
+    if inflated.trailing_bytes > 0 {
+        let bytes = inflated.trailing_bytes;
+        mismatches.push(DataMismatch::TrailingZlibData { bytes });
+    }
+    mismatches
+}
+
+// Apple CgBI files start with a CgBI chunk before IHDR,
+// which is removed so that the remaining chunks are validated as usual.
+fn strip_cgbi<'a, 'c>(chunks: &'a [Chunk<'c>]) -> (&'a [Chunk<'c>], bool) {
+    match chunks.first() {
+        Some(c) if c.chunk_type == ChunkType::Unknown(*b"CgBI") => (&chunks[1..], true),
+        _ => (chunks, false),
+    }
+}
+
+/// Fail for Apple CgBI files, which only `decode_with_options`
+/// and `decode_metadata` support.
+pub(crate) fn reject_cgbi(chunks: &[Chunk]) -> Result<(), String> {
+    match strip_cgbi(chunks) {
+        (_, true) => Err("Apple CgBI files are not supported".to_string()),
+        (_, false) => Ok(()),
+    }
+}
+
+fn ihdr_and_idats(
+    chunks: &[Chunk],
+    limits: &Limits,
+) -> Result<(IHDRData, InflatedIdats), Box<dyn Error>> {
+    let (ihdr_data, mut inflated) =
+        ihdr_and_inflated_idats(chunks, limits, &MinizInflater, true, false)?;
+    if inflated.truncated {
+        return Err("The zlib stream of the image data is truncated".into());
+    }
+    inflated.data.truncate(ihdr_data.image_data_len()?);
+    Ok((ihdr_data, inflated))
+}
+
+fn ihdr_and_inflated_idats(
+    chunks: &[Chunk],
+    limits: &Limits,
+    inflater: &dyn Inflater,
+    verify_adler32: bool,
+    raw_deflate: bool,
+) -> Result<(IHDRData, InflatedIdats), Box<dyn Error>> {
+    let ihdr_data = match chunks.first() {
+        Some(c) if c.chunk_type == ChunkType::IHDR => chunk_data::parse_valid_ihdr_data(c.data)?,
+        _ => return Err("The first chunk must be IHDR".into()),
+    };
+    limits.check_ihdr(&ihdr_data)?;
     let idats: Vec<_> = chunks
         .iter()
         .filter(|c| c.chunk_type == ChunkType::IDAT)
         .collect();
-    let len = ihdr_data.height as usize * ihdr_data.scanline_width();
-    let inflated_idats = chunk_data::inflate_idats(&idats[..], len)?;
-    Ok((ihdr_data, inflated_idats))
+    let len = ihdr_data.image_data_len()?;
+    let max_len = limits.max_output_bytes;
+    let inflated = if raw_deflate {
+        chunk_data::inflate_raw_idats(&idats[..], len, max_len)?
+    } else {
+        chunk_data::inflate_idats_with(inflater, &idats[..], len, max_len, verify_adler32)?
+    };
+    Ok((ihdr_data, inflated))
 }
 
 fn ihdr_and_idats_timed(
     chunks: &[Chunk],
     now: &mut Instant,
-) -> Result<(IHDRData, Vec<u8>), Box<Error>> {
+) -> Result<(IHDRData, InflatedIdats), Box<dyn Error>> {
     println!("parse_chunks: {} us", now.elapsed().as_micros());
     *now = Instant::now();
 
-    let ihdr_chunk = &chunks[0];
-    let ihdr_data = chunk_data::parse_ihdr_data(ihdr_chunk.data).unwrap().1;
+    let ihdr_data = match chunks.first() {
+        Some(c) if c.chunk_type == ChunkType::IHDR => chunk_data::parse_valid_ihdr_data(c.data)?,
+        _ => return Err("The first chunk must be IHDR".into()),
+    };
     println!("parse_ihdr_data: {} us", now.elapsed().as_micros());
     *now = Instant::now();
 
@@ -252,8 +665,12 @@ fn ihdr_and_idats_timed(
     println!("filter idats: {} us", now.elapsed().as_micros());
     *now = Instant::now();
 
-    let len = ihdr_data.height as usize * ihdr_data.scanline_width();
-    let inflated_idats = chunk_data::inflate_idats(&idats[..], len)?;
+    let len = ihdr_data.image_data_len()?;
+    let mut inflated = chunk_data::inflate_idats_full(&idats[..], len, usize::MAX, true)?;
+    if inflated.truncated {
+        return Err("The zlib stream of the image data is truncated".into());
+    }
+    inflated.data.truncate(len);
     println!("inflate idats: {} us", now.elapsed().as_micros());
-    Ok((ihdr_data, inflated_idats))
+    Ok((ihdr_data, inflated))
 }
@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::error::Error;
+
+// Internal imports
+use crate::chunk::{self, ChunkType};
+use crate::chunk_data::{ChunkData, IccProfile, InternationalText, PhysicalPixelDimension, Text};
+use crate::encoder;
+use crate::limits::Limits;
+use crate::png::{self, SIGNATURE};
+
+// TYPES #######################################################################
+
+/// Edits applied by `rewrite`, in the order of the fields.
+#[derive(Debug, Clone, Default)]
+pub struct Edits {
+    /// Remove every ancillary chunk, known or not.
+    pub strip_ancillary: bool,
+    /// Keywords of the textual chunks (tEXt, zTXt, iTXt) to remove.
+    pub remove_texts: Vec<String>,
+    /// Texts added as (keyword, text), replacing textual chunks with the
+    /// same keyword. Written as tEXt if the text is ASCII, iTXt otherwise.
+    pub set_texts: Vec<(String, String)>,
+    /// Texts added as iTXt, replacing textual chunks with the same keyword.
+    pub set_international_texts: Vec<InternationalText>,
+    /// Horizontal and vertical dots per inch, replacing the pHYs chunk.
+    pub dpi: Option<(f64, f64)>,
+    /// Profile replacing the iCCP and sRGB chunks.
+    pub icc_profile: Option<IccProfile>,
+}
+
+// FUNCTIONS ###################################################################
+
+/// Apply edits to the ancillary chunks of a PNG image, without decoding it.
+/// Critical chunks, including IDAT, are copied verbatim, so unknown chunks
+/// are kept even if they are unsafe to copy, unless stripped.
+/// CRCs are recomputed and the chunk ordering of the output is checked.
+pub fn rewrite(input: &[u8], edits: &Edits) -> Result<Vec<u8>, Box<dyn Error>> {
+    let chunks = png::parse_chunks_with_limits(input, &Limits::default())?;
+    png::reject_cgbi(&chunks)?;
+    let chunks = chunk::validate_chunk_constraints(&chunks)?;
+
+    // Keywords replaced by new texts are removed first.
+    let mut new_texts = Vec::new();
+    for (keyword, text) in edits.set_texts.iter() {
+        check_keyword(keyword)?;
+        if text.is_ascii() {
+            let data = ChunkData::tEXt(Text {
+                keyword: keyword.clone(),
+                text: text.clone(),
+            });
+            new_texts.push((keyword.as_str(), *b"tEXt", data.to_bytes()));
+        } else {
+            let itxt = ChunkData::iTXt(InternationalText {
+                keyword: keyword.clone(),
+                compressed: false,
+                method: 0,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+                text: text.clone(),
+                zlib_stream: None,
+            });
+            new_texts.push((keyword.as_str(), *b"iTXt", itxt.to_bytes()));
+        }
+    }
+    for itxt in edits.set_international_texts.iter() {
+        check_keyword(&itxt.keyword)?;
+        let data = ChunkData::iTXt(itxt.clone()).to_bytes();
+        new_texts.push((itxt.keyword.as_str(), *b"iTXt", data));
+    }
+    let removed_keywords: Vec<&str> = edits
+        .remove_texts
+        .iter()
+        .map(|k| k.as_str())
+        .chain(new_texts.iter().map(|(k, _, _)| *k))
+        .collect();
+
+    let is_kept = |chunk_type: ChunkType, data: &[u8]| match chunk_type {
+        _ if edits.strip_ancillary => chunk_type.is_critical(),
+        ChunkType::tEXt | ChunkType::zTXt | ChunkType::iTXt => {
+            // Keywords are Latin-1 encoded.
+            let keyword = data.split(|b| *b == 0).next().unwrap_or_default();
+            let keyword: String = keyword.iter().map(|&b| char::from(b)).collect();
+            !removed_keywords.contains(&keyword.as_str())
+        }
+        ChunkType::pHYs => edits.dpi.is_none(),
+        ChunkType::iCCP | ChunkType::sRGB => edits.icc_profile.is_none(),
+        _ => true,
+    };
+    let mut out_chunks: Vec<([u8; 4], Cow<[u8]>)> = chunks
+        .iter()
+        .filter(|c| is_kept(c.chunk_type, c.data))
+        .map(|c| (c.chunk_type.name(), Cow::Borrowed(c.data)))
+        .collect();
+
+    // The iCCP chunk must be before PLTE and IDAT, the others before IDAT.
+    let position = |chunks: &[([u8; 4], Cow<[u8]>)], types: &[&[u8; 4]]| {
+        chunks
+            .iter()
+            .position(|(t, _)| types.contains(&t))
+            .unwrap_or(chunks.len() - 1)
+    };
+    if let Some(profile) = &edits.icc_profile {
+        let at = position(&out_chunks, &[b"PLTE", b"IDAT"]);
+        check_keyword(&profile.name)?;
+        let data = ChunkData::iCCP(profile.clone()).to_bytes();
+        out_chunks.insert(at, (*b"iCCP", Cow::Owned(data)));
+    }
+    if let Some((x_dpi, y_dpi)) = edits.dpi {
+        let at = position(&out_chunks, &[b"IDAT"]);
+        let phys = PhysicalPixelDimension::from_dpi(x_dpi, y_dpi);
+        let data = ChunkData::pHYs(phys).to_bytes();
+        out_chunks.insert(at, (*b"pHYs", Cow::Owned(data)));
+    }
+    for (_, chunk_type, data) in new_texts.into_iter() {
+        let at = position(&out_chunks, &[b"IDAT"]);
+        out_chunks.insert(at, (chunk_type, Cow::Owned(data)));
+    }
+
+    let mut output = SIGNATURE.to_vec();
+    for (chunk_type, data) in out_chunks.iter() {
+        encoder::write_chunk(&mut output, *chunk_type, data);
+    }
+    let (_, written) = png::parse_chunks(&output).map_err(|e| format!("{:?}", e))?;
+    chunk::validate_chunk_constraints(&written)?;
+    Ok(output)
+}
+
+// Helpers #####################################################################
+
+// Keywords are restricted to 1 to 79 printable ASCII characters,
+// which are stored the same in Latin-1 and UTF-8.
+fn check_keyword(keyword: &str) -> Result<(), String> {
+    let printable = keyword.bytes().all(|b| (32..127).contains(&b));
+    if keyword.is_empty() || keyword.len() > 79 || !printable {
+        Err(format!(
+            "Keyword {:?} must have 1 to 79 printable ASCII characters",
+            keyword
+        ))
+    } else {
+        Ok(())
+    }
+}
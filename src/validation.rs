@@ -0,0 +1,308 @@
+use std::collections::HashSet;
+
+// Internal imports
+use crate::chunk::{self, Chunk, ChunkType};
+use crate::chunk_data::{self, IHDRData};
+use crate::color::ColorType;
+use crate::custom_chunk::ChunkHandlers;
+use crate::limits::Limits;
+use crate::png;
+
+// TYPES #######################################################################
+
+/// A spec violation, located by the index of the chunk
+/// and its byte offset in the PNG file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub chunk_index: usize,
+    pub offset: usize,
+    pub chunk_type: Option<ChunkType>,
+    pub message: String,
+}
+
+// Length of the PNG signature and of the chunk fields other than data.
+const SIGNATURE_LENGTH: usize = 8;
+const CHUNK_OVERHEAD: usize = 12;
+
+// FUNCTIONS ###################################################################
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.chunk_type {
+            Some(t) => write!(
+                f,
+                "chunk {} ({}) at byte {}: {}",
+                self.chunk_index, t, self.offset, self.message
+            ),
+            None => write!(f, "at byte {}: {}", self.offset, self.message),
+        }
+    }
+}
+
+/// Byte offset of each chunk, followed by the offset after the last chunk.
+pub fn chunk_offsets(chunks: &[Chunk]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(chunks.len() + 1);
+    let mut offset = SIGNATURE_LENGTH;
+    offsets.push(offset);
+    for chunk in chunks.iter() {
+        offset += CHUNK_OVERHEAD + chunk.data.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Parse a PNG file and return all its spec violations,
+/// including bytes after the IEND chunk.
+pub fn validate_png(input: &[u8], handlers: &ChunkHandlers) -> Result<Vec<Violation>, String> {
+    let (trailing, chunks) = png::parse_chunks(input).map_err(|e| format!("{:?}", e))?;
+    png::reject_cgbi(&chunks)?;
+    let mut violations = validate_chunks(&chunks, handlers);
+    if !trailing.is_empty() {
+        violations.push(Violation {
+            chunk_index: chunks.len(),
+            offset: input.len() - trailing.len(),
+            chunk_type: None,
+            message: format!("{} bytes after the last chunk", trailing.len()),
+        });
+    }
+    Ok(violations)
+}
+
+/// Check chunks ordering and payloads against the IHDR chunk.
+/// Chunks are assumed to follow the PNG signature to compute byte offsets.
+pub fn validate_chunks(chunks: &[Chunk], handlers: &ChunkHandlers) -> Vec<Violation> {
+    let (mut violations, ancillary) = validate_chunks_split(chunks, handlers, &Limits::default());
+    violations.extend(ancillary);
+    violations.sort_by_key(|v| v.chunk_index);
+    violations
+}
+
+/// Same as `validate_chunks`, but invalid payloads of ancillary chunks
+/// are returned separately since decoders can safely ignore these chunks.
+/// Compressed chunks are inflated within the given limits.
+pub(crate) fn validate_chunks_split(
+    chunks: &[Chunk],
+    handlers: &ChunkHandlers,
+    limits: &Limits,
+) -> (Vec<Violation>, Vec<Violation>) {
+    let mut violations = Vec::new();
+    let mut ancillary = Vec::new();
+    let mut sets = chunk::start_validation_sets();
+    let mut offset = SIGNATURE_LENGTH;
+    // Checks depending on IHDR are skipped if it is invalid.
+    let ihdr = match chunks.first() {
+        Some(c) if c.chunk_type == ChunkType::IHDR => {
+            match chunk_data::parse_valid_ihdr_data(c.data) {
+                Ok(ihdr) => Some(ihdr),
+                Err(message) => {
+                    violations.push(Violation {
+                        chunk_index: 0,
+                        offset,
+                        chunk_type: Some(ChunkType::IHDR),
+                        message,
+                    });
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let palette_len = chunks
+        .iter()
+        .find(|c| c.chunk_type == ChunkType::PLTE)
+        .map(|c| c.data.len() / 3);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let violation = |message: String| Violation {
+            chunk_index: index,
+            offset,
+            chunk_type: Some(chunk.chunk_type),
+            message,
+        };
+        // Chunk ordering, an invalid chunk does not change the state.
+        match chunk::validate_chunk(sets.clone(), chunk, handlers) {
+            Ok(new_sets) => sets = new_sets,
+            Err(message) => violations.push(violation(message)),
+        }
+        // Chunk payload
+        if let Err(message) = check_payload(chunk, ihdr.as_ref(), palette_len, &sets.0, limits) {
+            if chunk.chunk_type.is_ancillary() {
+                ancillary.push(violation(message));
+            } else {
+                violations.push(violation(message));
+            }
+        }
+        offset += CHUNK_OVERHEAD + chunk.data.len();
+    }
+    let present: HashSet<ChunkType> = chunks.iter().map(|c| c.chunk_type).collect();
+    let mut report_global = |message: &str| {
+        violations.push(Violation {
+            chunk_index: chunks.len(),
+            offset,
+            chunk_type: None,
+            message: message.to_string(),
+        })
+    };
+    if !present.contains(&ChunkType::IDAT) {
+        report_global("There must be at least one IDAT chunk");
+    }
+    if chunks.last().map(|c| c.chunk_type) != Some(ChunkType::IEND) {
+        report_global("The last chunk must be IEND");
+    }
+    if let Some(ihdr) = &ihdr {
+        if let ColorType::PLTE = ihdr.color_type {
+            if !present.contains(&ChunkType::PLTE) {
+                report_global("PLTE is required for indexed-color images");
+            }
+        }
+    }
+    (violations, ancillary)
+}
+
+// Spec: http://www.libpng.org/pub/png/spec/1.2/png-1.2-pdg.html#C.Summary-of-standard-chunks
+// `present` contains the chunks validated so far, including this one.
+fn check_payload(
+    chunk: &Chunk,
+    ihdr: Option<&IHDRData>,
+    palette_len: Option<usize>,
+    present: &HashSet<ChunkType>,
+    limits: &Limits,
+) -> Result<(), String> {
+    let len = chunk.data.len();
+    let expect_len = |expected: usize| {
+        if len == expected {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} data must be {} bytes long, not {}",
+                chunk.chunk_type, expected, len
+            ))
+        }
+    };
+    match chunk.chunk_type {
+        // IHDR is checked separately
+        ChunkType::IHDR => return Ok(()),
+        ChunkType::IEND => expect_len(0)?,
+        ChunkType::gAMA => expect_len(4)?,
+        ChunkType::cHRM => expect_len(32)?,
+        ChunkType::sRGB => expect_len(1)?,
+        ChunkType::pHYs => expect_len(9)?,
+        ChunkType::tIME => expect_len(7)?,
+        ChunkType::cICP => expect_len(4)?,
+        ChunkType::mDCv => expect_len(24)?,
+        ChunkType::cLLi => expect_len(8)?,
+        ChunkType::oFFs => expect_len(9)?,
+        ChunkType::sTER => expect_len(1)?,
+        ChunkType::gIFg => expect_len(4)?,
+        ChunkType::acTL => expect_len(8)?,
+        ChunkType::fcTL => expect_len(26)?,
+        _ => {}
+    }
+    // Only one of sRGB and iCCP
+    if (chunk.chunk_type == ChunkType::sRGB && present.contains(&ChunkType::iCCP))
+        || (chunk.chunk_type == ChunkType::iCCP && present.contains(&ChunkType::sRGB))
+    {
+        return Err("sRGB and iCCP chunks must not both be present".to_string());
+    }
+    if chunk.chunk_type == ChunkType::hIST && palette_len.is_none() {
+        return Err("hIST requires a PLTE chunk".to_string());
+    }
+    if let Some(ihdr) = ihdr {
+        check_payload_with_ihdr(chunk, ihdr, palette_len)?;
+    }
+    if let ChunkType::tRNS | ChunkType::Unknown(_) = chunk.chunk_type {
+        Ok(())
+    } else {
+        match chunk_data::parse_chunk_data_with_limits(chunk, limits) {
+            Ok((_, chunk_data::ChunkData::tIME(time))) => time.validate(),
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Invalid {} data: {:?}", chunk.chunk_type, e)),
+        }
+    }
+}
+
+fn check_payload_with_ihdr(
+    chunk: &Chunk,
+    ihdr: &IHDRData,
+    palette_len: Option<usize>,
+) -> Result<(), String> {
+    let len = chunk.data.len();
+    let color_type = ihdr.color_type;
+    let is_gray = matches!(color_type, ColorType::Gray | ColorType::GrayAlpha);
+    let has_alpha = matches!(color_type, ColorType::GrayAlpha | ColorType::RGBA);
+    match chunk.chunk_type {
+        ChunkType::PLTE => {
+            if is_gray {
+                return Err("PLTE is forbidden for grayscale images".to_string());
+            }
+            let max_entries = match color_type {
+                ColorType::PLTE => 1 << ihdr.bit_depth.min(8),
+                _ => 256,
+            };
+            if len == 0 || len % 3 != 0 || len / 3 > max_entries {
+                return Err(format!(
+                    "PLTE data length {} must be a multiple of 3 with 1 to {} entries",
+                    len, max_entries
+                ));
+            }
+        }
+        ChunkType::tRNS => match color_type {
+            _ if has_alpha => {
+                return Err("tRNS is forbidden for images with an alpha channel".to_string());
+            }
+            ColorType::Gray if len != 2 => {
+                return Err("tRNS data must be 2 bytes long for grayscale images".to_string());
+            }
+            ColorType::RGB if len != 6 => {
+                return Err("tRNS data must be 6 bytes long for RGB images".to_string());
+            }
+            ColorType::PLTE if len > palette_len.unwrap_or(0) => {
+                return Err("tRNS has more entries than PLTE".to_string());
+            }
+            _ => {}
+        },
+        ChunkType::bKGD => match color_type {
+            ColorType::PLTE => {
+                if len != 1 {
+                    return Err("bKGD data must be 1 byte long for indexed images".to_string());
+                }
+                if chunk.data[0] as usize >= palette_len.unwrap_or(0) {
+                    return Err("bKGD palette index is out of the palette".to_string());
+                }
+            }
+            _ if is_gray && len != 2 => {
+                return Err("bKGD data must be 2 bytes long for grayscale images".to_string());
+            }
+            _ if !is_gray && len != 6 => {
+                return Err("bKGD data must be 6 bytes long for color images".to_string());
+            }
+            _ => {}
+        },
+        ChunkType::sBIT => {
+            let (expected, sample_depth) = match color_type {
+                ColorType::Gray => (1, ihdr.bit_depth),
+                ColorType::RGB => (3, ihdr.bit_depth),
+                ColorType::PLTE => (3, 8),
+                ColorType::GrayAlpha => (2, ihdr.bit_depth),
+                ColorType::RGBA => (4, ihdr.bit_depth),
+            };
+            if len != expected {
+                return Err(format!("sBIT data must be {} bytes long", expected));
+            }
+            if chunk.data.iter().any(|&b| b == 0 || b > sample_depth) {
+                return Err(format!("sBIT values must be in 1-{}", sample_depth));
+            }
+        }
+        ChunkType::hIST => {
+            if let Some(palette_len) = palette_len {
+                if len != 2 * palette_len {
+                    return Err(format!(
+                        "hIST must have {} entries, one per PLTE entry",
+                        palette_len
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+// TYPES #######################################################################
+
+/// Fields of the 2 bytes header starting a zlib stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ZlibHeader {
+    /// Size of the LZ77 window in bytes, from 256 to 32768.
+    pub window_size: usize,
+    pub level: CompressionLevel,
+    /// Not allowed in PNG images.
+    pub preset_dictionary: bool,
+}
+
+/// Compression level hint written by the encoder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompressionLevel {
+    Fastest,
+    Fast,
+    Default,
+    Maximum,
+}
+
+// FUNCTIONS ###################################################################
+
+impl ZlibHeader {
+    /// Parse and check the CMF and FLG bytes of a zlib stream.
+    pub fn parse(cmf: u8, flg: u8) -> Result<ZlibHeader, String> {
+        if (u16::from(cmf) * 256 + u16::from(flg)) % 31 != 0 {
+            return Err(format!(
+                "Invalid zlib header check bits {:#04x} {:#04x}",
+                cmf, flg
+            ));
+        }
+        let method = cmf & 0x0F;
+        if method != 8 {
+            return Err(format!(
+                "zlib compression method {} is not deflate (8)",
+                method
+            ));
+        }
+        let window_bits = cmf >> 4;
+        if window_bits > 7 {
+            return Err(format!("zlib window size 2^{} is too big", window_bits + 8));
+        }
+        let level = match flg >> 6 {
+            0 => CompressionLevel::Fastest,
+            1 => CompressionLevel::Fast,
+            2 => CompressionLevel::Default,
+            _ => CompressionLevel::Maximum,
+        };
+        Ok(ZlibHeader {
+            window_size: 1 << (window_bits + 8),
+            level,
+            preset_dictionary: flg & 0x20 != 0,
+        })
+    }
+}
+
+impl std::fmt::Display for ZlibHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "zlib window size: {} bytes, compression level: {:?}",
+            self.window_size, self.level
+        )?;
+        if self.preset_dictionary {
+            write!(f, ", preset dictionary")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+mod common;
+
+use std::time::Duration;
+
+use png_decoder::apng::{decode_animation, encode_animation, AnimationOptions, Frame};
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::filter::FilterStrategy;
+use png_decoder::png;
+use png_decoder::validation::validate_png;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+const CLEAR: [u8; 4] = [0; 4];
+
+// Frame control of a region, with (dispose_op, blend_op).
+fn fctl(sequence: u32, region: (u32, u32, u32, u32), delay: (u16, u16), ops: (u8, u8)) -> Vec<u8> {
+    let (x, y, width, height) = region;
+    let mut data = Vec::new();
+    for n in [sequence, width, height, x, y].iter() {
+        data.extend_from_slice(&n.to_be_bytes());
+    }
+    data.extend_from_slice(&delay.0.to_be_bytes());
+    data.extend_from_slice(&delay.1.to_be_bytes());
+    data.extend_from_slice(&[ops.0, ops.1]);
+    data
+}
+
+fn actl(num_frames: u32, num_plays: u32) -> Vec<u8> {
+    let mut data = num_frames.to_be_bytes().to_vec();
+    data.extend_from_slice(&num_plays.to_be_bytes());
+    data
+}
+
+// Zlib stream of 8 bits RGBA pixels.
+fn rgba(width: usize, pixels: &[[u8; 4]]) -> Vec<u8> {
+    let scanlines: Vec<u8> = pixels
+        .chunks(width)
+        .flat_map(|row| std::iter::once(0).chain(row.iter().flatten().cloned()))
+        .collect();
+    common::zlib(&scanlines)
+}
+
+fn fdat(sequence: u32, width: usize, pixels: &[[u8; 4]]) -> Vec<u8> {
+    let mut data = sequence.to_be_bytes().to_vec();
+    data.extend(rgba(width, pixels));
+    data
+}
+
+fn pixels(frame: &Frame) -> Vec<[u8; 4]> {
+    frame
+        .data
+        .chunks(4)
+        .map(|p| [p[0], p[1], p[2], p[3]])
+        .collect()
+}
+
+fn decode_frames(file: &[u8]) -> Vec<Frame> {
+    decode_animation(file)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn still_image_is_a_single_frame() {
+    let header = common::ihdr(2, 1, 8, 6);
+    let idat = rgba(2, &[RED, GREEN]);
+    let file = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])]);
+    let animation = decode_animation(&file).unwrap();
+    assert_eq!((animation.width, animation.height), (2, 1));
+    assert_eq!((animation.num_frames(), animation.num_plays), (1, 0));
+    let frames = decode_frames(&file);
+    assert_eq!(pixels(&frames[0]), vec![RED, GREEN]);
+    assert_eq!(frames[0].delay, Duration::from_secs(0));
+}
+
+#[test]
+fn default_image_outside_of_animation() {
+    let header = common::ihdr(1, 1, 8, 6);
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"acTL", &actl(2, 3)),
+        (b"IDAT", &rgba(1, &[RED])),
+        (b"fcTL", &fctl(0, (0, 0, 1, 1), (1, 10), (0, 0))),
+        (b"fdAT", &fdat(1, 1, &[GREEN])),
+        (b"fcTL", &fctl(2, (0, 0, 1, 1), (1, 10), (0, 0))),
+        (b"fdAT", &fdat(3, 1, &[BLUE])),
+        (b"IEND", &[]),
+    ]);
+    let animation = decode_animation(&file).unwrap();
+    assert_eq!((animation.num_frames(), animation.num_plays), (2, 3));
+    let frames = decode_frames(&file);
+    assert_eq!(pixels(&frames[0]), vec![GREEN]);
+    assert_eq!(pixels(&frames[1]), vec![BLUE]);
+}
+
+#[test]
+fn dispose_and_blend_operations() {
+    let header = common::ihdr(2, 2, 8, 6);
+    let translucent_white = [255, 255, 255, 128];
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"acTL", &actl(5, 0)),
+        // Red background, kept.
+        (b"fcTL", &fctl(0, (0, 0, 2, 2), (0, 0), (0, 0))),
+        (b"IDAT", &rgba(2, &[RED; 4])),
+        // Green bottom right pixel, cleared afterwards.
+        (b"fcTL", &fctl(1, (1, 1, 1, 1), (0, 0), (1, 0))),
+        (b"fdAT", &fdat(2, 1, &[GREEN])),
+        // Blue top left pixel, reverted afterwards.
+        (b"fcTL", &fctl(3, (0, 0, 1, 1), (0, 0), (2, 0))),
+        (b"fdAT", &fdat(4, 1, &[BLUE])),
+        // Translucent white blended over the bottom row.
+        (b"fcTL", &fctl(5, (0, 1, 2, 1), (0, 0), (0, 1))),
+        (b"fdAT", &fdat(6, 2, &[translucent_white, CLEAR])),
+        // Transparent pixel replacing the top right one.
+        (b"fcTL", &fctl(7, (1, 0, 1, 1), (0, 0), (0, 0))),
+        (b"fdAT", &fdat(8, 1, &[CLEAR])),
+        (b"IEND", &[]),
+    ]);
+    let frames = decode_frames(&file);
+    let expected = vec![
+        vec![RED, RED, RED, RED],
+        vec![RED, RED, RED, GREEN],
+        vec![BLUE, RED, RED, CLEAR],
+        vec![RED, RED, [255, 128, 128, 255], CLEAR],
+        vec![RED, CLEAR, [255, 128, 128, 255], CLEAR],
+    ];
+    let frames: Vec<_> = frames.iter().map(pixels).collect();
+    assert_eq!(frames, expected);
+}
+
+#[test]
+fn first_frame_disposed_to_previous_is_cleared() {
+    let header = common::ihdr(1, 1, 8, 6);
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"acTL", &actl(2, 0)),
+        (b"fcTL", &fctl(0, (0, 0, 1, 1), (0, 0), (2, 0))),
+        (b"IDAT", &rgba(1, &[RED])),
+        (b"fcTL", &fctl(1, (0, 0, 1, 1), (0, 0), (0, 1))),
+        (b"fdAT", &fdat(2, 1, &[[0, 0, 255, 0]])),
+        (b"IEND", &[]),
+    ]);
+    let frames = decode_frames(&file);
+    assert_eq!(pixels(&frames[0]), vec![RED]);
+    assert_eq!(pixels(&frames[1]), vec![CLEAR]);
+}
+
+#[test]
+fn delays() {
+    let header = common::ihdr(1, 1, 8, 6);
+    let delays = [(1, 0), (3, 1000), (1, 3), (0, 10), (65535, 1)];
+    let mut chunks = vec![(*b"IHDR", header), (*b"acTL", actl(delays.len() as u32, 1))];
+    for (i, delay) in delays.iter().enumerate() {
+        // The default image data has no sequence number.
+        let sequence = (2 * i as u32).saturating_sub(1);
+        chunks.push((*b"fcTL", fctl(sequence, (0, 0, 1, 1), *delay, (0, 0))));
+        if i == 0 {
+            chunks.push((*b"IDAT", rgba(1, &[RED])));
+        } else {
+            chunks.push((*b"fdAT", fdat(sequence + 1, 1, &[RED])));
+        }
+    }
+    chunks.push((*b"IEND", Vec::new()));
+    let chunks: Vec<(&[u8; 4], &[u8])> = chunks.iter().map(|(t, d)| (t, d.as_slice())).collect();
+    let frames = decode_frames(&common::png_file(&chunks));
+    let delays: Vec<Duration> = frames.iter().map(|f| f.delay).collect();
+    assert_eq!(delays[0], Duration::from_millis(10));
+    assert_eq!(delays[1], Duration::from_millis(3));
+    assert_eq!(delays[2].as_millis(), 333);
+    assert_eq!(delays[3], Duration::from_secs(0));
+    assert_eq!(delays[4], Duration::from_secs(65535));
+}
+
+#[test]
+fn palette_frames() {
+    let header = common::ihdr(2, 1, 8, 3);
+    let palette = [255, 0, 0, 0, 255, 0];
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"PLTE", &palette),
+        (b"tRNS", &[255, 0]),
+        (b"acTL", &actl(2, 0)),
+        (b"fcTL", &fctl(0, (0, 0, 2, 1), (0, 0), (0, 0))),
+        (b"IDAT", &common::zlib(&[0, 0, 0])),
+        (b"fcTL", &fctl(1, (0, 0, 2, 1), (0, 0), (0, 1))),
+        (b"fdAT", &{
+            let mut data = 2_u32.to_be_bytes().to_vec();
+            data.extend(common::zlib(&[0, 1, 1]));
+            data
+        }),
+        (b"IEND", &[]),
+    ]);
+    let frames = decode_frames(&file);
+    assert_eq!(pixels(&frames[0]), vec![RED, RED]);
+    // Transparent green pixels are blended over the red ones.
+    assert_eq!(pixels(&frames[1]), vec![RED, RED]);
+}
+
+#[test]
+fn invalid_transparency_is_ignored() {
+    let file = common::gray_image(2, &[0, 255], &[(b"tRNS", &[0])]);
+    let frames = decode_frames(&file);
+    assert_eq!(pixels(&frames[0]), vec![[0, 0, 0, 255], [255; 4]]);
+}
+
+#[test]
+fn invalid_animations() {
+    let header = common::ihdr(2, 2, 8, 6);
+    let idat = rgba(2, &[RED; 4]);
+    let whole = fctl(0, (0, 0, 2, 2), (0, 0), (0, 0));
+    let build = |chunks: &[(&[u8; 4], &[u8])]| {
+        let mut all: Vec<(&[u8; 4], &[u8])> = vec![(b"IHDR", &header)];
+        all.extend_from_slice(chunks);
+        all.push((b"IEND", &[]));
+        common::png_file(&all)
+    };
+    let is_invalid = |chunks: &[(&[u8; 4], &[u8])]| decode_animation(&build(chunks)).is_err();
+
+    // Valid reference.
+    let one_frame = actl(1, 0);
+    assert!(!is_invalid(&[
+        (b"acTL", &one_frame),
+        (b"fcTL", &whole),
+        (b"IDAT", &idat)
+    ]));
+    // Wrong frame count.
+    let two_frames = actl(2, 0);
+    assert!(is_invalid(&[
+        (b"acTL", &two_frames),
+        (b"fcTL", &whole),
+        (b"IDAT", &idat)
+    ]));
+    // Wrong sequence number.
+    let second = fctl(0, (0, 0, 1, 1), (0, 0), (0, 0));
+    let data = fdat(1, 1, &[GREEN]);
+    assert!(is_invalid(&[
+        (b"acTL", &two_frames),
+        (b"fcTL", &whole),
+        (b"IDAT", &idat),
+        (b"fcTL", &second),
+        (b"fdAT", &data),
+    ]));
+    // First frame not covering the canvas.
+    let partial = fctl(0, (0, 0, 1, 2), (0, 0), (0, 0));
+    assert!(is_invalid(&[
+        (b"acTL", &one_frame),
+        (b"fcTL", &partial),
+        (b"IDAT", &idat)
+    ]));
+    // Frame outside of the canvas.
+    let outside = fctl(1, (1, 1, 2, 1), (0, 0), (0, 0));
+    let data = fdat(2, 2, &[GREEN; 2]);
+    assert!(is_invalid(&[
+        (b"acTL", &two_frames),
+        (b"fcTL", &whole),
+        (b"IDAT", &idat),
+        (b"fcTL", &outside),
+        (b"fdAT", &data),
+    ]));
+    // Frame data before the image data.
+    let early = fdat(1, 2, &[GREEN; 4]);
+    assert!(is_invalid(&[
+        (b"acTL", &one_frame),
+        (b"fcTL", &whole),
+        (b"fdAT", &early),
+        (b"IDAT", &idat),
+    ]));
+
+    // Truncated frame data is an error when the frame is decoded.
+    let mut truncated = fdat(2, 1, &[GREEN]);
+    truncated.truncate(truncated.len() - 6);
+    let third = fctl(1, (0, 0, 1, 1), (0, 0), (0, 0));
+    let file = build(&[
+        (b"acTL", &two_frames),
+        (b"fcTL", &whole),
+        (b"IDAT", &idat),
+        (b"fcTL", &third),
+        (b"fdAT", &truncated),
+    ]);
+    let frames: Vec<_> = decode_animation(&file).unwrap().collect();
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].is_ok());
+    assert!(frames[1].is_err());
+}
+
+// Frames changing in random regions, sometimes reverting to older frames.
+fn random_frames(rng: &mut StdRng) -> Vec<Frame> {
+    let width = rng.gen_range(1, 12);
+    let height = rng.gen_range(1, 12);
+    let random_pixel = |rng: &mut StdRng| -> [u8; 4] {
+        match rng.gen_range(0, 3) {
+            0 => CLEAR,
+            1 => [rng.gen(), rng.gen(), rng.gen(), 255],
+            _ => rng.gen(),
+        }
+    };
+    let mut frames: Vec<Frame> = Vec::new();
+    for i in 0..rng.gen_range(1, 8) {
+        let mut data: Vec<u8> = match frames.last() {
+            Some(previous) => previous.data.clone(),
+            None => (0..width * height)
+                .flat_map(|_| random_pixel(rng).to_vec())
+                .collect(),
+        };
+        match rng.gen_range(0, 4) {
+            0 if i > 1 => data = frames[i - 2].data.clone(),
+            1 => {}
+            _ => {
+                let (x, y) = (rng.gen_range(0, width), rng.gen_range(0, height));
+                let (w, h) = (
+                    rng.gen_range(1, width - x + 1),
+                    rng.gen_range(1, height - y + 1),
+                );
+                let pixel = random_pixel(rng);
+                for row in y..y + h {
+                    for col in x..x + w {
+                        let start = (row * width + col) * 4;
+                        data[start..start + 4].copy_from_slice(&pixel);
+                    }
+                }
+            }
+        }
+        let delay = match rng.gen_range(0, 4) {
+            0 => Duration::from_secs(rng.gen_range(66, 1000)),
+            _ => Duration::from_millis(rng.gen_range(0, 3000)),
+        };
+        frames.push(Frame {
+            width,
+            height,
+            data,
+            delay,
+        });
+    }
+    frames
+}
+
+#[test]
+fn encoded_animations_decode_to_their_frames() {
+    let mut rng = StdRng::seed_from_u64(49);
+    for _ in 0..200 {
+        let frames = random_frames(&mut rng);
+        let options = AnimationOptions {
+            num_plays: rng.gen_range(0, 5),
+            compression_level: rng.gen_range(0, 11),
+            filter_strategy: FilterStrategy::MinSumAbsDiff,
+            optimize_regions: rng.gen(),
+        };
+        let file = encode_animation(&frames, &options).unwrap();
+        let violations = validate_png(&file, &ChunkHandlers::new()).unwrap();
+        assert!(violations.is_empty(), "{:?}", violations);
+
+        let animation = decode_animation(&file).unwrap();
+        assert_eq!(
+            (animation.width, animation.height),
+            (frames[0].width, frames[0].height)
+        );
+        assert_eq!(animation.num_plays, options.num_plays);
+        let decoded: Vec<Frame> = animation.collect::<Result<_, _>>().unwrap();
+        assert_eq!(decoded.len(), frames.len());
+        for (i, (decoded, frame)) in decoded.iter().zip(frames.iter()).enumerate() {
+            assert_eq!(decoded.data, frame.data, "frame {} of {:?}", i, options);
+            let millis = (decoded.delay.as_secs_f64() * 1000.0).round() as u128;
+            assert_eq!(millis, frame.delay.as_millis(), "frame {}", i);
+        }
+
+        // The default image is the first frame.
+        let (img, _) = png::decode(&file).unwrap();
+        assert_eq!(img.data, frames[0].data);
+    }
+}
+
+#[test]
+fn optimized_regions_are_smaller() {
+    let mut data = vec![0; 32 * 32 * 4];
+    let mut frames = Vec::new();
+    for i in 0..8 {
+        data[i * 4..i * 4 + 4].copy_from_slice(&[i as u8, 0, 0, 255]);
+        frames.push(Frame {
+            width: 32,
+            height: 32,
+            data: data.clone(),
+            delay: Duration::from_millis(100),
+        });
+    }
+    let encode = |optimize_regions| {
+        let options = AnimationOptions {
+            optimize_regions,
+            ..Default::default()
+        };
+        encode_animation(&frames, &options).unwrap()
+    };
+    let (optimized, full) = (encode(true), encode(false));
+    assert!(optimized.len() < full.len());
+    for file in [optimized, full].iter() {
+        let decoded: Vec<Frame> = decode_animation(file)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let decoded: Vec<&[u8]> = decoded.iter().map(|f| f.data.as_slice()).collect();
+        let expected: Vec<&[u8]> = frames.iter().map(|f| f.data.as_slice()).collect();
+        assert_eq!(decoded, expected);
+    }
+}
+
+#[test]
+fn invalid_frames_are_not_encoded() {
+    let options = AnimationOptions::default();
+    assert!(encode_animation(&[], &options).is_err());
+    let frame = |width, height, len| Frame {
+        width,
+        height,
+        data: vec![0; len],
+        delay: Duration::from_secs(1),
+    };
+    assert!(encode_animation(&[frame(2, 2, 16), frame(2, 2, 16)], &options).is_ok());
+    assert!(encode_animation(&[frame(2, 2, 16), frame(2, 1, 8)], &options).is_err());
+    assert!(encode_animation(&[frame(2, 2, 16), frame(2, 2, 12)], &options).is_err());
+    assert!(encode_animation(&[frame(0, 0, 0)], &options).is_err());
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use miniz_oxide::deflate::compress_to_vec;
+use png_decoder::apng::decode_animation;
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::lint::lint;
+use png_decoder::optimizer::{optimize, OptimizeOptions};
+use png_decoder::png;
+use png_decoder::rewriter::{rewrite, Edits};
+use png_decoder::validation::validate_png;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Data of the CgBI chunk of iOS files, its content is not used.
+const CGBI: [u8; 4] = [0x50, 0x00, 0x20, 0x06];
+
+// Apple CgBI file of 8-bit samples, with raw deflate image data
+// split in IDAT chunks of `idat_size` bytes.
+fn cgbi_file(width: u32, color_type: u8, pixels: &[u8], idat_size: usize) -> Vec<u8> {
+    let channels = if color_type == 6 { 4 } else { 3 };
+    let row_len = width as usize * channels;
+    let height = (pixels.len() / row_len) as u32;
+    let scanlines: Vec<u8> = pixels
+        .chunks(row_len)
+        .flat_map(|row| std::iter::once(0).chain(row.iter().cloned()))
+        .collect();
+    let header = common::ihdr(width, height, 8, color_type);
+    let deflated = compress_to_vec(&scanlines, 6);
+    let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"CgBI", &CGBI), (b"IHDR", &header)];
+    chunks.extend(deflated.chunks(idat_size).map(|d| (b"IDAT", d)));
+    chunks.push((b"IEND", &[]));
+    common::png_file(&chunks)
+}
+
+#[test]
+fn bgr_is_converted_to_rgb() {
+    let file = cgbi_file(2, 2, &[1, 2, 3, 4, 5, 6], 100);
+    let (img, _) = png::decode(&file).unwrap();
+    assert_eq!((img.width, img.height), (2, 1));
+    assert_eq!(img.data, vec![3, 2, 1, 6, 5, 4]);
+    assert_eq!(img.zlib_header, None);
+    assert!(!img.adler32_verified);
+}
+
+#[test]
+fn premultiplied_bgra_is_converted_to_straight_rgba() {
+    let pixels = [
+        10, 20, 30, 255, // opaque
+        0, 0, 0, 0, // transparent
+        25, 50, 100, 128, // half transparent
+        1, 1, 1, 1, // almost transparent
+    ];
+    let (img, _) = png::decode(&cgbi_file(2, 6, &pixels, 5)).unwrap();
+    assert_eq!(
+        img.data,
+        vec![30, 20, 10, 255, 0, 0, 0, 0, 199, 100, 50, 128, 255, 255, 255, 1]
+    );
+}
+
+#[test]
+fn random_pixels_are_unpremultiplied() {
+    let mut rng = StdRng::seed_from_u64(50);
+    for _ in 0..20 {
+        let width = rng.gen_range(1, 20);
+        let height = rng.gen_range(1, 20);
+        let straight: Vec<u8> = (0..width * height * 4).map(|_| rng.gen()).collect();
+        let premultiplied: Vec<u8> = straight
+            .chunks(4)
+            .flat_map(|p| {
+                let alpha = p[3] as u32;
+                let premultiply = |c: u8| ((c as u32 * alpha + 127) / 255) as u8;
+                vec![
+                    premultiply(p[2]),
+                    premultiply(p[1]),
+                    premultiply(p[0]),
+                    p[3],
+                ]
+            })
+            .collect();
+        let idat_size = rng.gen_range(1, 200);
+        let file = cgbi_file(width as u32, 6, &premultiplied, idat_size);
+        let (img, _) = png::decode(&file).unwrap();
+        for (decoded, original) in img.data.chunks(4).zip(straight.chunks(4)) {
+            let alpha = original[3];
+            assert_eq!(decoded[3], alpha);
+            // Premultiplication loses precision for translucent pixels.
+            let tolerance = match alpha {
+                0 => 0,
+                _ => 255 / alpha as i32 + 1,
+            };
+            for c in 0..3 {
+                let expected = if alpha == 0 { 0 } else { original[c] as i32 };
+                let error = (decoded[c] as i32 - expected).abs();
+                assert!(error <= tolerance, "{:?} {:?}", decoded, original);
+            }
+        }
+    }
+}
+
+#[test]
+fn metadata_is_decoded() {
+    let file = cgbi_file(1, 6, &[0, 0, 0, 255], 100);
+    assert!(png::decode_metadata(&file).is_ok());
+}
+
+#[test]
+fn unsupported_functions() {
+    let file = cgbi_file(1, 6, &[0, 0, 0, 255], 100);
+    let handlers = ChunkHandlers::new();
+    let errors = [
+        lint(&file, &handlers).unwrap_err(),
+        validate_png(&file, &handlers).unwrap_err(),
+        decode_animation(&file).unwrap_err().to_string(),
+        optimize(&file, &OptimizeOptions::default())
+            .unwrap_err()
+            .to_string(),
+        rewrite(&file, &Edits::default()).unwrap_err().to_string(),
+    ];
+    for error in errors.iter() {
+        assert_eq!(error, "Apple CgBI files are not supported");
+    }
+}
+
+#[test]
+fn invalid_files() {
+    // Only 8-bit RGB and RGBA images are supported.
+    let header = common::ihdr(1, 1, 8, 0);
+    let data = compress_to_vec(&[0, 0], 6);
+    let gray = common::png_file(&[
+        (b"CgBI", &CGBI),
+        (b"IHDR", &header),
+        (b"IDAT", &data),
+        (b"IEND", &[]),
+    ]);
+    assert!(png::decode(&gray).is_err());
+
+    // Raw deflate data without CgBI chunk has no zlib header.
+    let file = cgbi_file(1, 2, &[1, 2, 3], 100);
+    let without_cgbi = [&file[..8], &file[8 + 16..]].concat();
+    assert!(png::decode(&without_cgbi).is_err());
+
+    // The CgBI chunk must be the first one.
+    let header = common::ihdr(1, 1, 8, 2);
+    let data = compress_to_vec(&[0, 1, 2, 3], 6);
+    let misplaced = common::png_file(&[
+        (b"IHDR", &header),
+        (b"CgBI", &CGBI),
+        (b"IDAT", &data),
+        (b"IEND", &[]),
+    ]);
+    assert!(png::decode(&misplaced).is_err());
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use png_decoder::chunk::{Chunk, ChunkType};
+use png_decoder::png;
+use std::convert::TryFrom;
+
+fn with_chunk(chunk_type: &[u8; 4], before_idat: bool) -> Vec<u8> {
+    let header = common::ihdr(1, 1, 8, 0);
+    let idat = common::zlib(&[0, 7]);
+    let chunk = (chunk_type, &[1, 2, 3][..]);
+    let mut chunks = vec![(b"IHDR", header.as_slice())];
+    if before_idat {
+        chunks.push(chunk);
+    }
+    chunks.push((b"IDAT", &idat));
+    if !before_idat {
+        chunks.push(chunk);
+    }
+    chunks.push((b"IEND", &[]));
+    common::png_file(&chunks)
+}
+
+#[test]
+fn property_bits() {
+    let idot = ChunkType::try_from(*b"iDOT").unwrap();
+    assert!(idot.is_ancillary() && !idot.is_private());
+    assert!(!idot.is_reserved_bit_set() && !idot.is_safe_to_copy());
+    let nptc = ChunkType::try_from(*b"npTc").unwrap();
+    assert!(nptc.is_ancillary() && nptc.is_private() && nptc.is_safe_to_copy());
+    assert!(ChunkType::IDAT.is_critical() && !ChunkType::IDAT.is_safe_to_copy());
+    assert!(ChunkType::tEXt.is_ancillary() && ChunkType::tEXt.is_safe_to_copy());
+    assert!(ChunkType::try_from(*b"abcd").unwrap().is_reserved_bit_set());
+}
+
+#[test]
+fn unknown_ancillary_chunks_are_ignored() {
+    for name in [b"iDOT", b"npTc", b"prVt"].iter() {
+        for before_idat in [true, false].iter() {
+            let (img, _) = png::decode(&with_chunk(name, *before_idat)).unwrap();
+            assert_eq!(img.data, vec![7]);
+        }
+    }
+}
+
+#[test]
+fn unknown_critical_chunks_are_rejected() {
+    for name in [b"ZZZZ", b"XyZw"].iter() {
+        let err = png::decode(&with_chunk(name, true)).err().unwrap();
+        assert!(
+            err.to_string().contains("Unknown critical chunk"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn chunk_type_codes() {
+    assert_eq!("IDAT".parse::<ChunkType>(), Ok(ChunkType::IDAT));
+    assert_eq!(ChunkType::IDAT.to_string(), "IDAT");
+    let vendor: ChunkType = "vpAg".parse().unwrap();
+    assert_eq!(vendor, ChunkType::Unknown(*b"vpAg"));
+    assert_eq!(vendor.to_string(), "vpAg");
+    assert_eq!(ChunkType::try_from(vendor.name()), Ok(vendor));
+    assert!("pr1t".parse::<ChunkType>().is_err());
+    assert!("IDA".parse::<ChunkType>().is_err());
+    assert!(ChunkType::try_from([b'I', b'D', 0xc1, b'T']).is_err());
+}
+
+#[test]
+fn chunk_parse_rejects_non_letter_types() {
+    let mut bytes = vec![0, 0, 0, 1];
+    bytes.extend_from_slice(b"ab\xffd");
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0]);
+    assert!(Chunk::parse(&bytes).is_err());
+    bytes[6] = b'C';
+    let (rest, chunk) = Chunk::parse(&bytes).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(chunk.chunk_type, ChunkType::Unknown(*b"abCd"));
+}
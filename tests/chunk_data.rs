@@ -0,0 +1,79 @@
+mod common;
+
+use png_decoder::chunk::{Chunk, ChunkType};
+use png_decoder::chunk_data::{parse_chunk_data, ChunkData, SuggestedPaletteEntry};
+use png_decoder::png;
+
+// Parse chunk data with `check`, panicking if it is not valid.
+fn parse<T>(chunk_type: ChunkType, data: &[u8], check: impl FnOnce(ChunkData) -> T) -> T {
+    let chunk = Chunk::new(chunk_type, data);
+    let (_, parsed) = parse_chunk_data(&chunk).unwrap();
+    check(parsed)
+}
+
+fn is_valid(chunk_type: ChunkType, data: &[u8]) -> bool {
+    parse_chunk_data(&Chunk::new(chunk_type, data)).is_ok()
+}
+
+#[test]
+fn suggested_palettes() {
+    let splt_8 = b"pal\0\x08\x01\x02\x03\x04\x00\x05\x06\x07\x08\x09\x00\x0a";
+    parse(ChunkType::sPLT, splt_8, |d| match d {
+        ChunkData::sPLT(splt) => {
+            assert_eq!(splt.name, "pal");
+            assert_eq!(splt.sample_depth, 8);
+            assert_eq!(
+                splt.entries[1],
+                SuggestedPaletteEntry {
+                    red: 6,
+                    green: 7,
+                    blue: 8,
+                    alpha: 9,
+                    frequency: 10,
+                }
+            );
+        }
+        other => panic!("{:?}", other),
+    });
+    let splt_16 = b"p\0\x10\x01\x00\x02\x00\x03\x00\xff\xff\x00\x01";
+    parse(ChunkType::sPLT, splt_16, |d| match d {
+        ChunkData::sPLT(splt) => assert_eq!(splt.entries[0].red, 256),
+        other => panic!("{:?}", other),
+    });
+    assert!(!is_valid(
+        ChunkType::sPLT,
+        b"pal\0\x04\x01\x02\x03\x04\x00\x05"
+    ));
+    assert!(!is_valid(ChunkType::sPLT, b"pal\0\x08\x01\x02\x03"));
+}
+
+#[test]
+fn histogram() {
+    parse(ChunkType::hIST, &[0, 1, 1, 0], |d| match d {
+        ChunkData::hIST(hist) => assert_eq!(hist, vec![1, 256]),
+        other => panic!("{:?}", other),
+    });
+    assert!(!is_valid(ChunkType::hIST, &[0, 1, 1]));
+}
+
+#[test]
+fn palette_metadata() {
+    let header = common::ihdr(1, 1, 8, 3);
+    let idat = common::zlib(&[0, 1]);
+    let file = |hist: &[u8]| {
+        common::png_file(&[
+            (b"IHDR", &header),
+            (b"PLTE", &[0, 0, 0, 255, 255, 255]),
+            (b"hIST", hist),
+            (b"sPLT", b"pal\0\x08\x01\x02\x03\x04\x00\x05"),
+            (b"IDAT", &idat),
+            (b"IEND", &[]),
+        ])
+    };
+    let metadata = png::decode_metadata(&file(&[0, 0, 0, 1])).unwrap();
+    assert_eq!(metadata.histogram, Some(vec![0, 1]));
+    assert_eq!(metadata.suggested_palette("pal").unwrap().entries.len(), 1);
+    // A histogram of the wrong size is ignored.
+    let metadata = png::decode_metadata(&file(&[0, 0, 0, 1, 0, 2])).unwrap();
+    assert_eq!(metadata.histogram, None);
+}
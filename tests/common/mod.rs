@@ -0,0 +1,44 @@
+// Helpers building PNG files chunk by chunk.
+#![allow(dead_code)]
+
+use png_decoder::encoder::write_chunk;
+
+pub const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// IHDR data of a non-interlaced image.
+pub fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+    let mut data = width.to_be_bytes().to_vec();
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    data
+}
+
+/// Zlib stream of scanlines, each starting with its filter byte.
+pub fn zlib(scanlines: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(scanlines, 6)
+}
+
+/// PNG file made of the signature and the given chunks, CRCs included.
+pub fn png_file(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut file = SIGNATURE.to_vec();
+    for (chunk_type, data) in chunks.iter() {
+        write_chunk(&mut file, **chunk_type, data);
+    }
+    file
+}
+
+/// PNG file of 8-bit gray pixels, with the ancillary chunks before IDAT.
+pub fn gray_image(width: u32, pixels: &[u8], ancillary: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let height = pixels.len() as u32 / width;
+    let scanlines: Vec<u8> = pixels
+        .chunks(width as usize)
+        .flat_map(|row| std::iter::once(0).chain(row.iter().cloned()))
+        .collect();
+    let header = ihdr(width, height, 8, 0);
+    let idat = zlib(&scanlines);
+    let mut chunks = vec![(b"IHDR", header.as_slice())];
+    chunks.extend_from_slice(ancillary);
+    chunks.push((b"IDAT", &idat));
+    chunks.push((b"IEND", &[]));
+    png_file(&chunks)
+}
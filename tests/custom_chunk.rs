@@ -0,0 +1,84 @@
+mod common;
+
+use png_decoder::chunk::ChunkType;
+use png_decoder::custom_chunk::{ChunkHandlers, ChunkOrdering};
+use png_decoder::png::{self, DecodeOptions};
+
+#[derive(Debug, PartialEq)]
+struct Calibration(Vec<u8>);
+
+fn prvt() -> ChunkType {
+    "prVt".parse().unwrap()
+}
+
+fn decode_with(file: &[u8], handlers: ChunkHandlers) -> Result<Vec<Calibration>, String> {
+    let options = DecodeOptions {
+        chunk_handlers: handlers,
+        ..Default::default()
+    };
+    let (_, metadata) = png::decode_with_options(file, &options).map_err(|e| e.to_string())?;
+    let values = metadata.customs::<Calibration>(prvt());
+    Ok(values.map(|c| Calibration(c.0.clone())).collect())
+}
+
+fn handlers(ordering: ChunkOrdering, multiple: bool) -> ChunkHandlers {
+    let mut handlers = ChunkHandlers::new();
+    handlers
+        .register(prvt(), ordering, multiple, |data| match data {
+            [] => Err("Empty calibration".to_string()),
+            _ => Ok(Calibration(data.to_vec())),
+        })
+        .unwrap();
+    handlers
+}
+
+#[test]
+fn handler_values() {
+    let file = common::gray_image(1, &[0], &[(b"prVt", b"calib")]);
+    let values = decode_with(&file, handlers(ChunkOrdering::BeforeIdat, false));
+    assert_eq!(values, Ok(vec![Calibration(b"calib".to_vec())]));
+    // Without handler, the unknown ancillary chunk is ignored.
+    let (_, metadata) = png::decode(&file).unwrap();
+    assert!(metadata.custom_chunks.is_empty());
+}
+
+#[test]
+fn handler_errors() {
+    let file = common::gray_image(1, &[0], &[(b"prVt", b"")]);
+    let err = decode_with(&file, handlers(ChunkOrdering::Anywhere, false));
+    assert!(err.unwrap_err().contains("Empty calibration"));
+}
+
+#[test]
+fn handler_constraints() {
+    let twice = common::gray_image(1, &[0], &[(b"prVt", b"a"), (b"prVt", b"b")]);
+    assert!(decode_with(&twice, handlers(ChunkOrdering::Anywhere, false)).is_err());
+    let values = decode_with(&twice, handlers(ChunkOrdering::Anywhere, true));
+    assert_eq!(values.unwrap().len(), 2);
+    let before_idat = common::gray_image(1, &[0], &[(b"prVt", b"a")]);
+    assert!(decode_with(&before_idat, handlers(ChunkOrdering::AfterIdat, false)).is_err());
+}
+
+#[test]
+fn register_known_chunk_type() {
+    let mut handlers = ChunkHandlers::new();
+    let result = handlers.register(ChunkType::tEXt, ChunkOrdering::Anywhere, true, |_| Ok(()));
+    assert!(result.is_err());
+    // Raw codes of known chunk types and codes that are not ASCII letters.
+    for name in [*b"IHDR", *b"acTL", *b"pr1t", *b"pr t"].iter() {
+        let unknown = ChunkType::Unknown(*name);
+        let result = handlers.register(unknown, ChunkOrdering::Anywhere, true, |_| Ok(()));
+        assert!(result.is_err(), "{:?}", name);
+    }
+    assert!(handlers.is_empty());
+    // A raw code of an unknown chunk type is the parsed chunk type.
+    handlers
+        .register(
+            ChunkType::Unknown(*b"prVt"),
+            ChunkOrdering::Anywhere,
+            true,
+            |_| Ok(()),
+        )
+        .unwrap();
+    assert!(handlers.get(&prvt()).is_some());
+}
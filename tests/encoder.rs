@@ -0,0 +1,212 @@
+mod common;
+
+use png_decoder::chunk_data::{parse_valid_ihdr_data, IHDRData, Transparency};
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::encoder::{encode, EncodeOptions};
+use png_decoder::filter::{Filter, FilterStrategy};
+use png_decoder::png;
+use png_decoder::validation::validate_png;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// All allowed (color type, bit depth) combinations.
+const FORMATS: [(u8, u8); 15] = [
+    (0, 1),
+    (0, 2),
+    (0, 4),
+    (0, 8),
+    (0, 16),
+    (2, 8),
+    (2, 16),
+    (3, 1),
+    (3, 2),
+    (3, 4),
+    (3, 8),
+    (4, 8),
+    (4, 16),
+    (6, 8),
+    (6, 16),
+];
+
+fn header(width: u32, height: u32, bit_depth: u8, color_type: u8) -> IHDRData {
+    parse_valid_ihdr_data(&common::ihdr(width, height, bit_depth, color_type)).unwrap()
+}
+
+// Random packed rows of the image.
+fn random_rows(rng: &mut StdRng, ihdr: &IHDRData) -> Vec<u8> {
+    let row_len = ihdr.scanline_width().unwrap() - 1;
+    (0..row_len * ihdr.height as usize)
+        .map(|_| rng.gen())
+        .collect()
+}
+
+// Samples of packed rows, one byte each below 8 bits like the decoder output.
+fn unpacked(ihdr: &IHDRData, rows: &[u8]) -> Vec<u8> {
+    let depth = ihdr.bit_depth as usize;
+    if depth >= 8 {
+        return rows.to_vec();
+    }
+    let row_len = ihdr.scanline_width().unwrap() - 1;
+    rows.chunks(row_len)
+        .flat_map(|row| {
+            (0..ihdr.width as usize).map(move |x| {
+                let bit = x * depth;
+                (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8
+            })
+        })
+        .collect()
+}
+
+fn full_palette(bit_depth: u8) -> Vec<[u8; 3]> {
+    (0..1 << bit_depth)
+        .map(|i| [i as u8, 0, 255 - i as u8])
+        .collect()
+}
+
+// Rows decoded by the png crate, without any transformation.
+fn decode_with_png_crate(file: &[u8]) -> Vec<u8> {
+    let mut decoder = ::png::Decoder::new(file);
+    decoder.set_transformations(::png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer).unwrap();
+    buffer
+}
+
+#[test]
+fn round_trip_all_formats() {
+    let mut rng = StdRng::seed_from_u64(43);
+    let strategies = [
+        FilterStrategy::Fixed(Filter::None),
+        FilterStrategy::Fixed(Filter::Paeth),
+        FilterStrategy::MinSumAbsDiff,
+        FilterStrategy::Entropy,
+    ];
+    for &(color_type, bit_depth) in FORMATS.iter() {
+        for &(width, height) in [(1, 1), (3, 2), (13, 7)].iter() {
+            let ihdr = header(width, height, bit_depth, color_type);
+            let rows = random_rows(&mut rng, &ihdr);
+            let options = EncodeOptions {
+                palette: if color_type == 3 {
+                    Some(full_palette(bit_depth))
+                } else {
+                    None
+                },
+                filter_strategy: strategies[rng.gen_range(0, strategies.len())],
+                idat_size: rng.gen_range(1, 64),
+                ..Default::default()
+            };
+            let file = encode(&ihdr, &rows, &options).unwrap();
+            let context = format!("color type {} bit depth {}", color_type, bit_depth);
+            let violations = validate_png(&file, &ChunkHandlers::new()).unwrap();
+            assert!(violations.is_empty(), "{}: {:?}", context, violations);
+            let (img, _) = png::decode(&file).unwrap();
+            assert_eq!(img.data, unpacked(&ihdr, &rows), "{}", context);
+            assert_eq!(decode_with_png_crate(&file), rows, "{}", context);
+        }
+    }
+}
+
+#[test]
+fn idat_splitting() {
+    let ihdr = header(64, 64, 8, 2);
+    let rows: Vec<u8> = (0..64 * 64 * 3).map(|i| (i * 7 % 256) as u8).collect();
+    let options = EncodeOptions {
+        compression_level: 0,
+        idat_size: 1000,
+        ..Default::default()
+    };
+    let file = encode(&ihdr, &rows, &options).unwrap();
+    let (_, chunks) = png::parse_chunks(&file).unwrap();
+    let idats: Vec<usize> = chunks
+        .iter()
+        .filter(|c| c.chunk_type == png_decoder::chunk::ChunkType::IDAT)
+        .map(|c| c.data.len())
+        .collect();
+    assert!(idats.len() > 1);
+    assert!(idats.iter().all(|&len| len <= 1000));
+    assert!(idats[..idats.len() - 1].iter().all(|&len| len == 1000));
+    assert_eq!(png::decode(&file).unwrap().0.data, rows);
+}
+
+#[test]
+fn ancillary_chunks_ordering() {
+    let ihdr = header(2, 1, 4, 3);
+    let options = EncodeOptions {
+        palette: Some(full_palette(2)),
+        transparency: Some(Transparency::Palette(vec![0, 128])),
+        ancillary_chunks: vec![
+            (*b"bKGD", vec![1]),
+            (*b"hIST", vec![0, 1, 0, 0, 0, 0, 0, 1]),
+            (*b"gAMA", vec![0, 0, 177, 143]),
+            (*b"tEXt", b"Title\0palette".to_vec()),
+            (*b"sBIT", vec![8, 8, 8]),
+        ],
+        ..Default::default()
+    };
+    let file = encode(&ihdr, &[0x13], &options).unwrap();
+    let (_, chunks) = png::parse_chunks(&file).unwrap();
+    let names: Vec<String> = chunks.iter().map(|c| c.chunk_type.to_string()).collect();
+    assert_eq!(
+        names,
+        vec!["IHDR", "gAMA", "sBIT", "PLTE", "tRNS", "bKGD", "hIST", "tEXt", "IDAT", "IEND"]
+    );
+    assert!(validate_png(&file, &ChunkHandlers::new())
+        .unwrap()
+        .is_empty());
+    let (img, metadata) = png::decode(&file).unwrap();
+    assert_eq!(img.data, vec![1, 3]);
+    assert_eq!(metadata.histogram, Some(vec![1, 0, 0, 1]));
+    assert_eq!(metadata.text("Title"), Some("palette"));
+}
+
+#[test]
+fn transparency() {
+    let ihdr = header(2, 1, 16, 2);
+    let rows = [0, 1, 0, 2, 0, 3, 1, 0, 1, 0, 1, 0];
+    let options = EncodeOptions {
+        transparency: Some(Transparency::RGB([1, 2, 3])),
+        ..Default::default()
+    };
+    let file = encode(&ihdr, &rows, &options).unwrap();
+    let (_, metadata) = png::decode(&file).unwrap();
+    assert_eq!(metadata.transparency, Some(Transparency::RGB([1, 2, 3])));
+    // Samples must fit in the bit depth.
+    let ihdr = header(1, 1, 2, 0);
+    let options = EncodeOptions {
+        transparency: Some(Transparency::Gray(4)),
+        ..Default::default()
+    };
+    assert!(encode(&ihdr, &[0], &options).is_err());
+    // More alpha values than palette entries.
+    let ihdr = header(1, 1, 8, 3);
+    let options = EncodeOptions {
+        palette: Some(vec![[0, 0, 0]]),
+        transparency: Some(Transparency::Palette(vec![0, 0])),
+        ..Default::default()
+    };
+    assert!(encode(&ihdr, &[0], &options).is_err());
+}
+
+#[test]
+fn invalid_inputs() {
+    let default = EncodeOptions::default();
+    // Wrong data length
+    assert!(encode(&header(2, 2, 8, 0), &[0; 3], &default).is_err());
+    assert!(encode(&header(9, 1, 1, 0), &[0; 1], &default).is_err());
+    // Palette missing, forbidden or too big
+    assert!(encode(&header(1, 1, 8, 3), &[0], &default).is_err());
+    let palette = EncodeOptions {
+        palette: Some(full_palette(2)),
+        ..Default::default()
+    };
+    assert!(encode(&header(1, 1, 8, 0), &[0], &palette).is_err());
+    assert!(encode(&header(1, 1, 1, 3), &[0], &palette).is_err());
+    // Interlacing and invalid headers
+    let mut interlaced = header(1, 1, 8, 0);
+    interlaced.interlace_method = 1;
+    assert!(encode(&interlaced, &[0], &default).is_err());
+    let mut invalid = header(1, 1, 8, 2);
+    invalid.bit_depth = 4;
+    assert!(encode(&invalid, &[0], &default).is_err());
+}
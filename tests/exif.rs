@@ -0,0 +1,72 @@
+mod common;
+
+use png_decoder::exif::{Exif, Orientation};
+use png_decoder::png::{self, DecodeOptions};
+
+// EXIF data with a single orientation entry in IFD0, at the given offset.
+fn exif_big_endian(ifd_offset: u32, orientation: u16) -> Vec<u8> {
+    let mut data = b"MM\0*".to_vec();
+    data.extend_from_slice(&ifd_offset.to_be_bytes());
+    data.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+    data.extend_from_slice(&orientation.to_be_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    data
+}
+
+#[test]
+fn orientation_tag() {
+    let exif = Exif::new(exif_big_endian(8, 6)).unwrap();
+    assert_eq!(exif.orientation(), Some(Orientation::Rotate90));
+    let little_endian = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0\x03\0\0\0\0\0\0\0";
+    let exif = Exif::new(little_endian.to_vec()).unwrap();
+    assert_eq!(exif.orientation(), Some(Orientation::Rotate180));
+    assert_eq!(Exif(exif_big_endian(8, 9)).orientation(), None);
+    assert!(Exif::new(b"not a TIFF header".to_vec()).is_err());
+}
+
+#[test]
+fn out_of_bounds_offsets() {
+    assert_eq!(Exif(exif_big_endian(100, 6)).orientation(), None);
+    assert_eq!(Exif(exif_big_endian(u32::MAX, 6)).orientation(), None);
+    assert_eq!(Exif(exif_big_endian(u32::MAX - 1, 6)).orientation(), None);
+    assert_eq!(Exif(b"MM\0*\0\0".to_vec()).orientation(), None);
+}
+
+#[test]
+fn transform() {
+    let (width, height) = (3, 2);
+    let corners = [(0, 0), (2, 0), (0, 1), (2, 1)];
+    let transformed = |o: Orientation| -> Vec<(usize, usize)> {
+        corners
+            .iter()
+            .map(|&(x, y)| o.transform(x, y, width, height))
+            .collect()
+    };
+    assert_eq!(transformed(Orientation::Normal), corners);
+    assert_eq!(
+        transformed(Orientation::Rotate90),
+        [(1, 0), (1, 2), (0, 0), (0, 2)]
+    );
+    assert_eq!(
+        transformed(Orientation::Rotate180),
+        [(2, 1), (0, 1), (2, 0), (0, 0)]
+    );
+    assert!(Orientation::Transpose.swaps_dimensions());
+    assert!(!Orientation::FlipVertical.swaps_dimensions());
+}
+
+#[test]
+fn decode_applies_orientation() {
+    let exif = exif_big_endian(8, 6);
+    let file = common::gray_image(3, &[1, 2, 3, 4, 5, 6], &[(b"eXIf", &exif)]);
+    let (img, metadata) = png::decode(&file).unwrap();
+    assert_eq!(metadata.orientation(), Some(Orientation::Rotate90));
+    assert_eq!((img.width, img.height), (3, 2));
+    let options = DecodeOptions {
+        apply_exif_orientation: true,
+        ..Default::default()
+    };
+    let (img, _) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!((img.width, img.height), (2, 3));
+    assert_eq!(img.data, vec![4, 1, 5, 2, 6, 3]);
+}
@@ -0,0 +1,120 @@
+use png_decoder::filter::{self, Filter, FilterStrategy};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::convert::TryFrom;
+
+// Average filter of a first scanline: the up byte is zero,
+// so only half of the left byte is predicted.
+#[test]
+fn average_first_scanline() {
+    let raw = [10, 20, 30];
+    let filtered = [Filter::Average.into(), 10, 20 - 5, 30 - 10];
+    let scanlines = vec![(Filter::Average, &filtered[1..])];
+    assert_eq!(filter::unfilter(3, 1, 1, scanlines), raw);
+    let mut inflated = filtered.to_vec();
+    let scanlines = vec![(Filter::Average, 1)];
+    assert_eq!(filter::unfilter_bis(3, 1, 1, scanlines, &mut inflated), raw);
+}
+
+// Unfilter the output of `filter::filter` with both implementations.
+fn unfilter_both(bpp: usize, row_len: usize, filtered: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let height = filtered.len() / (row_len + 1);
+    let lines = filtered.chunks(row_len + 1);
+    let scanlines = lines
+        .clone()
+        .map(|l| (Filter::try_from(l[0]).unwrap(), &l[1..]))
+        .collect();
+    let unfiltered = filter::unfilter(row_len / bpp, height, bpp, scanlines);
+    let starts = lines
+        .enumerate()
+        .map(|(i, l)| (Filter::try_from(l[0]).unwrap(), i * (row_len + 1) + 1))
+        .collect();
+    let mut inflated = filtered.to_vec();
+    let unfiltered_bis = filter::unfilter_bis(row_len / bpp, height, bpp, starts, &mut inflated);
+    (unfiltered, unfiltered_bis)
+}
+
+#[test]
+fn round_trip() {
+    let mut rng = StdRng::seed_from_u64(44);
+    let strategies = [
+        FilterStrategy::Fixed(Filter::None),
+        FilterStrategy::Fixed(Filter::Sub),
+        FilterStrategy::Fixed(Filter::Up),
+        FilterStrategy::Fixed(Filter::Average),
+        FilterStrategy::Fixed(Filter::Paeth),
+        FilterStrategy::MinSumAbsDiff,
+        FilterStrategy::Entropy,
+        FilterStrategy::BruteForce(6),
+    ];
+    for &bpp in [1, 2, 3, 4, 6, 8].iter() {
+        for &width in [1, 2, 17].iter() {
+            let row_len = bpp * width;
+            let height = rng.gen_range(1, 6);
+            // Smooth data exercises the predictors better than noise.
+            let mut data: Vec<u8> = Vec::with_capacity(row_len * height);
+            for i in 0..row_len * height {
+                let noise: u8 = rng.gen_range(0, 8);
+                data.push((i as u8).wrapping_mul(3).wrapping_add(noise));
+            }
+            for strategy in strategies.iter() {
+                let filtered = filter::filter(bpp, row_len, &data, *strategy).unwrap();
+                assert_eq!(filtered.len(), data.len() + height);
+                if let FilterStrategy::Fixed(f) = strategy {
+                    let expected = u8::from(*f);
+                    assert!(filtered.chunks(row_len + 1).all(|l| l[0] == expected));
+                }
+                let (unfiltered, unfiltered_bis) = unfilter_both(bpp, row_len, &filtered);
+                let context = format!("bpp {} width {} {:?}", bpp, width, strategy);
+                assert_eq!(unfiltered, data, "{}", context);
+                assert_eq!(unfiltered_bis, data, "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+fn encode_lines() {
+    let prev = [10, 20, 30, 40];
+    let line = [15, 25, 35, 45];
+    let mut out = [0; 4];
+    let mut encode = |f: Filter| {
+        filter::encode_line(f, 2, &line, &prev, &mut out);
+        out
+    };
+    assert_eq!(encode(Filter::None), line);
+    assert_eq!(encode(Filter::Sub), [15, 25, 20, 20]);
+    assert_eq!(encode(Filter::Up), [5, 5, 5, 5]);
+    // (left + up) / 2 is predicted.
+    assert_eq!(encode(Filter::Average), [10, 15, 35 - 22, 45 - 32]);
+    // Paeth predicts up for the first pixel, then left or up.
+    assert_eq!(encode(Filter::Paeth), [5, 5, 5, 5]);
+}
+
+#[test]
+fn strategies_pick_the_cheapest_filter() {
+    // A gradient is all ones with Sub, chosen before Paeth on ties.
+    let gradient: Vec<u8> = (0..32).collect();
+    let filtered = filter::filter(1, 32, &gradient, FilterStrategy::MinSumAbsDiff).unwrap();
+    assert_eq!(filtered[0], u8::from(Filter::Sub));
+    // Identical rows are all zeros with Up.
+    let rows: Vec<u8> = (0..2)
+        .flat_map(|_| (0..32).map(|i| i * 37 % 256))
+        .map(|i| i as u8)
+        .collect();
+    for strategy in [FilterStrategy::MinSumAbsDiff, FilterStrategy::Entropy].iter() {
+        let filtered = filter::filter(1, 32, &rows, *strategy).unwrap();
+        assert_eq!(filtered[33], u8::from(Filter::Up), "{:?}", strategy);
+        assert!(filtered[34..].iter().all(|&b| b == 0));
+    }
+}
+
+#[test]
+fn invalid_row_layout() {
+    let strategy = FilterStrategy::Fixed(Filter::Sub);
+    assert!(filter::filter(1, 0, &[1, 2], strategy).is_err());
+    assert!(filter::filter(0, 2, &[1, 2], strategy).is_err());
+    assert!(filter::filter(3, 2, &[1, 2], strategy).is_err());
+    assert!(filter::filter(1, 2, &[1, 2, 3], strategy).is_err());
+    assert_eq!(filter::filter(1, 2, &[], strategy), Ok(Vec::new()));
+}
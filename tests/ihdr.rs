@@ -0,0 +1,189 @@
+mod common;
+
+use png_decoder::chunk_data::{parse_valid_ihdr_data, IHDRData};
+use png_decoder::color::ColorType;
+use png_decoder::limits::Limits;
+use png_decoder::png::{self, DecodeOptions};
+
+const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+fn with_methods(compression: u8, filter: u8, interlace: u8) -> Vec<u8> {
+    let mut data = common::ihdr(1, 1, 8, 0);
+    data[10..].copy_from_slice(&[compression, filter, interlace]);
+    data
+}
+
+#[test]
+fn color_type_and_bit_depth_combinations() {
+    let allowed: [(u8, &[u8]); 5] = [
+        (0, &[1, 2, 4, 8, 16]),
+        (2, &[8, 16]),
+        (3, &[1, 2, 4, 8]),
+        (4, &[8, 16]),
+        (6, &[8, 16]),
+    ];
+    for (color_type, depths) in allowed.iter() {
+        for bit_depth in [0, 1, 2, 3, 4, 8, 16, 32].iter() {
+            let result = parse_valid_ihdr_data(&common::ihdr(1, 1, *bit_depth, *color_type));
+            assert_eq!(
+                result.is_ok(),
+                depths.contains(bit_depth),
+                "color type {} bit depth {}",
+                color_type,
+                bit_depth
+            );
+        }
+    }
+    let error = parse_valid_ihdr_data(&common::ihdr(1, 1, 4, 2)).unwrap_err();
+    assert!(error.contains("bit depth 4"), "{}", error);
+    for color_type in [1, 5, 7].iter() {
+        assert!(parse_valid_ihdr_data(&common::ihdr(1, 1, 8, *color_type)).is_err());
+    }
+}
+
+#[test]
+fn dimensions() {
+    let max = (1 << 31) - 1;
+    assert!(parse_valid_ihdr_data(&common::ihdr(max, max, 8, 0)).is_ok());
+    for (width, height) in [(0, 1), (1, 0), (max + 1, 1), (1, u32::MAX)].iter() {
+        let error = parse_valid_ihdr_data(&common::ihdr(*width, *height, 8, 0)).unwrap_err();
+        assert!(error.contains("1-2^31-1"), "{}", error);
+    }
+}
+
+#[test]
+fn methods() {
+    assert!(parse_valid_ihdr_data(&with_methods(0, 0, 0)).is_ok());
+    assert!(parse_valid_ihdr_data(&with_methods(0, 0, 1)).is_ok());
+    let error = parse_valid_ihdr_data(&with_methods(1, 0, 0)).unwrap_err();
+    assert!(error.contains("compression method 1"), "{}", error);
+    let error = parse_valid_ihdr_data(&with_methods(0, 1, 0)).unwrap_err();
+    assert!(error.contains("filter method 1"), "{}", error);
+    let error = parse_valid_ihdr_data(&with_methods(0, 0, 2)).unwrap_err();
+    assert!(error.contains("interlace method 2"), "{}", error);
+}
+
+#[test]
+fn length() {
+    let mut data = common::ihdr(1, 1, 8, 0);
+    data.push(0);
+    assert!(parse_valid_ihdr_data(&data)
+        .unwrap_err()
+        .contains("13 bytes"));
+    assert!(parse_valid_ihdr_data(&data[..12]).is_err());
+}
+
+#[test]
+fn decode_rejects_invalid_ihdr() {
+    // RGB at 4 bits per sample used to decode to a wrong buffer.
+    let header = common::ihdr(2, 1, 4, 2);
+    let idat = common::zlib(&[0, 0x12, 0x34, 0x56]);
+    let file = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])]);
+    assert!(png::decode(&file).is_err());
+    let header = common::ihdr(0, 1, 8, 0);
+    let file = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])]);
+    assert!(png::decode(&file).is_err());
+}
+
+fn header(width: u32, height: u32, bit_depth: u8, color_type: u8) -> IHDRData {
+    parse_valid_ihdr_data(&common::ihdr(width, height, bit_depth, color_type)).unwrap()
+}
+
+#[test]
+fn scanline_width() {
+    // Sub-byte pixels are packed and rows are rounded up to a byte.
+    assert_eq!(header(16, 1, 1, 0).scanline_width(), Ok(3));
+    assert_eq!(header(17, 1, 1, 0).scanline_width(), Ok(4));
+    assert_eq!(header(3, 1, 2, 3).scanline_width(), Ok(2));
+    assert_eq!(header(3, 1, 4, 3).scanline_width(), Ok(3));
+    assert_eq!(header(3, 1, 8, 3).scanline_width(), Ok(4));
+    assert_eq!(header(3, 1, 16, 2).scanline_width(), Ok(19));
+    assert_eq!(header(3, 1, 8, 4).scanline_width(), Ok(7));
+    assert_eq!(header(1, 1, 1, 3).bytes_per_pixel(), 1);
+    assert_eq!(header(1, 1, 16, 6).bytes_per_pixel(), 8);
+}
+
+#[test]
+fn widest_scanlines() {
+    let gray = header(MAX_DIMENSION, 1, 1, 0);
+    assert_eq!(gray.scanline_width(), Ok(1 + (1 << 28)));
+    let palette = header(MAX_DIMENSION, 1, 8, 3);
+    assert_eq!(palette.scanline_width(), Ok(MAX_DIMENSION as usize + 1));
+    let rgba = header(MAX_DIMENSION, MAX_DIMENSION, 16, 6);
+    if cfg!(target_pointer_width = "64") {
+        assert_eq!(rgba.scanline_width(), Ok(8 * MAX_DIMENSION as usize + 1));
+        // Almost 2^65 bytes of image data.
+        assert!(rgba.image_data_len().is_err());
+    } else {
+        assert!(rgba.scanline_width().is_err());
+    }
+}
+
+#[test]
+fn decode_widest_images() {
+    let idat = common::zlib(&[0; 16]);
+    let unlimited = DecodeOptions {
+        limits: Limits::unlimited(),
+        ..Default::default()
+    };
+    for &(bit_depth, color_type) in [(1, 0), (8, 3), (16, 6)].iter() {
+        let ihdr = common::ihdr(MAX_DIMENSION, MAX_DIMENSION, bit_depth, color_type);
+        let mut chunks = vec![(b"IHDR", ihdr.as_slice())];
+        if color_type == 3 {
+            chunks.push((b"PLTE", &[0, 0, 0]));
+        }
+        chunks.push((b"IDAT", &idat));
+        chunks.push((b"IEND", &[]));
+        let file = common::png_file(&chunks);
+        assert!(png::decode(&file).is_err());
+    }
+    // Too big for memory even without limits, which fails instead of panicking.
+    let ihdr = common::ihdr(MAX_DIMENSION, MAX_DIMENSION, 16, 6);
+    let file = common::png_file(&[(b"IHDR", &ihdr), (b"IDAT", &idat), (b"IEND", &[])]);
+    assert!(png::decode_with_options(&file, &unlimited).is_err());
+}
+
+#[test]
+fn decode_palette_indices() {
+    let ihdr = common::ihdr(3, 2, 8, 3);
+    let plte: &[u8] = &[0, 0, 0, 255, 0, 0, 0, 0, 255];
+    let idat = common::zlib(&[0, 0, 1, 2, 1, 2, 255, 0]);
+    let file = common::png_file(&[
+        (b"IHDR", &ihdr),
+        (b"PLTE", plte),
+        (b"IDAT", &idat),
+        (b"IEND", &[]),
+    ]);
+    let (img, _) = png::decode(&file).unwrap();
+    assert_eq!(img.color_type, ColorType::PLTE);
+    assert_eq!(img.bytes_per_pixel, 1);
+    // The second row is Sub filtered.
+    assert_eq!(img.data, vec![0, 1, 2, 2, 1, 1]);
+}
+
+#[test]
+fn decode_sub_byte_samples() {
+    let ihdr = common::ihdr(16, 2, 1, 0);
+    let idat = common::zlib(&[0, 0b1010_0000, 0b0000_0001, 0, 0xff, 0x0f]);
+    let file = common::png_file(&[(b"IHDR", &ihdr), (b"IDAT", &idat), (b"IEND", &[])]);
+    let (img, _) = png::decode(&file).unwrap();
+    let mut expected = vec![1, 0, 1];
+    expected.extend(vec![0; 12]);
+    expected.push(1);
+    expected.extend(vec![1; 8]);
+    expected.extend(vec![0, 0, 0, 0, 1, 1, 1, 1]);
+    assert_eq!(img.data, expected);
+
+    // 4-bit palette with an odd width, rows are padded.
+    let ihdr = common::ihdr(3, 1, 4, 3);
+    let plte = [0; 3 * 16];
+    let idat = common::zlib(&[0, 0x2f, 0x70]);
+    let file = common::png_file(&[
+        (b"IHDR", &ihdr),
+        (b"PLTE", &plte),
+        (b"IDAT", &idat),
+        (b"IEND", &[]),
+    ]);
+    let (img, _) = png::decode(&file).unwrap();
+    assert_eq!(img.data, vec![2, 15, 7]);
+}
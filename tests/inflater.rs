@@ -0,0 +1,131 @@
+mod common;
+
+use miniz_oxide::deflate::compress_to_vec;
+use png_decoder::inflater::{Inflated, Inflater, MinizInflater, RawDeflateInflater};
+use png_decoder::limits::LimitError;
+use png_decoder::png::{self, DecodeOptions};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn sample_data() -> Vec<u8> {
+    (0..5000_u32).map(|i| (i * i % 251) as u8).collect()
+}
+
+// Inflate `input` split in slices of `slice_len` bytes.
+fn inflate_split(
+    inflater: &dyn Inflater,
+    input: &[u8],
+    slice_len: usize,
+    len: usize,
+) -> Result<Inflated, Box<dyn Error>> {
+    let slices: Vec<&[u8]> = input.chunks(slice_len).collect();
+    inflater.inflate(&slices, len, usize::MAX, true)
+}
+
+// Check a zlib inflater on complete, split, truncated and trailing input.
+fn check_zlib_inflater(inflater: &dyn Inflater) {
+    let data = sample_data();
+    let zlib = common::zlib(&data);
+    for &slice_len in [1, 7, zlib.len()].iter() {
+        // Expected lengths too small, exact and too big.
+        for &len in [0, 100, data.len(), 2 * data.len()].iter() {
+            let inflated = inflate_split(inflater, &zlib, slice_len, len).unwrap();
+            assert_eq!(inflated.data, data);
+            assert!(!inflated.truncated);
+            assert_eq!(inflated.trailing_bytes, 0);
+        }
+    }
+    let mut trailing = zlib.clone();
+    trailing.extend_from_slice(&[1, 2, 3]);
+    let inflated = inflate_split(inflater, &trailing, 10, data.len()).unwrap();
+    assert_eq!(inflated.data, data);
+    assert_eq!(inflated.trailing_bytes, 3);
+
+    let mut corrupted = zlib.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 1;
+    assert!(inflate_split(inflater, &corrupted, 10, data.len()).is_err());
+    let slices = [corrupted.as_slice()];
+    let inflated = inflater
+        .inflate(&slices, data.len(), usize::MAX, false)
+        .unwrap();
+    assert_eq!(inflated.data, data);
+
+    let limited = inflater.inflate(&[zlib.as_slice()], 100, 1000, true);
+    let error = limited.unwrap_err().downcast::<LimitError>().unwrap();
+    assert!(matches!(*error, LimitError::OutputBytes { max: 1000, .. }));
+
+    let cut = &zlib[..zlib.len() / 2];
+    let inflated = inflate_split(inflater, cut, 10, data.len()).unwrap();
+    assert!(inflated.truncated);
+    assert!(inflated.data.len() < data.len());
+    assert_eq!(inflated.data[..], data[..inflated.data.len()]);
+}
+
+#[test]
+fn miniz_inflater() {
+    check_zlib_inflater(&MinizInflater);
+}
+
+#[cfg(feature = "zlib-rs")]
+#[test]
+fn zlib_rs_inflater() {
+    check_zlib_inflater(&png_decoder::inflater::ZlibRsInflater);
+    let file = common::gray_image(3, &[1, 2, 3, 4, 5, 6], &[]);
+    let options = DecodeOptions {
+        inflater: Some(Arc::new(png_decoder::inflater::ZlibRsInflater)),
+        ..Default::default()
+    };
+    let (img, _) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!(img.data, vec![1, 2, 3, 4, 5, 6]);
+    assert!(img.adler32_verified);
+}
+
+#[test]
+fn raw_deflate_inflater() {
+    let data = sample_data();
+    let deflate = compress_to_vec(&data, 6);
+    let slices: Vec<&[u8]> = deflate.chunks(9).collect();
+    let inflated = RawDeflateInflater
+        .inflate(&slices, data.len(), usize::MAX, true)
+        .unwrap();
+    assert_eq!(inflated.data, data);
+    assert!(!inflated.truncated);
+    // A zlib stream is not raw deflate data.
+    let zlib = common::zlib(&data);
+    let raw = RawDeflateInflater.inflate(&[zlib.as_slice()], data.len(), usize::MAX, true);
+    assert!(raw.map(|i| i.data != data).unwrap_or(true));
+}
+
+// Backend counting its calls, delegating to miniz_oxide.
+#[derive(Debug, Default)]
+struct CountingInflater {
+    calls: AtomicUsize,
+}
+
+impl Inflater for CountingInflater {
+    fn inflate(
+        &self,
+        input: &[&[u8]],
+        len: usize,
+        max_len: usize,
+        verify_adler32: bool,
+    ) -> Result<Inflated, Box<dyn Error>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        MinizInflater.inflate(input, len, max_len, verify_adler32)
+    }
+}
+
+#[test]
+fn custom_backend() {
+    let file = common::gray_image(3, &[1, 2, 3, 4, 5, 6], &[]);
+    let counting = Arc::new(CountingInflater::default());
+    let options = DecodeOptions {
+        inflater: Some(counting.clone()),
+        ..Default::default()
+    };
+    let (img, _) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!(img.data, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use png_decoder::chunk_data::IHDRData;
+use png_decoder::limits::{LimitError, Limits};
+use png_decoder::png::{self, DecodeOptions};
+use std::error::Error;
+
+fn decode_error(file: &[u8], limits: Limits) -> Box<dyn Error> {
+    let options = DecodeOptions {
+        limits,
+        ..Default::default()
+    };
+    match png::decode_with_options(file, &options) {
+        Ok(_) => panic!("limits are not enforced"),
+        Err(e) => e,
+    }
+}
+
+fn limit_error(file: &[u8], limits: Limits) -> LimitError {
+    let error = decode_error(file, limits);
+    *error.downcast::<LimitError>().expect("not a limit error")
+}
+
+fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8) -> IHDRData {
+    let data = common::ihdr(width, height, bit_depth, color_type);
+    png_decoder::chunk_data::parse_valid_ihdr_data(&data).unwrap()
+}
+
+// IHDR claiming a huge image, followed by a tiny IDAT.
+fn huge_image(width: u32, height: u32) -> Vec<u8> {
+    let header = common::ihdr(width, height, 16, 6);
+    let idat = common::zlib(&[0; 16]);
+    common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])])
+}
+
+#[test]
+fn dimensions() {
+    let limits = Limits {
+        max_width: 100,
+        max_height: 50,
+        ..Limits::default()
+    };
+    assert!(limits.check_ihdr(&ihdr(100, 50, 8, 0)).is_ok());
+    assert_eq!(
+        limits.check_ihdr(&ihdr(101, 1, 8, 0)),
+        Err(LimitError::Width {
+            width: 101,
+            max: 100
+        })
+    );
+    assert_eq!(
+        limits.check_ihdr(&ihdr(1, 51, 8, 0)),
+        Err(LimitError::Height {
+            height: 51,
+            max: 50
+        })
+    );
+}
+
+#[test]
+fn pixels_and_output_bytes() {
+    let limits = Limits {
+        max_pixels: 1000,
+        max_output_bytes: 1000,
+        ..Limits::default()
+    };
+    assert_eq!(
+        limits.check_ihdr(&ihdr(100, 11, 1, 0)),
+        Err(LimitError::Pixels {
+            pixels: 1100,
+            max: 1000
+        })
+    );
+    // 1-bit rows of 100 pixels take 1 + 13 bytes.
+    assert!(limits.check_ihdr(&ihdr(100, 10, 1, 0)).is_ok());
+    // 10 rows of 1 + 80 bytes.
+    assert!(limits.check_ihdr(&ihdr(10, 10, 16, 6)).is_ok());
+    assert_eq!(
+        limits.check_ihdr(&ihdr(20, 20, 16, 6)),
+        Err(LimitError::OutputBytes {
+            bytes: 20 * 161,
+            max: 1000
+        })
+    );
+}
+
+#[test]
+fn decode_checks_ihdr_before_allocating() {
+    let default = Limits::default();
+    let error = limit_error(&huge_image(1 << 20, 1 << 20), default);
+    assert_eq!(
+        error,
+        LimitError::Pixels {
+            pixels: 1 << 40,
+            max: default.max_pixels
+        }
+    );
+    let small = Limits {
+        max_width: 10,
+        ..Limits::default()
+    };
+    assert!(matches!(
+        limit_error(&huge_image(11, 1), small),
+        LimitError::Width { .. }
+    ));
+}
+
+#[test]
+fn text_size() {
+    let mut ztxt = b"Comment\0\0".to_vec();
+    ztxt.extend(common::zlib(&[b'a'; 1000]));
+    let file = common::gray_image(1, &[0], &[(b"zTXt", &ztxt)]);
+    let limits = Limits {
+        max_text_size: 999,
+        ..Limits::default()
+    };
+    assert_eq!(
+        limit_error(&file, limits),
+        LimitError::TextSize { max: 999 }
+    );
+    let limits = Limits {
+        max_text_size: 1000,
+        ..Limits::default()
+    };
+    let options = DecodeOptions {
+        limits,
+        ..Default::default()
+    };
+    let (_, metadata) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!(metadata.compressed_texts[0].text.len(), 1000);
+}
+
+#[test]
+fn text_size_above_default_limit() {
+    let size = Limits::default().max_text_size + 1;
+    let mut ztxt = b"Comment\0\0".to_vec();
+    ztxt.extend(common::zlib(&vec![b'a'; size]));
+    let file = common::gray_image(1, &[0], &[(b"zTXt", &ztxt)]);
+    let options = DecodeOptions {
+        limits: Limits::unlimited(),
+        ..Default::default()
+    };
+    let (_, metadata) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!(metadata.compressed_texts[0].text.len(), size);
+    assert_eq!(
+        limit_error(&file, Limits::default()),
+        LimitError::TextSize {
+            max: Limits::default().max_text_size
+        }
+    );
+}
+
+#[test]
+fn chunks_count() {
+    let texts = vec![(b"tEXt", b"a\0b".as_ref()); 10];
+    let file = common::gray_image(1, &[0], &texts);
+    // IHDR, IDAT and IEND plus the texts.
+    let limits = |max_chunks| Limits {
+        max_chunks,
+        ..Limits::default()
+    };
+    assert_eq!(
+        limit_error(&file, limits(12)),
+        LimitError::Chunks { max: 12 }
+    );
+    let options = DecodeOptions {
+        limits: limits(13),
+        ..Default::default()
+    };
+    assert!(png::decode_with_options(&file, &options).is_ok());
+}
+
+#[test]
+fn display() {
+    let error = LimitError::Pixels { pixels: 10, max: 5 };
+    assert_eq!(
+        error.to_string(),
+        "Image pixels count 10 exceeds the limit 5"
+    );
+    assert_eq!(
+        LimitError::Chunks { max: 3 }.to_string(),
+        "Chunks count exceeds the limit 3"
+    );
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::lint::{lint, LintReport};
+use png_decoder::png;
+
+fn report(file: &[u8]) -> LintReport {
+    lint(file, &ChunkHandlers::new()).unwrap()
+}
+
+fn warnings(file: &[u8]) -> Vec<String> {
+    report(file)
+        .warnings
+        .into_iter()
+        .map(|w| w.message)
+        .collect()
+}
+
+fn gamma(value: u32) -> [u8; 4] {
+    value.to_be_bytes()
+}
+
+// cHRM data from (x, y) pairs multiplied by 100000.
+fn chromaticities(points: [(u32, u32); 4]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (x, y) in points.iter() {
+        data.extend_from_slice(&x.to_be_bytes());
+        data.extend_from_slice(&y.to_be_bytes());
+    }
+    data
+}
+
+#[test]
+fn clean_file() {
+    let file = common::gray_image(2, &[0, 255], &[(b"gAMA", &gamma(45455))]);
+    assert!(report(&file).is_clean());
+}
+
+#[test]
+fn texts() {
+    let text = |keyword: &str, text: &str| {
+        let mut data = keyword.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(text.as_bytes());
+        data
+    };
+    let (a, b) = (text("Title", "a"), text("Title", "b"));
+    let file = common::gray_image(1, &[0], &[(b"tEXt", &a), (b"tEXt", &b)]);
+    let found = report(&file);
+    assert!(found.errors.is_empty());
+    assert_eq!(found.warnings.len(), 1);
+    assert_eq!(found.warnings[0].chunk_index, 2);
+    assert_eq!(found.warnings[0].message, "Duplicate text keyword Title");
+
+    let large = text("Comment", &"x".repeat((1 << 16) + 1));
+    let file = common::gray_image(1, &[0], &[(b"tEXt", &large)]);
+    assert_eq!(warnings(&file), vec!["Comment text is 65537 bytes long"]);
+
+    let long_keyword = text(&"k".repeat(80), "a");
+    let empty_keyword = text("", "a");
+    for data in [long_keyword, empty_keyword].iter() {
+        let found = report(&common::gray_image(1, &[0], &[(b"tEXt", data)]));
+        assert_eq!(found.errors.len(), 1);
+        assert!(found.errors[0].message.contains("not 1 to 79"));
+    }
+    // Latin-1 characters count once.
+    let latin1 = [vec![0xe9; 79], b"\0a".to_vec()].concat();
+    assert!(report(&common::gray_image(1, &[0], &[(b"tEXt", &latin1)])).is_clean());
+}
+
+#[test]
+fn color_space() {
+    let file = common::gray_image(1, &[0], &[(b"gAMA", &gamma(60000))]);
+    assert_eq!(warnings(&file), vec!["gAMA 60000 is not a usual value"]);
+    for usual in [45455, 45000, 55556, 100_000].iter() {
+        let file = common::gray_image(1, &[0], &[(b"gAMA", &gamma(*usual))]);
+        assert!(report(&file).is_clean(), "{}", usual);
+    }
+
+    let srgb: (&[u8; 4], &[u8]) = (b"sRGB", &[0]);
+    let file = common::gray_image(1, &[0], &[srgb, (b"gAMA", &gamma(100_000))]);
+    assert_eq!(
+        warnings(&file),
+        vec!["gAMA 100000 is inconsistent with sRGB"]
+    );
+    let file = common::gray_image(1, &[0], &[srgb, (b"gAMA", &gamma(45455))]);
+    assert!(report(&file).is_clean());
+
+    let srgb_chrm = chromaticities([
+        (31270, 32900),
+        (64000, 33000),
+        (30000, 60000),
+        (15000, 6000),
+    ]);
+    let wide_chrm = chromaticities([
+        (31270, 32900),
+        (70800, 29200),
+        (17000, 79700),
+        (13100, 4600),
+    ]);
+    let file = common::gray_image(1, &[0], &[(b"cHRM", &srgb_chrm), srgb]);
+    assert!(report(&file).is_clean());
+    let file = common::gray_image(1, &[0], &[(b"cHRM", &wide_chrm), srgb]);
+    assert_eq!(warnings(&file), vec!["cHRM is inconsistent with sRGB"]);
+    // Without sRGB, any chromaticities are fine.
+    let file = common::gray_image(1, &[0], &[(b"cHRM", &wide_chrm)]);
+    assert!(report(&file).is_clean());
+}
+
+#[test]
+fn unnecessary_16_bit() {
+    let header = common::ihdr(2, 1, 16, 0);
+    let file = |samples: &[u8]| {
+        let idat = common::zlib(&[&[0], samples].concat());
+        common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])])
+    };
+    let found = report(&file(&[0x12, 0x12, 0xff, 0xff]));
+    assert_eq!(found.warnings.len(), 1);
+    assert_eq!(found.warnings[0].chunk_index, 0);
+    assert!(found.warnings[0].message.contains("16-bit"));
+    assert!(report(&file(&[0x12, 0x13, 0xff, 0xff])).is_clean());
+}
+
+#[test]
+fn invalid_filter_type_does_not_panic() {
+    let header = common::ihdr(2, 1, 16, 0);
+    let idat = common::zlib(&[9, 0x12, 0x12, 0xff, 0xff]);
+    let file = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])]);
+    assert!(report(&file).warnings.is_empty());
+    assert!(png::decode(&file).is_err());
+    assert!(png::decode_no_check(&file).is_err());
+}
+
+#[test]
+fn tiny_idats() {
+    let header = common::ihdr(1, 2, 8, 0);
+    let idat = common::zlib(&[0, 1, 0, 2]);
+    let (first, second) = idat.split_at(4);
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"IDAT", first),
+        (b"IDAT", second),
+        (b"IEND", &[]),
+    ]);
+    let found = report(&file);
+    assert_eq!(found.warnings.len(), 1);
+    assert_eq!(found.warnings[0].chunk_index, 1);
+    assert!(found.warnings[0]
+        .message
+        .starts_with("2 IDAT chunks of 4 bytes"));
+    // A single IDAT is never reported.
+    assert!(report(&common::gray_image(1, &[1, 2], &[])).is_clean());
+}
+
+#[test]
+fn trailing_bytes() {
+    let mut file = common::gray_image(1, &[0], &[]);
+    let end = file.len();
+    file.extend_from_slice(&[0; 5]);
+    let found = report(&file);
+    assert!(found.errors.is_empty());
+    assert_eq!(found.warnings.len(), 1);
+    assert_eq!(found.warnings[0].offset, end);
+    assert_eq!(found.warnings[0].message, "5 bytes after the last chunk");
+}
+
+#[test]
+fn spec_violations_are_errors() {
+    let header = common::ihdr(1, 1, 8, 0);
+    let idat = common::zlib(&[0, 0]);
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"IDAT", &idat),
+        (b"gAMA", &gamma(45455)),
+        (b"IEND", &[]),
+    ]);
+    let found = report(&file);
+    assert_eq!(found.errors.len(), 1);
+    assert_eq!(found.errors[0].chunk_index, 2);
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use png_decoder::chunk::{Chunk, ChunkType};
+use png_decoder::chunk_data::{parse_chunk_data, ChunkData};
+use png_decoder::chunk_data::{DimensionUnit, LastModificationTime, PhysicalPixelDimension};
+use png_decoder::png;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn time(year: u16, month: u8, day: u8, hour: u8) -> LastModificationTime {
+    LastModificationTime {
+        year,
+        month,
+        day,
+        hour,
+        minute: 30,
+        second: 15,
+    }
+}
+
+#[test]
+fn dpi_conversions() {
+    let phys = PhysicalPixelDimension::from_dpi(72.0, 300.0);
+    assert_eq!((phys.x, phys.y), (2835, 11811));
+    let (x_dpi, y_dpi) = phys.dpi().unwrap();
+    assert!((x_dpi - 72.0).abs() < 0.01 && (y_dpi - 300.0).abs() < 0.01);
+    let unknown = PhysicalPixelDimension {
+        x: 1,
+        y: 2,
+        unit: DimensionUnit::Unknown,
+    };
+    assert_eq!(unknown.dpi(), None);
+}
+
+#[test]
+fn latin1_texts() {
+    let text = b"Author\0Jos\xe9";
+    let chunk = Chunk::new(ChunkType::tEXt, text);
+    let (_, data) = parse_chunk_data(&chunk).unwrap();
+    match &data {
+        ChunkData::tEXt(t) => assert_eq!(
+            (t.keyword.as_str(), t.text.as_str()),
+            ("Author", "Jos\u{e9}")
+        ),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(data.to_bytes(), text.to_vec());
+    let mut ztxt = b"Caf\xe9\0\0".to_vec();
+    ztxt.extend(common::zlib(b"na\xefve"));
+    let chunk = Chunk::new(ChunkType::zTXt, &ztxt);
+    match parse_chunk_data(&chunk).unwrap().1 {
+        ChunkData::zTXt(t) => assert_eq!(
+            (t.keyword.as_str(), t.text.as_str()),
+            ("Caf\u{e9}", "na\u{ef}ve")
+        ),
+        other => panic!("{:?}", other),
+    }
+    let file = common::gray_image(1, &[0], &[(b"tEXt", text)]);
+    let (_, metadata) = png::decode(&file).unwrap();
+    assert_eq!(metadata.text("Author"), Some("Jos\u{e9}"));
+}
+
+#[test]
+fn modification_time() {
+    let since_epoch = |t: LastModificationTime| {
+        t.to_system_time()
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+    };
+    assert_eq!(
+        since_epoch(time(2024, 2, 29, 12)),
+        Duration::from_secs(1709209815)
+    );
+    assert!(time(1969, 12, 31, 23).to_system_time().unwrap() < UNIX_EPOCH);
+    assert!(time(2023, 2, 29, 12).to_system_time().is_err());
+    assert!(time(2024, 13, 1, 12).to_system_time().is_err());
+    assert!(time(2024, 4, 31, 12).to_system_time().is_err());
+    assert!(time(2024, 1, 1, 24).to_system_time().is_err());
+}
+
+#[test]
+fn decode_collects_metadata() {
+    let time_data = [0x07, 0xe8, 2, 29, 12, 30, 15];
+    let file = common::gray_image(
+        2,
+        &[1, 2],
+        &[
+            (b"gAMA", &[0, 0, 0xb1, 0x8f]),
+            (b"pHYs", &[0, 0, 0x0b, 0x13, 0, 0, 0x0b, 0x13, 1]),
+            (b"tEXt", b"Title\0Hello"),
+            (b"tIME", &time_data),
+        ],
+    );
+    let (img, metadata) = png::decode(&file).unwrap();
+    assert_eq!(img.data, vec![1, 2]);
+    assert_eq!(metadata.gamma.unwrap().0, 45455);
+    assert_eq!(metadata.text("Title"), Some("Hello"));
+    assert_eq!(metadata.dpi().unwrap().0.round(), 72.0);
+    let seconds = metadata
+        .modification_time()
+        .unwrap()
+        .duration_since(UNIX_EPOCH);
+    assert_eq!(seconds.unwrap().as_secs(), 1709209815);
+    assert_eq!(png::decode_metadata(&file).unwrap().texts.len(), 1);
+}
+
+#[test]
+fn hdr_chunks() {
+    let mdcv = [
+        0x8a, 0x48, 0x39, 0x08, 0x21, 0x34, 0x9b, 0xaa, 0x19, 0x96, 0x08, 0xfc, 0x3d, 0x13, 0x40,
+        0x42, 0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x32,
+    ];
+    let file = |cicp: &[u8]| {
+        common::gray_image(
+            1,
+            &[0],
+            &[
+                (b"cICP", cicp),
+                (b"mDCv", &mdcv),
+                (b"cLLi", &[0, 0x98, 0x96, 0x80, 0, 0x0f, 0x42, 0x40]),
+            ],
+        )
+    };
+    let metadata = png::decode_metadata(&file(&[9, 16, 0, 1])).unwrap();
+    let cicp = metadata.cicp.unwrap();
+    assert!(cicp.is_pq() && !cicp.is_hlg() && cicp.video_full_range);
+    assert!(metadata.is_hdr());
+    let (max, min) = metadata.mastering_display.unwrap().luminance_nits();
+    assert_eq!((max, min), (1000.0, 0.005));
+    let cll = metadata.content_light_level.unwrap().light_levels_nits();
+    assert_eq!(cll, (1000.0, 100.0));
+
+    let hlg = png::decode_metadata(&file(&[9, 18, 0, 1])).unwrap();
+    assert!(hlg.cicp.unwrap().is_hlg() && hlg.is_hdr());
+    let sdr = png::decode_metadata(&file(&[1, 13, 0, 1])).unwrap();
+    assert!(!sdr.is_hdr());
+}
+
+#[test]
+fn hdr_chunks_ordering() {
+    let header = common::ihdr(1, 1, 8, 0);
+    let idat = common::zlib(&[0, 0]);
+    let cicp_after_idat = common::png_file(&[
+        (b"IHDR", &header),
+        (b"IDAT", &idat),
+        (b"cICP", &[9, 16, 0, 1]),
+        (b"IEND", &[]),
+    ]);
+    assert!(png::decode(&cicp_after_idat).is_err());
+}
+
+#[test]
+fn extension_chunks() {
+    let mut pcal = b"temp\0".to_vec();
+    pcal.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 100, 0, 2]);
+    pcal.extend_from_slice(b"K\0-10\x002e1");
+    let file = common::gray_image(
+        1,
+        &[0],
+        &[
+            (b"oFFs", &[0xff, 0xff, 0xff, 0xfe, 0, 0, 0, 1, 0]),
+            (b"pCAL", &pcal),
+            (b"sCAL", b"\x011.5\x002"),
+            (b"sTER", &[1]),
+            (b"gIFg", &[1, 0, 0, 10]),
+        ],
+    );
+    let metadata = png::decode_metadata(&file).unwrap();
+    let offset = metadata.offset.unwrap();
+    assert_eq!((offset.x, offset.y), (-2, 1));
+    let scale = metadata.physical_scale.unwrap();
+    assert_eq!((scale.width, scale.height), (1.5, 2.0));
+    assert!(metadata.stereo_mode.is_some());
+    assert_eq!(metadata.gif_graphic_controls[0].delay_time, 10);
+
+    let calibration = metadata.pixel_calibration.unwrap();
+    assert_eq!(calibration.unit, "K");
+    assert_eq!(calibration.physical_value(0, 8), Ok(-10.0));
+    assert_eq!(calibration.physical_value(255, 8), Ok(10.0));
+    assert_eq!(
+        calibration.physical_values(&[0, 65535], 16),
+        Ok(vec![-10.0, 10.0])
+    );
+    assert!(calibration.physical_value(0, 0).is_err());
+    assert!(calibration.physical_value(0, 64).is_err());
+    assert!(calibration.physical_value(256, 8).is_err());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use png_decoder::png::{self, DataMismatch, DecodeOptions, Png};
+
+// 8-bit gray image of 2x2 pixels with the given IDAT data.
+fn image(idat: &[u8]) -> Vec<u8> {
+    let header = common::ihdr(2, 2, 8, 0);
+    common::png_file(&[(b"IHDR", &header), (b"IDAT", idat), (b"IEND", &[])])
+}
+
+fn mismatch(file: &[u8]) -> DataMismatch {
+    match png::decode(file) {
+        Ok(_) => panic!("the mismatch is not detected"),
+        Err(e) => *e.downcast::<DataMismatch>().expect("not a data mismatch"),
+    }
+}
+
+fn decode_tolerant(file: &[u8]) -> Png {
+    let options = DecodeOptions {
+        tolerate_short_data: true,
+        tolerate_long_data: true,
+        tolerate_trailing_zlib_data: true,
+        ..Default::default()
+    };
+    png::decode_with_options(file, &options).unwrap().0
+}
+
+#[test]
+fn exact_data() {
+    let (img, _) = png::decode(&image(&common::zlib(&[0, 1, 2, 0, 3, 4]))).unwrap();
+    assert_eq!(img.data, vec![1, 2, 3, 4]);
+    assert!(img.data_mismatches.is_empty());
+}
+
+#[test]
+fn too_short() {
+    // A partial last scanline is not enough.
+    let file = image(&common::zlib(&[0, 1, 2, 0, 3]));
+    let expected = DataMismatch::TooShort {
+        expected: 6,
+        actual: 5,
+        zlib_truncated: false,
+    };
+    assert_eq!(mismatch(&file), expected);
+    let img = decode_tolerant(&file);
+    assert_eq!(img.data, vec![1, 2, 0, 0]);
+    assert_eq!(img.data_mismatches, vec![expected]);
+}
+
+#[test]
+fn truncated_zlib_stream() {
+    let zlib = common::zlib(&[0, 1, 2, 0, 3, 4]);
+    let file = image(&zlib[..zlib.len() - 4]);
+    match mismatch(&file) {
+        DataMismatch::TooShort { zlib_truncated, .. } => assert!(zlib_truncated),
+        other => panic!("{:?}", other),
+    }
+    let options = DecodeOptions {
+        tolerate_short_data: true,
+        ..Default::default()
+    };
+    assert!(png::decode_with_options(&file, &options).is_ok());
+}
+
+#[test]
+fn too_long() {
+    let file = image(&common::zlib(&[0, 1, 2, 0, 3, 4, 0, 5, 6]));
+    let expected = DataMismatch::TooLong {
+        expected: 6,
+        actual: 9,
+    };
+    assert_eq!(mismatch(&file), expected);
+    let options = DecodeOptions {
+        tolerate_long_data: true,
+        ..Default::default()
+    };
+    let (img, _) = png::decode_with_options(&file, &options).unwrap();
+    assert_eq!(img.data, vec![1, 2, 3, 4]);
+    assert_eq!(img.data_mismatches, vec![expected]);
+}
+
+#[test]
+fn trailing_zlib_data() {
+    let mut idat = common::zlib(&[0, 1, 2, 0, 3, 4]);
+    idat.extend_from_slice(&[1, 2, 3]);
+    let file = image(&idat);
+    assert_eq!(mismatch(&file), DataMismatch::TrailingZlibData { bytes: 3 });
+    let img = decode_tolerant(&file);
+    assert_eq!(img.data, vec![1, 2, 3, 4]);
+    // Only the tolerated mismatch is an error.
+    let options = DecodeOptions {
+        tolerate_long_data: true,
+        ..Default::default()
+    };
+    assert!(png::decode_with_options(&file, &options).is_err());
+}
+
+#[test]
+fn display() {
+    let short = DataMismatch::TooShort {
+        expected: 6,
+        actual: 5,
+        zlib_truncated: false,
+    };
+    assert!(short.to_string().contains('6'));
+    assert!(short.to_string().contains('5'));
+    let trailing = DataMismatch::TrailingZlibData { bytes: 3 };
+    assert!(trailing.to_string().contains('3'));
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use png_decoder::chunk_data::{parse_valid_ihdr_data, IHDRData};
+use png_decoder::color::ColorType;
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::encoder::{encode, EncodeOptions};
+use png_decoder::filter::{Filter, FilterStrategy};
+use png_decoder::limits::LimitError;
+use png_decoder::optimizer::{decode_pixels, optimize, OptimizeOptions};
+use png_decoder::png;
+use png_decoder::validation::validate_png;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn header(width: u32, height: u32, bit_depth: u8, color_type: u8) -> IHDRData {
+    parse_valid_ihdr_data(&common::ihdr(width, height, bit_depth, color_type)).unwrap()
+}
+
+// Unoptimized encoding: no filter and no compression.
+fn encode_raw(ihdr: &IHDRData, rows: &[u8], ancillary_chunks: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    let options = EncodeOptions {
+        compression_level: 0,
+        filter_strategy: FilterStrategy::Fixed(Filter::None),
+        ancillary_chunks,
+        ..Default::default()
+    };
+    encode(ihdr, rows, &options).unwrap()
+}
+
+// Optimize and check that the output is smaller, valid, and has the same pixels.
+fn check_optimized(input: &[u8]) -> Vec<u8> {
+    let output = optimize(input, &OptimizeOptions::default()).unwrap();
+    assert!(output.len() <= input.len());
+    assert!(validate_png(&output, &ChunkHandlers::new())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        decode_pixels(&output).unwrap().1,
+        decode_pixels(input).unwrap().1
+    );
+    output
+}
+
+fn color_type(file: &[u8]) -> (ColorType, u8) {
+    let (img, _) = png::decode(file).unwrap();
+    let (_, chunks) = png::parse_chunks(file).unwrap();
+    let ihdr = parse_valid_ihdr_data(chunks[0].data).unwrap();
+    (img.color_type, ihdr.bit_depth)
+}
+
+#[test]
+fn reductions() {
+    // Opaque gray pixels stored as RGBA 16 bits with equal bytes.
+    let ihdr = header(16, 16, 16, 6);
+    let rows: Vec<u8> = (0..16 * 16)
+        .flat_map(|i| {
+            let v = (i % 16 * 17) as u8;
+            vec![v, v, v, v, v, v, 255, 255]
+        })
+        .collect();
+    let output = check_optimized(&encode_raw(&ihdr, &rows, Vec::new()));
+    let (color, bit_depth) = color_type(&output);
+    assert_eq!(color, ColorType::Gray);
+    assert!(bit_depth <= 8);
+
+    // Few colors with alpha become a palette with tRNS.
+    let mut rng = StdRng::seed_from_u64(45);
+    let ihdr = header(64, 64, 8, 6);
+    let colors = [[255, 0, 0, 255], [0, 0, 255, 128], [0, 255, 0, 0]];
+    let rows: Vec<u8> = (0..64 * 64)
+        .flat_map(|_| colors[rng.gen_range(0, 3)].to_vec())
+        .collect();
+    let output = check_optimized(&encode_raw(&ihdr, &rows, Vec::new()));
+    assert_eq!(color_type(&output).0, ColorType::PLTE);
+}
+
+#[test]
+fn random_images_keep_their_pixels() {
+    let mut rng = StdRng::seed_from_u64(45);
+    let formats = [
+        (0, 1),
+        (0, 8),
+        (0, 16),
+        (2, 8),
+        (2, 16),
+        (3, 4),
+        (4, 8),
+        (6, 8),
+        (6, 16),
+    ];
+    for &(color_type, bit_depth) in formats.iter() {
+        let ihdr = header(
+            rng.gen_range(1, 20),
+            rng.gen_range(1, 20),
+            bit_depth,
+            color_type,
+        );
+        let row_len = ihdr.scanline_width().unwrap() - 1;
+        // Few distinct bytes so that several reductions apply.
+        let rows: Vec<u8> = (0..row_len * ihdr.height as usize)
+            .map(|_| [0, 17, 255][rng.gen_range(0, 3)])
+            .collect();
+        let options = EncodeOptions {
+            palette: Some((0..16).map(|i| [i * 16, 0, 255 - i * 16]).collect()),
+            compression_level: 0,
+            ..Default::default()
+        };
+        let options = if color_type == 3 {
+            options
+        } else {
+            EncodeOptions::default()
+        };
+        let input = encode(&ihdr, &rows, &options).unwrap();
+        check_optimized(&input);
+    }
+}
+
+#[test]
+fn metadata() {
+    let ihdr = header(32, 32, 8, 2);
+    let rows = vec![100; 32 * 32 * 3];
+    let chunks = vec![
+        (*b"gAMA", vec![0, 0, 177, 143]),
+        (*b"bKGD", vec![0, 1, 0, 2, 0, 3]),
+        (*b"tEXt", b"Title\0gray".to_vec()),
+    ];
+    let input = encode_raw(&ihdr, &rows, chunks);
+    let output = check_optimized(&input);
+    let (_, metadata) = png::decode(&output).unwrap();
+    assert!(metadata.gamma.is_some());
+    assert_eq!(metadata.text("Title"), Some("gray"));
+    // bKGD depends on the color type.
+    assert!(metadata.background.is_none());
+
+    let options = OptimizeOptions {
+        keep_metadata: false,
+        ..Default::default()
+    };
+    let stripped = optimize(&input, &options).unwrap();
+    let (_, metadata) = png::decode(&stripped).unwrap();
+    assert!(metadata.gamma.is_none());
+    assert!(metadata.texts.is_empty());
+}
+
+#[test]
+fn icc_profile_keeps_the_color_family() {
+    let mut iccp = b"Profile\0\0".to_vec();
+    iccp.extend(common::zlib(&[7; 200]));
+    // Gray pixels of an RGB image are not reduced to gray with an RGB profile.
+    let ihdr = header(32, 32, 8, 2);
+    let rows: Vec<u8> = (0..32 * 32 * 3).map(|i| (i / 3 % 200) as u8).collect();
+    let input = encode_raw(&ihdr, &rows, vec![(*b"iCCP", iccp.clone())]);
+    let output = check_optimized(&input);
+    assert_ne!(color_type(&output).0, ColorType::Gray);
+    assert!(png::decode(&output).unwrap().1.icc_profile.is_some());
+    let options = OptimizeOptions {
+        keep_metadata: false,
+        ..Default::default()
+    };
+    let stripped = optimize(&input, &options).unwrap();
+    assert_eq!(color_type(&stripped).0, ColorType::Gray);
+
+    // Gray images with a gray profile do not become palettes.
+    let input = common::gray_image(2, &[0, 255, 255, 0], &[(b"iCCP", &iccp)]);
+    let output = check_optimized(&input);
+    assert_eq!(color_type(&output).0, ColorType::Gray);
+}
+
+#[test]
+fn animations_are_rejected() {
+    let actl = [0, 0, 0, 1, 0, 0, 0, 0];
+    let input = common::gray_image(2, &[0, 255, 255, 0], &[(b"acTL", &actl)]);
+    assert!(decode_pixels(&input).is_ok());
+    assert!(optimize(&input, &OptimizeOptions::default()).is_err());
+    let options = OptimizeOptions {
+        keep_metadata: false,
+        ..Default::default()
+    };
+    assert!(optimize(&input, &options).is_err());
+}
+
+#[test]
+fn input_is_kept_if_smallest() {
+    let input = common::gray_image(1, &[0], &[]);
+    assert_eq!(
+        optimize(&input, &OptimizeOptions::default()).unwrap(),
+        input
+    );
+}
+
+#[test]
+fn huge_header_is_rejected_before_allocating() {
+    let header = common::ihdr(1 << 20, 1 << 20, 16, 6);
+    let idat = common::zlib(&[0; 16]);
+    let file = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat), (b"IEND", &[])]);
+    let error = decode_pixels(&file).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<LimitError>(),
+        Some(LimitError::Pixels { .. })
+    ));
+    assert!(optimize(&file, &OptimizeOptions::default()).is_err());
+}
+
+#[test]
+fn invalid_transparency_is_ignored() {
+    let input = common::gray_image(2, &[10, 20, 30, 40], &[(b"tRNS", &[1])]);
+    let (_, pixels) = decode_pixels(&input).unwrap();
+    let (_, valid) = decode_pixels(&common::gray_image(2, &[10, 20, 30, 40], &[])).unwrap();
+    assert_eq!(pixels, valid);
+    check_optimized(&input);
+}
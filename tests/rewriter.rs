@@ -0,0 +1,193 @@
+mod common;
+
+use png_decoder::chunk::{Chunk, ChunkType};
+use png_decoder::chunk_data::{IccProfile, InternationalText};
+use png_decoder::crc::chunk_crc;
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::png;
+use png_decoder::rewriter::{rewrite, Edits};
+use png_decoder::validation::validate_png;
+
+const PIXELS: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+fn image(ancillary: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    common::gray_image(3, &PIXELS, ancillary)
+}
+
+fn chunk_types(file: &[u8]) -> Vec<String> {
+    let (_, chunks) = png::parse_chunks(file).unwrap();
+    chunks.iter().map(|c| c.chunk_type.to_string()).collect()
+}
+
+fn idats(file: &[u8]) -> Vec<Vec<u8>> {
+    let (_, chunks) = png::parse_chunks(file).unwrap();
+    chunks
+        .iter()
+        .filter(|c: &&Chunk| c.chunk_type == ChunkType::IDAT)
+        .map(|c| c.data.to_vec())
+        .collect()
+}
+
+// Rewrite and check that the output is valid with the same image data.
+fn check_rewrite(input: &[u8], edits: &Edits) -> Vec<u8> {
+    let output = rewrite(input, edits).unwrap();
+    assert!(validate_png(&output, &ChunkHandlers::new())
+        .unwrap()
+        .is_empty());
+    assert_eq!(idats(&output), idats(input));
+    assert_eq!(png::decode(&output).unwrap().0.data, PIXELS.to_vec());
+    output
+}
+
+#[test]
+fn no_edits() {
+    let input = image(&[(b"gAMA", &[0, 0, 177, 143]), (b"tEXt", b"a\0b")]);
+    assert_eq!(check_rewrite(&input, &Edits::default()), input);
+}
+
+#[test]
+fn set_texts() {
+    let mut ztxt = b"Title\0\0".to_vec();
+    ztxt.extend(common::zlib(b"old"));
+    let input = image(&[(b"zTXt", &ztxt), (b"tEXt", b"Author\0me")]);
+    let edits = Edits {
+        set_texts: vec![
+            ("Title".to_string(), "new".to_string()),
+            ("Comment".to_string(), "\u{1F600}".to_string()),
+        ],
+        set_international_texts: vec![InternationalText {
+            keyword: "Description".to_string(),
+            compressed: true,
+            method: 0,
+            language_tag: "fr".to_string(),
+            translated_keyword: "Description".to_string(),
+            text: "Une image".to_string(),
+            zlib_stream: None,
+        }],
+        ..Default::default()
+    };
+    let output = check_rewrite(&input, &edits);
+    assert_eq!(
+        chunk_types(&output),
+        vec!["IHDR", "tEXt", "tEXt", "iTXt", "iTXt", "IDAT", "IEND"]
+    );
+    let (_, metadata) = png::decode(&output).unwrap();
+    assert_eq!(metadata.text("Title"), Some("new"));
+    assert_eq!(metadata.text("Author"), Some("me"));
+    assert_eq!(metadata.text("Comment"), Some("\u{1F600}"));
+    assert_eq!(metadata.text("Description"), Some("Une image"));
+    assert!(metadata.compressed_texts.is_empty());
+
+    // Keywords are Latin-1, but only printable ASCII is accepted.
+    let keywords = ["", "Tab\tkeyword", &"k".repeat(80), "Caf\u{e9}", "\u{263A}"];
+    for keyword in keywords.iter() {
+        let edits = Edits {
+            set_texts: vec![(keyword.to_string(), "text".to_string())],
+            ..Default::default()
+        };
+        assert!(rewrite(&input, &edits).is_err(), "{:?}", keyword);
+    }
+}
+
+#[test]
+fn remove_texts() {
+    let input = image(&[
+        (b"tEXt", b"Caf\xe9\0latin-1"),
+        (b"tEXt", b"Author\0me"),
+        (b"iTXt", b"Author\0\0\0\0\0again"),
+    ]);
+    let edits = Edits {
+        remove_texts: vec![
+            "Caf\u{e9}".to_string(),
+            "Author".to_string(),
+            "Missing".to_string(),
+        ],
+        ..Default::default()
+    };
+    let output = check_rewrite(&input, &edits);
+    assert_eq!(chunk_types(&output), vec!["IHDR", "IDAT", "IEND"]);
+}
+
+#[test]
+fn strip_ancillary() {
+    let input = image(&[
+        (b"gAMA", &[0, 0, 177, 143]),
+        (b"tEXt", b"a\0b"),
+        (b"prVt", b"safe to copy"),
+        (b"prVT", b"unsafe to copy"),
+    ]);
+    let edits = Edits {
+        strip_ancillary: true,
+        ..Default::default()
+    };
+    let output = check_rewrite(&input, &edits);
+    assert_eq!(chunk_types(&output), vec!["IHDR", "IDAT", "IEND"]);
+    // Unknown chunks are kept otherwise, safe to copy or not.
+    let output = check_rewrite(&input, &Edits::default());
+    assert_eq!(chunk_types(&output).len(), 7);
+}
+
+#[test]
+fn dpi() {
+    let input = image(&[(b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0])]);
+    let edits = Edits {
+        dpi: Some((300.0, 72.0)),
+        ..Default::default()
+    };
+    let output = check_rewrite(&input, &edits);
+    assert_eq!(chunk_types(&output), vec!["IHDR", "pHYs", "IDAT", "IEND"]);
+    let (_, metadata) = png::decode(&output).unwrap();
+    let (x, y) = metadata.dpi().unwrap();
+    assert!((x - 300.0).abs() < 0.1 && (y - 72.0).abs() < 0.1);
+}
+
+#[test]
+fn icc_profile() {
+    let header = common::ihdr(1, 1, 8, 3);
+    let idat = common::zlib(&[0, 0]);
+    let input = common::png_file(&[
+        (b"IHDR", &header),
+        (b"sRGB", &[0]),
+        (b"PLTE", &[1, 2, 3]),
+        (b"IDAT", &idat),
+        (b"IEND", &[]),
+    ]);
+    let profile = IccProfile {
+        name: "Display P3".to_string(),
+        method: 0,
+        profile: vec![42; 200],
+        zlib_stream: None,
+    };
+    let edits = Edits {
+        icc_profile: Some(profile),
+        ..Default::default()
+    };
+    let output = rewrite(&input, &edits).unwrap();
+    assert!(validate_png(&output, &ChunkHandlers::new())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        chunk_types(&output),
+        vec!["IHDR", "iCCP", "PLTE", "IDAT", "IEND"]
+    );
+    let (_, metadata) = png::decode(&output).unwrap();
+    assert!(metadata.srgb.is_none());
+    let icc = metadata.icc_profile.unwrap();
+    assert_eq!(icc.name, "Display P3");
+    assert_eq!(icc.profile, vec![42; 200]);
+}
+
+#[test]
+fn crcs_are_recomputed() {
+    let input = image(&[(b"tEXt", b"a\0b")]);
+    let edits = Edits {
+        set_texts: vec![("a".to_string(), "c".to_string())],
+        ..Default::default()
+    };
+    let output = check_rewrite(&input, &edits);
+    let (_, chunks) = png::parse_chunks(&output).unwrap();
+    for c in chunks.iter() {
+        let crc = chunk_crc(c.chunk_type.name(), c.data);
+        assert_eq!(c.crc, crc.to_be_bytes());
+    }
+}
@@ -0,0 +1,302 @@
+mod common;
+
+use std::convert::TryFrom;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use png_decoder::chunk::{Chunk, ChunkType};
+use png_decoder::chunk_data::{
+    parse_chunk_data, CalibrationEquation, ChunkData, PhysicalScale, PixelCalibration, ScaleUnit,
+    Text,
+};
+use png_decoder::encoder::write_chunk;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// Numbers as they may be written in pCAL and sCAL chunks.
+const NUMBERS: [&str; 10] = [
+    "1",
+    "1.0",
+    "2.50",
+    "-0.5",
+    "+3",
+    "007",
+    "1e3",
+    "1E-2",
+    "0.000",
+    "123456.789",
+];
+
+const CHUNK_TYPES: [&[u8; 4]; 27] = [
+    b"IHDR", b"IEND", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"tEXt", b"zTXt", b"iTXt", b"bKGD",
+    b"pHYs", b"sBIT", b"sPLT", b"hIST", b"tIME", b"eXIf", b"cICP", b"mDCv", b"cLLi", b"oFFs",
+    b"pCAL", b"sCAL", b"sTER", b"gIFg", b"gIFx", b"acTL", b"fcTL",
+];
+
+fn random_bytes(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+// Latin-1 keyword or name, null terminated.
+fn keyword(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(1, 80);
+    let mut name: Vec<u8> = (0..len).map(|_| rng.gen_range(1, 256) as u8).collect();
+    name.push(0);
+    name
+}
+
+fn utf8_text(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(0, 50);
+    (0..len)
+        .map(|_| match rng.gen_range(0, 3) {
+            0 => rng.gen_range(b' ', b'~') as char,
+            1 => std::char::from_u32(rng.gen_range(0xa0, 0x800)).unwrap(),
+            _ => '\u{1F600}',
+        })
+        .collect()
+}
+
+// Zlib stream at a random level, including stored blocks.
+fn zlib(rng: &mut StdRng, data: &[u8]) -> Vec<u8> {
+    compress_to_vec_zlib(data, rng.gen_range(0, 11))
+}
+
+fn number(rng: &mut StdRng) -> &'static [u8] {
+    NUMBERS.choose(rng).unwrap().as_bytes()
+}
+
+/// Random valid data of the given chunk type.
+fn random_data(rng: &mut StdRng, chunk_type: &[u8; 4]) -> Vec<u8> {
+    let mut data = Vec::new();
+    match chunk_type {
+        b"IHDR" => data = common::ihdr(rng.gen_range(1, 1000), rng.gen_range(1, 1000), 8, 6),
+        b"IEND" => {}
+        b"gAMA" => data = random_bytes(rng, 4),
+        b"cLLi" => data = random_bytes(rng, 8),
+        b"acTL" => {
+            data.extend_from_slice(&rng.gen_range(1_u32, u32::MAX).to_be_bytes());
+            data.extend(random_bytes(rng, 4));
+        }
+        b"cHRM" => data = random_bytes(rng, 32),
+        b"mDCv" => data = random_bytes(rng, 24),
+        b"sRGB" => data.push(rng.gen_range(0, 4)),
+        b"sTER" => data.push(rng.gen_range(0, 2)),
+        b"iCCP" => {
+            data = keyword(rng);
+            data.push(0);
+            let len = rng.gen_range(0, 2000);
+            let profile = random_bytes(rng, len);
+            data.extend(zlib(rng, &profile));
+        }
+        b"tEXt" => {
+            data = keyword(rng);
+            let len = rng.gen_range(0, 100);
+            data.extend((0..len).map(|_| rng.gen_range(1, 256) as u8));
+        }
+        b"zTXt" => {
+            data = keyword(rng);
+            data.push(0);
+            let len = rng.gen_range(0, 500);
+            let text = random_bytes(rng, len);
+            data.extend(zlib(rng, &text));
+        }
+        b"iTXt" => {
+            data = keyword(rng);
+            let compressed = rng.gen();
+            data.extend_from_slice(&[compressed as u8, 0]);
+            data.extend_from_slice(b"en-us\0");
+            data.extend(utf8_text(rng).bytes());
+            data.push(0);
+            let text = utf8_text(rng);
+            if compressed {
+                data.extend(zlib(rng, text.as_bytes()));
+            } else {
+                data.extend(text.bytes());
+            }
+        }
+        b"bKGD" => {
+            let len = *[1, 2, 6].choose(rng).unwrap();
+            data = random_bytes(rng, len);
+        }
+        b"sBIT" => {
+            let len = rng.gen_range(1, 5);
+            data = random_bytes(rng, len);
+        }
+        b"pHYs" | b"oFFs" => {
+            data = random_bytes(rng, 8);
+            data.push(rng.gen_range(0, 2));
+        }
+        b"sPLT" => {
+            data = keyword(rng);
+            let depth = *[8, 16].choose(rng).unwrap();
+            data.push(depth);
+            let entry_size = if depth == 8 { 6 } else { 10 };
+            let nb_entries = rng.gen_range(0, 20);
+            data.extend(random_bytes(rng, entry_size * nb_entries));
+        }
+        b"hIST" => {
+            let nb_entries = rng.gen_range(1, 256);
+            data = random_bytes(rng, 2 * nb_entries);
+        }
+        b"tIME" => data = random_bytes(rng, 7),
+        b"eXIf" => {
+            data.extend_from_slice(b"MM\0*");
+            let len = rng.gen_range(4, 100);
+            data.extend(random_bytes(rng, len));
+        }
+        b"cICP" => data = vec![rng.gen(), rng.gen(), 0, rng.gen_range(0, 2)],
+        b"pCAL" => {
+            data = keyword(rng);
+            data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            let equation = rng.gen_range(0, 4);
+            let nb_parameters = [2, 3, 3, 4][equation as usize];
+            data.extend_from_slice(&[equation, nb_parameters]);
+            data.extend_from_slice(b"unit");
+            for _ in 0..nb_parameters {
+                data.push(0);
+                data.extend_from_slice(number(rng));
+            }
+        }
+        b"sCAL" => {
+            data.push(rng.gen_range(1, 3));
+            data.extend_from_slice(number(rng));
+            data.push(0);
+            data.extend_from_slice(number(rng));
+        }
+        b"gIFg" => {
+            data = random_bytes(rng, 4);
+            data[1] = rng.gen_range(0, 2);
+        }
+        b"gIFx" => {
+            let len = rng.gen_range(11, 40);
+            data = random_bytes(rng, len);
+        }
+        b"fcTL" => {
+            data = random_bytes(rng, 24);
+            data.extend_from_slice(&[rng.gen_range(0, 3), rng.gen_range(0, 2)]);
+        }
+        _ => unreachable!(),
+    }
+    data
+}
+
+fn reparse(chunk_type: ChunkType, data: &[u8]) -> Vec<u8> {
+    let chunk = Chunk::new(chunk_type, data);
+    let (_, parsed) =
+        parse_chunk_data(&chunk).unwrap_or_else(|e| panic!("{} {:?}: {:?}", chunk_type, data, e));
+    parsed.to_bytes()
+}
+
+#[test]
+fn every_chunk_type_is_byte_identical() {
+    let mut rng = StdRng::seed_from_u64(47);
+    for _ in 0..50 {
+        for name in CHUNK_TYPES.iter() {
+            let chunk_type = ChunkType::try_from(**name).unwrap();
+            let data = random_data(&mut rng, name);
+            assert_eq!(reparse(chunk_type, &data), data, "{}", chunk_type);
+        }
+    }
+}
+
+#[test]
+fn whole_files_are_byte_identical() {
+    let mut rng = StdRng::seed_from_u64(470);
+    for _ in 0..20 {
+        let mut ancillary = Vec::new();
+        for name in CHUNK_TYPES[2..].iter() {
+            if rng.gen() {
+                ancillary.push((*name, random_data(&mut rng, name)));
+            }
+        }
+        ancillary.shuffle(&mut rng);
+        let chunks: Vec<(&[u8; 4], &[u8])> = ancillary
+            .iter()
+            .map(|(name, data)| (*name, data.as_slice()))
+            .collect();
+        let file = common::gray_image(4, &random_bytes(&mut rng, 16), &chunks);
+        let mut rewritten = common::SIGNATURE.to_vec();
+        let mut input = &file[8..];
+        while !input.is_empty() {
+            let (rest, chunk) = Chunk::parse(input).unwrap();
+            let (_, parsed) = parse_chunk_data(&chunk).unwrap();
+            write_chunk(&mut rewritten, chunk.chunk_type.name(), &parsed.to_bytes());
+            input = rest;
+        }
+        assert_eq!(rewritten, file);
+    }
+}
+
+#[test]
+fn edited_values_are_rewritten() {
+    let mut ztxt = b"Comment\0\0".to_vec();
+    ztxt.extend(compress_to_vec_zlib(b"old text", 0));
+    let chunk = Chunk::new(ChunkType::zTXt, &ztxt);
+    let mut parsed = parse_chunk_data(&chunk).unwrap().1;
+    if let ChunkData::zTXt(t) = &mut parsed {
+        t.text = "new text".to_string();
+    }
+    let data = parsed.to_bytes();
+    assert_ne!(data, ztxt);
+    let chunk = Chunk::new(ChunkType::zTXt, &data);
+    match parse_chunk_data(&chunk).unwrap().1 {
+        ChunkData::zTXt(t) => assert_eq!(t.text, "new text"),
+        other => panic!("{:?}", other),
+    }
+
+    let chunk = Chunk::new(ChunkType::sCAL, b"\x012.50\x001e3");
+    let mut parsed = parse_chunk_data(&chunk).unwrap().1;
+    if let ChunkData::sCAL(scal) = &mut parsed {
+        scal.width = 3.0;
+    }
+    assert_eq!(parsed.to_bytes(), b"\x013\x001e3");
+
+    let chunk = Chunk::new(
+        ChunkType::pCAL,
+        b"p\0\0\0\0\0\0\0\0\x01\x00\x02u\x001.0\x002.50",
+    );
+    let mut parsed = parse_chunk_data(&chunk).unwrap().1;
+    if let ChunkData::pCAL(pcal) = &mut parsed {
+        pcal.parameters[1] = 0.25;
+    }
+    assert_eq!(
+        parsed.to_bytes(),
+        b"p\0\0\0\0\0\0\0\0\x01\x00\x02u\x001.0\x000.25"
+    );
+}
+
+#[test]
+fn constructed_values() {
+    let scal = PhysicalScale::new(ScaleUnit::Meter, 2.5, 1000.0);
+    let data = ChunkData::sCAL(scal.clone()).to_bytes();
+    assert_eq!(data, b"\x012.5\x001000");
+    // Numbers as written in the chunk are not compared.
+    let chunk = Chunk::new(ChunkType::sCAL, b"\x012.50\x001e3");
+    match parse_chunk_data(&chunk).unwrap().1 {
+        ChunkData::sCAL(parsed) => assert_eq!(parsed, scal),
+        other => panic!("{:?}", other),
+    }
+
+    let pcal = PixelCalibration::new(
+        "p".to_string(),
+        0,
+        1,
+        CalibrationEquation::Linear,
+        "u".to_string(),
+        vec![1.0, 0.25],
+    );
+    let data = ChunkData::pCAL(pcal.clone()).to_bytes();
+    assert_eq!(data, b"p\0\0\0\0\0\0\0\0\x01\x00\x02u\x001\x000.25");
+    let chunk = Chunk::new(ChunkType::pCAL, &data);
+    match parse_chunk_data(&chunk).unwrap().1 {
+        ChunkData::pCAL(parsed) => assert_eq!(parsed, pcal),
+        other => panic!("{:?}", other),
+    }
+
+    // Characters outside of Latin-1 are replaced.
+    let text = ChunkData::tEXt(Text {
+        keyword: "Comment".to_string(),
+        text: "caf\u{e9} \u{263A}".to_string(),
+    });
+    assert_eq!(text.to_bytes(), b"Comment\0caf\xe9 ?");
+}
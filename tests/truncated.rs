@@ -0,0 +1,130 @@
+mod common;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use png_decoder::png::{self, DecodeOptions};
+
+const WIDTH: usize = 4;
+const HEIGHT: usize = 8;
+
+fn recover() -> DecodeOptions {
+    DecodeOptions {
+        recover_truncated: true,
+        ..Default::default()
+    }
+}
+
+// Gray image whose rows are stored uncompressed, so that a cut file
+// still holds the first complete rows. Returns the file and the offset
+// of the first row in it.
+fn stored_image(ancillary: &[(&[u8; 4], &[u8])]) -> (Vec<u8>, usize) {
+    let scanlines: Vec<u8> = (0..HEIGHT)
+        .flat_map(|y| std::iter::once(0).chain((0..WIDTH).map(move |x| (10 * y + x + 1) as u8)))
+        .collect();
+    let idat = compress_to_vec_zlib(&scanlines, 0);
+    let header = common::ihdr(WIDTH as u32, HEIGHT as u32, 8, 0);
+    let mut chunks = vec![(b"IHDR", header.as_slice())];
+    chunks.extend_from_slice(ancillary);
+    chunks.push((b"IDAT", &idat));
+    chunks.push((b"IEND", &[]));
+    let file = common::png_file(&chunks);
+    // IEND and IDAT CRC, then the stored block header and the zlib header.
+    let first_row = file.len() - 12 - 4 - (idat.len() - 2 - 5);
+    (file, first_row)
+}
+
+fn pixels(rows: usize, background: u8) -> Vec<u8> {
+    (0..HEIGHT)
+        .flat_map(|y| {
+            (0..WIDTH).map(move |x| {
+                if y < rows {
+                    (10 * y + x + 1) as u8
+                } else {
+                    background
+                }
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn complete_file() {
+    let (file, _) = stored_image(&[]);
+    let (img, _) = png::decode_with_options(&file, &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(HEIGHT));
+    assert_eq!(img.data, pixels(HEIGHT, 0));
+    let (img, _) = png::decode(&file).unwrap();
+    assert_eq!(img.recovered_rows, None);
+}
+
+#[test]
+fn cut_in_idat() {
+    let (file, first_row) = stored_image(&[]);
+    // Three rows and a half.
+    let cut = &file[..first_row + 3 * (WIDTH + 1) + 2];
+    assert!(png::decode(cut).is_err());
+    let (img, _) = png::decode_with_options(cut, &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(3));
+    assert_eq!(img.data, pixels(3, 0));
+    // Before the first row.
+    let (img, _) = png::decode_with_options(&file[..first_row], &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(0));
+    assert_eq!(img.data, pixels(0, 0));
+}
+
+#[test]
+fn cut_in_idat_crc() {
+    let (file, _) = stored_image(&[]);
+    // IEND and two bytes of the IDAT CRC are missing.
+    let cut = &file[..file.len() - 12 - 2];
+    let (img, _) = png::decode_with_options(cut, &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(HEIGHT));
+    assert_eq!(img.data, pixels(HEIGHT, 0));
+    assert!(img.data_mismatches.is_empty());
+    assert!(img.adler32_verified);
+}
+
+#[test]
+fn cut_before_idat() {
+    let (file, first_row) = stored_image(&[]);
+    // Inside the length and type of the IDAT chunk, there is no image data.
+    let cut = &file[..first_row - 2 - 6];
+    assert!(png::decode_with_options(cut, &recover()).is_err());
+}
+
+#[test]
+fn background() {
+    let (file, first_row) = stored_image(&[(b"bKGD", &[0, 200])]);
+    let cut = &file[..first_row + 2 * (WIDTH + 1)];
+    let (img, _) = png::decode_with_options(cut, &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(2));
+    assert_eq!(img.data, pixels(2, 200));
+    let options = DecodeOptions {
+        recovery_background: Some(vec![77]),
+        ..recover()
+    };
+    let (img, _) = png::decode_with_options(cut, &options).unwrap();
+    assert_eq!(img.data, pixels(2, 77));
+    let options = DecodeOptions {
+        recovery_background: Some(vec![1, 2]),
+        ..recover()
+    };
+    assert!(png::decode_with_options(cut, &options).is_err());
+}
+
+#[test]
+fn background_with_alpha() {
+    let header = common::ihdr(1, 2, 8, 6);
+    let bkgd: &[u8] = &[0, 10, 0, 20, 0, 30];
+    let idat = compress_to_vec_zlib(&[0, 1, 2, 3, 4, 0, 5, 6, 7, 8], 0);
+    let file = common::png_file(&[
+        (b"IHDR", &header),
+        (b"bKGD", bkgd),
+        (b"IDAT", &idat),
+        (b"IEND", &[]),
+    ]);
+    // Cut after the first row.
+    let cut = &file[..file.len() - 12 - 4 - 5];
+    let (img, _) = png::decode_with_options(cut, &recover()).unwrap();
+    assert_eq!(img.recovered_rows, Some(1));
+    assert_eq!(img.data, vec![1, 2, 3, 4, 10, 20, 30, 255]);
+}
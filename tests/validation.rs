@@ -0,0 +1,150 @@
+mod common;
+
+use png_decoder::chunk::ChunkType;
+use png_decoder::custom_chunk::ChunkHandlers;
+use png_decoder::lint::lint;
+use png_decoder::png;
+use png_decoder::validation::{validate_png, Violation};
+use std::convert::TryFrom;
+
+fn violations(file: &[u8]) -> Vec<Violation> {
+    validate_png(file, &ChunkHandlers::new()).unwrap()
+}
+
+// Messages of the violations, with the type of the chunk they refer to.
+fn messages(file: &[u8]) -> Vec<(Option<ChunkType>, String)> {
+    violations(file)
+        .into_iter()
+        .map(|v| (v.chunk_type, v.message))
+        .collect()
+}
+
+fn palette_image(color_type: u8, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let header = common::ihdr(1, 1, 8, color_type);
+    let idat = common::zlib(&[0, 0, 0, 0, 0]);
+    let mut all = vec![(b"IHDR", header.as_slice())];
+    all.extend_from_slice(chunks);
+    all.push((b"IDAT", &idat));
+    all.push((b"IEND", &[]));
+    common::png_file(&all)
+}
+
+#[test]
+fn valid_file() {
+    let file = common::gray_image(2, &[0, 255], &[(b"gAMA", &[0, 0, 177, 143])]);
+    assert!(violations(&file).is_empty());
+    assert!(lint(&file, &ChunkHandlers::new())
+        .unwrap()
+        .errors
+        .is_empty());
+}
+
+#[test]
+fn palette_rules() {
+    let plte: &[u8] = &[0, 0, 0, 255, 255, 255];
+    assert!(violations(&palette_image(3, &[(b"PLTE", plte)])).is_empty());
+    let missing = messages(&palette_image(3, &[]));
+    assert_eq!(missing.len(), 1);
+    assert!(missing[0].1.contains("PLTE is required"));
+    let gray = messages(&common::gray_image(1, &[0], &[(b"PLTE", plte)]));
+    assert!(gray
+        .iter()
+        .any(|(_, m)| m.contains("forbidden for grayscale")));
+    let partial = messages(&palette_image(3, &[(b"PLTE", &[0, 0, 0, 255])]));
+    assert_eq!(partial[0].0, Some(ChunkType::PLTE));
+    let hist = messages(&palette_image(2, &[(b"hIST", &[0, 1])]));
+    assert_eq!(
+        hist,
+        vec![(
+            Some(ChunkType::hIST),
+            "hIST requires a PLTE chunk".to_string()
+        )]
+    );
+    let too_many_alphas = [(b"PLTE", plte), (b"tRNS", &[0, 0, 0][..])];
+    assert!(!violations(&palette_image(3, &too_many_alphas)).is_empty());
+}
+
+#[test]
+fn transparency_rules() {
+    let rgba = palette_image(6, &[(b"tRNS", &[0, 0, 0, 0, 0, 0])]);
+    assert!(messages(&rgba)[0].1.contains("alpha channel"));
+    let rgb = palette_image(2, &[(b"tRNS", &[0, 0])]);
+    assert!(messages(&rgb)[0].1.contains("6 bytes"));
+    assert!(violations(&palette_image(2, &[(b"tRNS", &[0; 6])])).is_empty());
+}
+
+#[test]
+fn global_rules() {
+    let header = common::ihdr(1, 1, 8, 0);
+    let idat = common::zlib(&[0, 0]);
+    let no_idat = common::png_file(&[(b"IHDR", &header), (b"IEND", &[])]);
+    let missing = violations(&no_idat)
+        .into_iter()
+        .find(|v| v.chunk_type.is_none());
+    let missing = missing.unwrap();
+    assert_eq!(missing.chunk_index, 2);
+    assert!(missing.message.contains("IDAT"));
+    let no_iend = common::png_file(&[(b"IHDR", &header), (b"IDAT", &idat)]);
+    assert!(messages(&no_iend)[0].1.contains("IEND"));
+    let mut trailing = common::gray_image(1, &[0], &[]);
+    let end = trailing.len();
+    trailing.extend_from_slice(b"junk");
+    let found = violations(&trailing);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].offset, end);
+}
+
+#[test]
+fn violation_offsets() {
+    let gama: &[u8] = &[0, 0, 177];
+    let file = common::gray_image(1, &[0], &[(b"sRGB", &[0]), (b"gAMA", gama)]);
+    let found = violations(&file);
+    assert_eq!(found.len(), 1);
+    // Signature, IHDR and sRGB chunks come first.
+    assert_eq!(found[0].chunk_index, 2);
+    assert_eq!(found[0].offset, 8 + (12 + 13) + (12 + 1));
+    assert_eq!(
+        found[0].to_string(),
+        "chunk 2 (gAMA) at byte 46: gAMA data must be 4 bytes long, not 3"
+    );
+}
+
+#[test]
+fn color_space_exclusivity() {
+    let mut iccp = b"icc\0\0".to_vec();
+    iccp.extend(common::zlib(b"profile"));
+    let file = common::gray_image(1, &[0], &[(b"iCCP", &iccp), (b"sRGB", &[0])]);
+    let found = messages(&file);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, Some(ChunkType::sRGB));
+}
+
+#[test]
+fn invalid_ancillary_chunks_are_not_fatal() {
+    let invalid: [(&[u8; 4], &[u8]); 4] = [
+        (b"gAMA", &[0, 0, 177]),
+        (b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 7]),
+        (b"tIME", &[7, 228, 12, 1, 0, 0]),
+        (b"zTXt", b"key\0\0not zlib"),
+    ];
+    for chunk in invalid.iter() {
+        let file = common::gray_image(2, &[10, 20], &[*chunk]);
+        let (img, metadata) = png::decode(&file).unwrap();
+        assert_eq!(img.data, vec![10, 20]);
+        assert_eq!(metadata.gamma, None);
+        assert_eq!(metadata.physical_dimension, None);
+        assert_eq!(metadata.last_modification_time, None);
+        assert!(metadata.compressed_texts.is_empty());
+        assert_eq!(violations(&file).len(), 1);
+        let report = lint(&file, &ChunkHandlers::new()).unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(
+            report.warnings[0].chunk_type,
+            ChunkType::try_from(*chunk.0).ok()
+        );
+    }
+    // Critical chunks are still checked.
+    let file = palette_image(3, &[(b"PLTE", &[0, 0])]);
+    assert!(png::decode(&file).is_err());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use png_decoder::png::{self, DecodeOptions};
+use png_decoder::zlib::{CompressionLevel, ZlibHeader};
+
+fn image_with_idat(idat: &[u8]) -> Vec<u8> {
+    let header = common::ihdr(2, 1, 8, 0);
+    common::png_file(&[(b"IHDR", &header), (b"IDAT", idat), (b"IEND", &[])])
+}
+
+fn skip_adler32() -> DecodeOptions {
+    DecodeOptions {
+        skip_adler32: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn header_fields() {
+    let header = ZlibHeader::parse(0x78, 0x9c).unwrap();
+    assert_eq!(header.window_size, 32768);
+    assert_eq!(header.level, CompressionLevel::Default);
+    assert!(!header.preset_dictionary);
+    assert_eq!(
+        ZlibHeader::parse(0x78, 0x01).unwrap().level,
+        CompressionLevel::Fastest
+    );
+    assert_eq!(
+        ZlibHeader::parse(0x78, 0xda).unwrap().level,
+        CompressionLevel::Maximum
+    );
+    assert_eq!(ZlibHeader::parse(0x08, 0x1d).unwrap().window_size, 256);
+    assert!(ZlibHeader::parse(0x78, 0xbb).unwrap().preset_dictionary);
+    assert_eq!(
+        header.to_string(),
+        "zlib window size: 32768 bytes, compression level: Default"
+    );
+}
+
+#[test]
+fn invalid_headers() {
+    // Check bits
+    assert!(ZlibHeader::parse(0x78, 0x9d).is_err());
+    // Compression method 7
+    assert!(ZlibHeader::parse(0x77, 0x85).is_err());
+    // Window of 2^16 bytes
+    assert!(ZlibHeader::parse(0x88, 0x98).is_err());
+    // Preset dictionaries are parsed but not allowed in PNG.
+    let mut idat = vec![0x78, 0xbb, 0, 0, 0, 0];
+    idat.extend_from_slice(&common::zlib(&[0, 1, 2])[2..]);
+    assert!(png::decode(&image_with_idat(&idat)).is_err());
+}
+
+#[test]
+fn decode_reports_zlib_stream() {
+    let file = image_with_idat(&common::zlib(&[0, 1, 2]));
+    let expected = ZlibHeader::parse(0x78, 0x9c).ok();
+    let (img, _) = png::decode(&file).unwrap();
+    assert_eq!(img.zlib_header, expected);
+    assert!(img.adler32_verified);
+    let (img, _) = png::decode_with_options(&file, &skip_adler32()).unwrap();
+    assert_eq!(img.zlib_header, expected);
+    assert!(!img.adler32_verified);
+    for decode in [png::decode_no_check, png::decode_no_check_bis].iter() {
+        let img = decode(&file).unwrap();
+        assert_eq!(img.zlib_header, expected);
+        assert!(img.adler32_verified);
+    }
+}
+
+#[test]
+fn adler32_mismatch() {
+    let mut idat = common::zlib(&[0, 1, 2]);
+    let last = idat.len() - 1;
+    idat[last] ^= 0xff;
+    let file = image_with_idat(&idat);
+    assert!(png::decode(&file).is_err());
+    assert!(png::decode_no_check(&file).is_err());
+    let (img, _) = png::decode_with_options(&file, &skip_adler32()).unwrap();
+    assert_eq!(img.data, vec![1, 2]);
+    assert!(!img.adler32_verified);
+}
+
+#[test]
+fn truncated_stream_is_not_verified() {
+    let idat = common::zlib(&[0, 1, 2]);
+    let file = image_with_idat(&idat[..idat.len() - 4]);
+    let options = DecodeOptions {
+        tolerate_short_data: true,
+        ..Default::default()
+    };
+    let (img, _) = png::decode_with_options(&file, &options).unwrap();
+    assert!(img.zlib_header.is_some());
+    assert!(!img.adler32_verified);
+}